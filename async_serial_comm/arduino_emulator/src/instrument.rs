@@ -0,0 +1,159 @@
+//! Command handling of the emulated instrument.
+//!
+//! Mimics `arduino_answer_delay.ino`: `DELAY <n>` is answered with
+//! `ACK-SECOND` after `n` seconds, unknown commands are silently ignored
+//! (as the Vrekrer SCPI parser does).
+
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Reply sent by the Arduino after the delay has passed.
+pub const ACK: &str = "ACK-SECOND";
+
+/// Behaviour of the emulated instrument.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// Additional commands and their fixed replies.
+    pub replies: Vec<(String, String)>,
+    /// Maximum random delay added to every reply.
+    pub jitter: Duration,
+    /// Probability that a reply is not sent at all.
+    pub drop_rate: f64,
+}
+
+/// A reply, to be sent after a given time.
+#[derive(Debug, PartialEq)]
+pub struct Reply {
+    pub after: Duration,
+    pub text: String,
+}
+
+/// State of one emulated instrument.
+pub struct Instrument {
+    config: Config,
+    rng: StdRng,
+}
+
+/// Parse like Arduino's `String::toInt()`, i.e., `atol`: leading whitespace
+/// and a sign, followed by as many digits as there are. `"2abc"` is 2, garbage
+/// is 0.
+fn to_int(s: &str) -> i64 {
+    let s = s.trim_start();
+    let (sign, digits) = match s.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let value = digits
+        .bytes()
+        .take_while(u8::is_ascii_digit)
+        .fold(0i64, |acc, d| {
+            acc.saturating_mul(10).saturating_add(i64::from(d - b'0'))
+        });
+    sign * value
+}
+
+impl Instrument {
+    pub fn new(config: Config, seed: u64) -> Self {
+        Self {
+            config,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Handle one received line and return the reply, if any.
+    pub fn handle(&mut self, line: &str) -> Option<Reply> {
+        let line = line.trim();
+        let (header, params) = match line.split_once(char::is_whitespace) {
+            Some((header, params)) => (header, params.trim()),
+            None => (line, ""),
+        };
+
+        let (after, text) = if header.eq_ignore_ascii_case("DELAY") {
+            if params.is_empty() {
+                return None;
+            }
+            let secs = to_int(params.split(',').next().unwrap_or(""));
+            // The Arduino passes negative delays to `delay()` as a huge unsigned
+            // number, we answer them right away instead.
+            (Duration::from_secs(secs.max(0) as u64), ACK.to_string())
+        } else {
+            let (_, reply) = self
+                .config
+                .replies
+                .iter()
+                .find(|(cmd, _)| cmd.eq_ignore_ascii_case(header))?;
+            (Duration::ZERO, reply.clone())
+        };
+
+        if self.config.drop_rate > 0.0 && self.rng.gen_bool(self.config.drop_rate.min(1.0)) {
+            return None;
+        }
+        let jitter = match self.config.jitter.is_zero() {
+            true => Duration::ZERO,
+            false => self.rng.gen_range(Duration::ZERO..=self.config.jitter),
+        };
+
+        Some(Reply {
+            after: after + jitter,
+            text,
+        })
+    }
+
+    /// Scramble a reply as if it was received with the wrong baud rate.
+    ///
+    /// No line terminators are generated, such that clients still read exactly
+    /// one (garbage) line instead of hanging until their timeout.
+    pub fn garble(&mut self, text: &str) -> Vec<u8> {
+        (0..text.len())
+            .map(|_| loop {
+                let byte: u8 = self.rng.gen();
+                if byte != b'\n' && byte != b'\r' {
+                    break byte;
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_int_parses_leading_digits() {
+        assert_eq!(to_int("2"), 2);
+        assert_eq!(to_int(" 2abc"), 2);
+        assert_eq!(to_int("+3"), 3);
+        assert_eq!(to_int("-4s"), -4);
+        assert_eq!(to_int("abc"), 0);
+        assert_eq!(to_int(""), 0);
+    }
+
+    #[test]
+    fn delay_parameter() {
+        let mut instrument = Instrument::new(Config::default(), 0);
+        let after = |instrument: &mut Instrument, cmd| instrument.handle(cmd).unwrap().after;
+        assert_eq!(after(&mut instrument, "DELAY 2abc"), Duration::from_secs(2));
+        assert_eq!(after(&mut instrument, "delay 1,5"), Duration::from_secs(1));
+        assert_eq!(after(&mut instrument, "DELAY -1"), Duration::ZERO);
+        assert_eq!(instrument.handle("DELAY"), None);
+    }
+
+    #[test]
+    fn jitter_is_reproducible() {
+        let config = Config {
+            jitter: Duration::from_millis(200),
+            ..Config::default()
+        };
+        let delays = |seed| {
+            let mut instrument = Instrument::new(config.clone(), seed);
+            (0..10)
+                .map(|_| instrument.handle("DELAY 0").unwrap().after)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(delays(42), delays(42));
+        assert_ne!(delays(42), delays(43));
+    }
+}
@@ -0,0 +1,203 @@
+//! Emulator for the `arduino_answer_delay` sketch.
+//!
+//! Creates a pseudo-terminal (or listens on a TCP socket) that answers
+//! `DELAY <n>` with `ACK-SECOND` after `n` seconds, such that the async serial
+//! experiments can be run without the Arduinos attached.
+
+use std::net::SocketAddr;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Parser;
+use nix::pty::openpty;
+use nix::sys::termios::{self, SetArg};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::sleep;
+
+mod instrument;
+
+use crate::instrument::{Config, Instrument};
+
+#[derive(Parser)]
+#[command(about = "Emulate the arduino_answer_delay sketch on a pseudo-terminal or TCP socket")]
+struct Cli {
+    /// Listen on this TCP address instead of creating a pseudo-terminal.
+    #[arg(long)]
+    tcp: Option<SocketAddr>,
+    /// Create a symlink to the pseudo-terminal at this path, e.g., /tmp/ttyACM0.
+    #[arg(long)]
+    link: Option<PathBuf>,
+    /// Baud rate of the instrument. On a pseudo-terminal, replies are garbled
+    /// if the client opens the port with a different baud rate.
+    #[arg(long, default_value_t = 9600)]
+    baud: u32,
+    /// Always garble replies, as if client and instrument used different baud rates.
+    #[arg(long)]
+    baud_mismatch: bool,
+    /// Additional command with a fixed reply, given as `CMD=REPLY`. Can be repeated.
+    #[arg(long = "reply", value_parser = parse_reply)]
+    replies: Vec<(String, String)>,
+    /// Maximum random delay added to every reply, in milliseconds.
+    #[arg(long, default_value_t = 0)]
+    jitter_ms: u64,
+    /// Probability between 0 and 1 that a reply is dropped.
+    #[arg(long, default_value_t = 0.0, value_parser = parse_probability)]
+    drop_rate: f64,
+    /// Seed for jitter and dropped replies, random if not given.
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+fn parse_reply(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(cmd, reply)| (cmd.trim().to_string(), reply.to_string()))
+        .filter(|(cmd, _)| !cmd.is_empty())
+        .ok_or_else(|| format!("expected CMD=REPLY, got `{}`", s))
+}
+
+fn parse_probability(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+        _ => Err(format!(
+            "expected a probability between 0 and 1, got `{}`",
+            s
+        )),
+    }
+}
+
+/// Baud rate of a terminal, also for non-standard rates (Linux only).
+fn get_baud(fd: BorrowedFd) -> std::io::Result<u32> {
+    let mut tio: libc::termios2 = unsafe { std::mem::zeroed() };
+    // SAFETY: `tio` is a valid termios2 struct for the kernel to fill in.
+    if unsafe { libc::ioctl(fd.as_raw_fd(), libc::TCGETS2, &mut tio) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(tio.c_ospeed)
+}
+
+/// Set the baud rate of a terminal, also for non-standard rates (Linux only).
+fn set_baud(fd: BorrowedFd, baud: u32) -> std::io::Result<()> {
+    let mut tio: libc::termios2 = unsafe { std::mem::zeroed() };
+    // SAFETY: `tio` is a valid termios2 struct, read and written by the kernel.
+    unsafe {
+        if libc::ioctl(fd.as_raw_fd(), libc::TCGETS2, &mut tio) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        tio.c_cflag = (tio.c_cflag & !libc::CBAUD) | libc::BOTHER;
+        tio.c_ispeed = baud;
+        tio.c_ospeed = baud;
+        if libc::ioctl(fd.as_raw_fd(), libc::TCSETS2, &tio) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Serve one connection, line by line, like the Arduino does.
+///
+/// `mismatch` is checked before every reply and garbles it if it returns true.
+async fn serve<T>(
+    stream: T,
+    mut instrument: Instrument,
+    mismatch: impl Fn() -> bool,
+) -> std::io::Result<()>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = BufReader::new(stream);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if stream.read_until(b'\n', &mut buf).await? == 0 {
+            return Ok(());
+        }
+        let Some(reply) = instrument.handle(&String::from_utf8_lossy(&buf)) else {
+            continue;
+        };
+        sleep(reply.after).await;
+
+        let mut bytes = match mismatch() {
+            true => instrument.garble(&reply.text),
+            false => reply.text.into_bytes(),
+        };
+        // `Serial.println` terminates with CR LF.
+        bytes.extend_from_slice(b"\r\n");
+        let out = stream.get_mut();
+        out.write_all(&bytes).await?;
+        out.flush().await?;
+    }
+}
+
+async fn run_tcp(addr: SocketAddr, cli: Cli, config: Config, seed: u64) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    println!("Listening on {}", listener.local_addr()?);
+
+    // Every connection is an instrument of its own.
+    let mismatch = cli.baud_mismatch;
+    let mut conn = 0;
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let instrument = Instrument::new(config.clone(), seed.wrapping_add(conn));
+        conn += 1;
+        tokio::spawn(async move {
+            if let Err(e) = serve(stream, instrument, || mismatch).await {
+                eprintln!("Connection to {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn run_pty(cli: Cli, config: Config, seed: u64) -> std::io::Result<()> {
+    let pty = openpty(None, None)?;
+    // Without raw mode, the pty echoes commands back to the client.
+    let mut attrs = termios::tcgetattr(pty.slave.as_fd())?;
+    termios::cfmakeraw(&mut attrs);
+    termios::tcsetattr(pty.slave.as_fd(), SetArg::TCSANOW, &attrs)?;
+    set_baud(pty.slave.as_fd(), cli.baud)?;
+
+    let path = nix::unistd::ttyname(pty.slave.as_fd())?;
+    if let Some(link) = &cli.link {
+        let _ = std::fs::remove_file(link);
+        std::os::unix::fs::symlink(&path, link)?;
+    }
+    println!("Emulating arduino_answer_delay on {}", path.display());
+
+    // We keep the slave open, otherwise reading from the master fails while no
+    // client is connected.
+    let slave: OwnedFd = pty.slave;
+    let baud = cli.baud;
+    let mismatch =
+        move || cli.baud_mismatch || get_baud(slave.as_fd()).is_ok_and(|client| client != baud);
+    let master = tokio::fs::File::from_std(std::fs::File::from(pty.master));
+    let instrument = Instrument::new(config, seed);
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        res = serve(master, instrument, mismatch) => res?,
+        res = tokio::signal::ctrl_c() => res?,
+        _ = terminate.recv() => {}
+    }
+    if let Some(link) = &cli.link {
+        let _ = std::fs::remove_file(link);
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let cli = Cli::parse();
+    let config = Config {
+        replies: cli.replies.clone(),
+        jitter: Duration::from_millis(cli.jitter_ms),
+        drop_rate: cli.drop_rate,
+    };
+    let seed = cli.seed.unwrap_or_else(rand::random);
+
+    match cli.tcp {
+        Some(addr) => run_tcp(addr, cli, config, seed).await,
+        None => run_pty(cli, config, seed).await,
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Running emulator, killed when dropped.
+struct Emulator {
+    child: Child,
+    /// Address or pty path printed by the emulator on startup.
+    location: String,
+}
+
+impl Emulator {
+    fn start(args: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_arduino_emulator"))
+            .args(args)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.as_mut().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let location = line.split_whitespace().last().unwrap().to_string();
+        Self { child, location }
+    }
+
+    fn connect(&self) -> BufReader<TcpStream> {
+        let stream = TcpStream::connect(&self.location).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        BufReader::new(stream)
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn query(conn: &mut BufReader<TcpStream>, cmd: &str) -> std::io::Result<Vec<u8>> {
+    conn.get_mut().write_all(format!("{}\n", cmd).as_bytes())?;
+    let mut line = Vec::new();
+    conn.read_until(b'\n', &mut line)?;
+    Ok(line)
+}
+
+#[test]
+fn delay_is_acknowledged_after_the_delay() {
+    let emu = Emulator::start(&["--tcp", "127.0.0.1:0"]);
+    let mut conn = emu.connect();
+    conn.get_mut()
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+
+    let tic = Instant::now();
+    assert_eq!(query(&mut conn, "DELAY 1").unwrap(), b"ACK-SECOND\r\n");
+    assert!(tic.elapsed() >= Duration::from_secs(1));
+}
+
+#[test]
+fn extra_commands_and_unknown_commands() {
+    let emu = Emulator::start(&["--tcp", "127.0.0.1:0", "--reply", "*IDN?=ARDUINO,EMU"]);
+    let mut conn = emu.connect();
+
+    assert_eq!(query(&mut conn, "*idn?").unwrap(), b"ARDUINO,EMU\r\n");
+    assert!(query(&mut conn, "FOO 1").is_err());
+    assert_eq!(query(&mut conn, "DELAY 0").unwrap(), b"ACK-SECOND\r\n");
+}
+
+#[test]
+fn every_connection_is_its_own_instrument() {
+    let emu = Emulator::start(&["--tcp", "127.0.0.1:0"]);
+    let mut slow = emu.connect();
+    let mut fast = emu.connect();
+
+    slow.get_mut().write_all(b"DELAY 2\n").unwrap();
+    assert_eq!(query(&mut fast, "DELAY 0").unwrap(), b"ACK-SECOND\r\n");
+}
+
+#[test]
+fn dropped_replies() {
+    let emu = Emulator::start(&["--tcp", "127.0.0.1:0", "--drop-rate", "1"]);
+    let mut conn = emu.connect();
+
+    assert!(query(&mut conn, "DELAY 0").is_err());
+}
+
+/// The delays for a given seed are the same every time, see the unit tests of
+/// `instrument.rs`.
+#[test]
+fn jitter_is_bounded() {
+    let args = ["--tcp", "127.0.0.1:0", "--jitter-ms", "200", "--seed", "42"];
+    let emu = Emulator::start(&args);
+    let mut conn = emu.connect();
+
+    let tic = Instant::now();
+    assert_eq!(query(&mut conn, "DELAY 0").unwrap(), b"ACK-SECOND\r\n");
+    assert!(tic.elapsed() < Duration::from_millis(400));
+}
+
+#[test]
+fn baud_mismatch_garbles_replies() {
+    let emu = Emulator::start(&["--tcp", "127.0.0.1:0", "--baud-mismatch"]);
+    let mut conn = emu.connect();
+
+    let reply = query(&mut conn, "DELAY 0").unwrap();
+    assert_eq!(reply.len(), b"ACK-SECOND\r\n".len());
+    assert_ne!(reply, b"ACK-SECOND\r\n");
+    assert!(reply.ends_with(b"\r\n"));
+}
+
+#[test]
+fn pseudo_terminal() {
+    let emu = Emulator::start(&[]);
+    let mut port = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&emu.location)
+        .unwrap();
+
+    port.write_all(b"DELAY 0\n").unwrap();
+    let mut reply = [0; 12];
+    port.read_exact(&mut reply).unwrap();
+    assert_eq!(&reply, b"ACK-SECOND\r\n");
+}
@@ -0,0 +1,3 @@
+from async_serial_rs._lowlevel import Comm
+
+__all__ = ["Comm"]
@@ -0,0 +1,263 @@
+//! Async SCPI client that talks line based to an instrument.
+//!
+//! The client is generic over the transport, such that a serial port, a TCP
+//! socket or an in-memory pipe can be used interchangeably.
+//!
+//! The instrument answers one command after the other. A reply that arrives
+//! after its query timed out is therefore dropped when it arrives, instead of
+//! being returned for the next query. If the next query times out after
+//! dropping a reply, the earlier reply is taken as lost and the dropped line
+//! as the reply, such that a reply the instrument never sends does not
+//! desynchronize the client for good.
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::time::timeout;
+
+/// Line terminator used for sending and receiving SCPI messages.
+pub const TERMINATOR: &str = "\n";
+
+/// Errors that can occur when talking to an instrument.
+#[derive(Debug)]
+pub enum ScpiError {
+    /// The underlying transport failed.
+    Io(std::io::Error),
+    /// The serial port could not be opened.
+    Serial(tokio_serial::Error),
+    /// No complete reply was received within the timeout.
+    Timeout(Duration),
+    /// The instrument closed the connection.
+    Disconnected,
+}
+
+impl fmt::Display for ScpiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScpiError::Io(e) => write!(f, "I/O error: {}", e),
+            ScpiError::Serial(e) => write!(f, "Serial port error: {}", e),
+            ScpiError::Timeout(dt) => write!(f, "No reply within {:.3} s", dt.as_secs_f64()),
+            ScpiError::Disconnected => write!(f, "Instrument closed the connection"),
+        }
+    }
+}
+
+impl std::error::Error for ScpiError {}
+
+impl From<std::io::Error> for ScpiError {
+    fn from(e: std::io::Error) -> Self {
+        ScpiError::Io(e)
+    }
+}
+
+impl From<tokio_serial::Error> for ScpiError {
+    fn from(e: tokio_serial::Error) -> Self {
+        ScpiError::Serial(e)
+    }
+}
+
+/// SCPI client on top of any async byte stream.
+pub struct ScpiClient<T> {
+    stream: BufReader<T>,
+    timeout: Option<Duration>,
+    /// The received part of a line, kept when reading it times out.
+    line: Vec<u8>,
+    /// Replies still to come for queries that timed out or were cancelled.
+    stale: usize,
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> ScpiClient<T> {
+    /// Create a new client. A `timeout` of `None` waits forever for replies.
+    pub fn new(stream: T, timeout: Option<Duration>) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+            timeout,
+            line: Vec::new(),
+            stale: 0,
+        }
+    }
+
+    /// Set the timeout for reading replies.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Send a command without waiting for a reply.
+    pub async fn write(&mut self, cmd: &str) -> Result<(), ScpiError> {
+        let stream = self.stream.get_mut();
+        stream.write_all(cmd.as_bytes()).await?;
+        stream.write_all(TERMINATOR.as_bytes()).await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
+    /// Read one line from the instrument, without the terminator.
+    ///
+    /// Replies to queries that timed out are skipped. A line that is not
+    /// complete within the timeout is returned by the next call.
+    pub async fn read(&mut self) -> Result<String, ScpiError> {
+        self.reply(0).await
+    }
+
+    /// Send a command and read the reply.
+    pub async fn query(&mut self, cmd: &str) -> Result<String, ScpiError> {
+        self.write(cmd).await?;
+        // The reply counts as stale until it is received, such that it is
+        // skipped by later reads if this query does not finish.
+        self.stale += 1;
+        self.reply(1).await
+    }
+
+    /// Ask the instrument to acknowledge after `dt` seconds (`DELAY <dt>`).
+    pub async fn delay(&mut self, dt: u32) -> Result<String, ScpiError> {
+        self.query(&format!("DELAY {}", dt)).await
+    }
+
+    /// Read the next line after the stale replies, of which the last `own` are
+    /// the reply of the caller.
+    ///
+    /// If the timeout expires after skipping a line, the replies before it are
+    /// taken as lost, and the skipped line is returned.
+    async fn reply(&mut self, own: usize) -> Result<String, ScpiError> {
+        let mut skipped = None;
+        let result = with_timeout(self.timeout, async {
+            loop {
+                let line = self.next_line().await?;
+                if self.stale == own {
+                    self.stale = 0;
+                    return Ok(line);
+                }
+                self.stale -= 1;
+                skipped = Some(line);
+            }
+        })
+        .await;
+        match (result, skipped) {
+            (Err(ScpiError::Timeout(_)), Some(line)) => {
+                self.stale = 0;
+                Ok(line)
+            }
+            (result, _) => result,
+        }
+    }
+
+    /// Read up to the next terminator. Cancel safe: the received part of the
+    /// line stays in `self.line`.
+    async fn next_line(&mut self) -> Result<String, ScpiError> {
+        self.stream.read_until(b'\n', &mut self.line).await?;
+        if !self.line.ends_with(b"\n") {
+            return Err(ScpiError::Disconnected);
+        }
+        let line = String::from_utf8_lossy(&self.line).trim_end().to_string();
+        self.line.clear();
+        Ok(line)
+    }
+}
+
+/// Run `fut`, failing with `ScpiError::Timeout` if it takes longer than `dt`.
+async fn with_timeout<T>(
+    dt: Option<Duration>,
+    fut: impl Future<Output = Result<T, ScpiError>>,
+) -> Result<T, ScpiError> {
+    match dt {
+        Some(dt) => timeout(dt, fut).await.map_err(|_| ScpiError::Timeout(dt))?,
+        None => fut.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader as StdBufReader};
+    use std::process::{Child, Command, Stdio};
+
+    use tokio::io::duplex;
+    use tokio::net::TcpStream;
+
+    use super::*;
+
+    /// The `arduino_emulator` on a TCP port, killed when dropped.
+    struct Emulator {
+        child: Child,
+        addr: String,
+    }
+
+    impl Emulator {
+        fn start(args: &[&str]) -> Self {
+            let manifest = concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../arduino_emulator/Cargo.toml"
+            );
+            let mut child = Command::new(env!("CARGO"))
+                .args(["run", "--quiet", "--manifest-path", manifest, "--"])
+                .args(["--tcp", "127.0.0.1:0"])
+                .args(args)
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            let mut line = String::new();
+            StdBufReader::new(child.stdout.as_mut().unwrap())
+                .read_line(&mut line)
+                .unwrap();
+            let addr = line.split_whitespace().last().unwrap().to_string();
+            Self { child, addr }
+        }
+    }
+
+    impl Drop for Emulator {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    #[tokio::test]
+    async fn late_reply_is_not_returned_for_the_next_query() {
+        let emu = Emulator::start(&["--reply", "*IDN?=ARDUINO,EMU"]);
+        let stream = TcpStream::connect(&emu.addr).await.unwrap();
+        let mut client = ScpiClient::new(stream, Some(Duration::from_millis(200)));
+
+        assert!(matches!(client.delay(1).await, Err(ScpiError::Timeout(_))));
+        client.set_timeout(Some(Duration::from_secs(3)));
+        assert_eq!(client.query("*IDN?").await.unwrap(), "ARDUINO,EMU");
+        assert_eq!(client.delay(0).await.unwrap(), "ACK-SECOND");
+    }
+
+    #[tokio::test]
+    async fn lost_reply_does_not_desync_the_client() {
+        let (stream, mut instrument) = duplex(64);
+        let mut client = ScpiClient::new(stream, Some(Duration::from_millis(50)));
+
+        // The instrument never answers the first query.
+        assert!(matches!(
+            client.query("*IDN?").await,
+            Err(ScpiError::Timeout(_))
+        ));
+        instrument.write_all(b"ARDUINO,EMU\r\n").await.unwrap();
+        assert_eq!(client.query("*IDN?").await.unwrap(), "ARDUINO,EMU");
+        instrument.write_all(b"ACK-SECOND\r\n").await.unwrap();
+        assert_eq!(client.delay(0).await.unwrap(), "ACK-SECOND");
+    }
+
+    #[tokio::test]
+    async fn partial_line_is_kept_after_timeout() {
+        let (stream, mut instrument) = duplex(64);
+        let mut client = ScpiClient::new(stream, Some(Duration::from_millis(50)));
+
+        instrument.write_all(b"ACK-").await.unwrap();
+        assert!(matches!(client.read().await, Err(ScpiError::Timeout(_))));
+        instrument.write_all(b"SECOND\r\n").await.unwrap();
+        assert_eq!(client.read().await.unwrap(), "ACK-SECOND");
+    }
+
+    #[tokio::test]
+    async fn closed_connection() {
+        let (stream, mut instrument) = duplex(64);
+        let mut client = ScpiClient::new(stream, None);
+
+        instrument.write_all(b"ACK").await.unwrap();
+        drop(instrument);
+        assert!(matches!(client.read().await, Err(ScpiError::Disconnected)));
+    }
+}
@@ -0,0 +1,162 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use pyo3::exceptions::{PyConnectionError, PyIOError, PyTimeoutError, PyValueError};
+use pyo3::prelude::*;
+use pyo3_async_runtimes::tokio::{future_into_py, get_runtime};
+use tokio::sync::Mutex;
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+mod client;
+
+use crate::client::{ScpiClient, ScpiError};
+
+impl From<ScpiError> for PyErr {
+    fn from(e: ScpiError) -> Self {
+        match e {
+            ScpiError::Timeout(_) => PyTimeoutError::new_err(e.to_string()),
+            ScpiError::Disconnected => PyConnectionError::new_err(e.to_string()),
+            ScpiError::Io(_) | ScpiError::Serial(_) => PyIOError::new_err(e.to_string()),
+        }
+    }
+}
+
+/// Convert a timeout in seconds from Python into a `Duration`.
+fn to_duration(seconds: Option<f64>) -> PyResult<Option<Duration>> {
+    seconds
+        .map(|s| {
+            Duration::try_from_secs_f64(s)
+                .map_err(|_| PyValueError::new_err(format!("Invalid timeout: {}", s)))
+        })
+        .transpose()
+}
+
+/// Run a future on the shared tokio runtime while releasing the GIL.
+fn block_on<F, T>(py: Python<'_>, fut: F) -> PyResult<T>
+where
+    F: Future<Output = Result<T, ScpiError>> + Send,
+    T: Send,
+{
+    Ok(py.allow_threads(|| get_runtime().block_on(fut))?)
+}
+
+/// An SCPI instrument connected to a serial port.
+///
+/// Every instrument has its own connection, so several instruments can be
+/// awaited concurrently with `asyncio`. Commands to the same instrument are
+/// sent one after the other.
+#[pyclass]
+struct Comm {
+    #[pyo3(get)]
+    port: String,
+    #[pyo3(get)]
+    baudrate: u32,
+    client: Arc<Mutex<ScpiClient<SerialStream>>>,
+}
+
+#[pymethods]
+impl Comm {
+    /// Open the serial port.
+    ///
+    /// Arduinos reset when the port is opened, so we wait `reset_delay` seconds
+    /// before talking to them. A `timeout` of `None` waits forever for replies.
+    #[new]
+    #[pyo3(signature = (port, baudrate, timeout=None, reset_delay=1.0))]
+    fn new(
+        py: Python<'_>,
+        port: String,
+        baudrate: u32,
+        timeout: Option<f64>,
+        reset_delay: f64,
+    ) -> PyResult<Self> {
+        let timeout = to_duration(timeout)?;
+        let reset_delay = to_duration(Some(reset_delay))?.unwrap_or_default();
+
+        // The serial stream registers with the reactor of the current runtime.
+        let stream = {
+            let _guard = get_runtime().enter();
+            tokio_serial::new(&port, baudrate)
+                .open_native_async()
+                .map_err(ScpiError::from)?
+        };
+        py.allow_threads(|| std::thread::sleep(reset_delay));
+
+        Ok(Self {
+            port,
+            baudrate,
+            client: Arc::new(Mutex::new(ScpiClient::new(stream, timeout))),
+        })
+    }
+
+    /// Set the timeout for replies in seconds, `None` waits forever.
+    fn set_timeout(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<()> {
+        let timeout = to_duration(timeout)?;
+        let client = self.client.clone();
+        block_on(py, async move {
+            client.lock().await.set_timeout(timeout);
+            Ok(())
+        })
+    }
+
+    /// Send a command without waiting for a reply.
+    fn write(&self, py: Python<'_>, cmd: String) -> PyResult<()> {
+        let client = self.client.clone();
+        block_on(py, async move { client.lock().await.write(&cmd).await })
+    }
+
+    /// Read one line, e.g., after `write`.
+    fn read(&self, py: Python<'_>) -> PyResult<String> {
+        let client = self.client.clone();
+        block_on(py, async move { client.lock().await.read().await })
+    }
+
+    /// Send a command and return the reply.
+    fn query(&self, py: Python<'_>, cmd: String) -> PyResult<String> {
+        let client = self.client.clone();
+        block_on(py, async move { client.lock().await.query(&cmd).await })
+    }
+
+    /// Get an acknowledgement back after `dt` seconds.
+    fn delay(&self, py: Python<'_>, dt: u32) -> PyResult<String> {
+        let client = self.client.clone();
+        block_on(py, async move { client.lock().await.delay(dt).await })
+    }
+
+    /// Awaitable version of `write`.
+    fn async_write<'py>(&self, py: Python<'py>, cmd: String) -> PyResult<Bound<'py, PyAny>> {
+        let client = self.client.clone();
+        future_into_py(
+            py,
+            async move { Ok(client.lock().await.write(&cmd).await?) },
+        )
+    }
+
+    /// Awaitable version of `read`.
+    fn async_read<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let client = self.client.clone();
+        future_into_py(py, async move { Ok(client.lock().await.read().await?) })
+    }
+
+    /// Awaitable version of `query`.
+    fn async_query<'py>(&self, py: Python<'py>, cmd: String) -> PyResult<Bound<'py, PyAny>> {
+        let client = self.client.clone();
+        future_into_py(
+            py,
+            async move { Ok(client.lock().await.query(&cmd).await?) },
+        )
+    }
+
+    /// Awaitable version of `delay`.
+    fn async_delay<'py>(&self, py: Python<'py>, dt: u32) -> PyResult<Bound<'py, PyAny>> {
+        let client = self.client.clone();
+        future_into_py(py, async move { Ok(client.lock().await.delay(dt).await?) })
+    }
+}
+
+/// A Python module implemented in Rust.
+#[pymodule]
+fn _lowlevel(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Comm>()?;
+    Ok(())
+}
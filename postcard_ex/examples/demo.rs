@@ -0,0 +1,340 @@
+//! Demo of the wire commands: encoding, decoding, streaming, and the protocol.
+//!
+//! Attention: Not compatible with heapless 0.8, use 0.7 instead.
+
+use core::ops::Deref;
+use heapless::Vec;
+use std::time::{Duration, Instant};
+
+use postcard::experimental::max_size::MaxSize;
+use postcard::{to_vec, to_vec_cobs};
+
+use postcard_ex::capture::{self, CaptureReader, Direction, Recorder, RecordingPort, Timing};
+use postcard_ex::channel::{self, Demux, Mux, MuxError};
+use postcard_ex::checksum::{self, Checksum};
+use postcard_ex::commands::*;
+use postcard_ex::decode;
+use postcard_ex::host::Requests;
+use postcard_ex::stream::{max_frame_size, FrameDecoder};
+use postcard_ex::versioned::{self, Header, Message};
+
+/// Buffer for an encoded command, large enough for any command.
+type CommandBuf = Vec<u8, COMMAND_MAX_SIZE>;
+
+/// Buffer for a COBS frame of a command.
+type FrameBuf = Vec<u8, { max_frame_size(COMMAND_MAX_SIZE) }>;
+
+/// Buffer for an encoded request.
+type RequestBuf = Vec<u8, { Envelope::<Commands>::POSTCARD_MAX_SIZE }>;
+
+fn main() {
+    let position = Position { x: 123, y: 456 };
+    let send_position = Commands::SetPosition(position);
+
+    let send_position_pc: CommandBuf = to_vec(&send_position).unwrap();
+    decode(send_position_pc.deref());
+
+    let query_position = Commands::QueryPosition;
+
+    let query_position_pc: CommandBuf = to_vec(&query_position).unwrap();
+    decode(query_position_pc.deref());
+
+    let send_time = Commands::SetTime(1234567890);
+    let send_time_pc: CommandBuf = to_vec(&send_time).unwrap();
+    decode(send_time_pc.deref());
+
+    let set_label = Commands::SetLabel("stage 1".into());
+    let set_label_pc: CommandBuf = to_vec(&set_label).unwrap();
+    decode(set_label_pc.deref());
+
+    // The largest command, still fits into the buffer.
+    let upload = Commands::UploadChunk {
+        offset: u32::MAX,
+        data: Vec::from_slice(&[0xab; CHUNK_LEN]).unwrap(),
+    };
+    let upload_pc: CommandBuf = to_vec(&upload).unwrap();
+    println!(
+        "UploadChunk: {} of at most {} bytes",
+        upload_pc.len(),
+        COMMAND_MAX_SIZE
+    );
+    decode(upload_pc.deref());
+
+    // Test invalid data - should NOT panic!
+    decode(&[0x08, 0x00, 0x00, 0x00, 0x00]);
+    decode(&[0x00, 0x7b, 0xc8]);
+    decode(&[0x01, 0x00]);
+    decode(&[
+        0x02, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+    ]);
+    // A label one byte longer than allowed.
+    let mut long_label = std::vec![0x03, LABEL_LEN as u8 + 1];
+    long_label.extend_from_slice(&[b'a'; LABEL_LEN + 1]);
+    decode(&long_label);
+
+    stream();
+    protocol();
+    versions();
+    checksums();
+    record_and_replay();
+    channels();
+}
+
+/// Commands, telemetry, and log text over one stream, a telemetry flood does not delay the reply.
+fn channels() {
+    // Device: the telemetry queue overflows, the reply is queued anyway.
+    let mut mux = Mux::new();
+    mux.add_channel(channel::TELEMETRY, 0, 8)
+        .add_channel(channel::LOG, 1, 8)
+        .add_channel(channel::CONTROL, 2, 4);
+    let mut full = 0;
+    for x in 0..20 {
+        let report = Responses::PositionReport(Position { x, y: 0 });
+        if let Err(MuxError::Full(_)) = mux.push(channel::TELEMETRY, &report) {
+            full += 1;
+        }
+    }
+    mux.push(channel::LOG, &"position reached").unwrap();
+    let reply = Envelope {
+        seq: 7,
+        payload: Responses::Ack,
+    };
+    mux.push(channel::CONTROL, &reply).unwrap();
+    println!(
+        "Channels: {} telemetry reports did not fit into the queue",
+        full
+    );
+
+    let mut wire = std::vec::Vec::new();
+    while mux.write_next(&mut wire).unwrap() {}
+
+    // Host: the telemetry consumer is slow and only keeps 4 reports.
+    let mut demux = Demux::new();
+    let replies = demux.subscribe::<Envelope<Responses>>(channel::CONTROL, 4);
+    let telemetry = demux.subscribe::<Responses>(channel::TELEMETRY, 4);
+    let log = demux.subscribe::<std::string::String>(channel::LOG, 4);
+    for chunk in wire.chunks(7) {
+        demux.feed(chunk);
+    }
+    for reply in replies.try_iter() {
+        println!("Channels: reply {:?}", reply);
+    }
+    for line in log.try_iter() {
+        println!("Channels: log {:?}", line);
+    }
+    println!(
+        "Channels: {} telemetry reports",
+        telemetry.try_iter().count()
+    );
+    println!("Channels: {:?}", demux.stats());
+}
+
+/// Record the frames sent to a port, store them in a capture file, and replay them.
+fn record_and_replay() {
+    let path = std::env::temp_dir().join("postcard_ex_demo.cap");
+    let file = std::fs::File::create(&path).unwrap();
+    // The port is simulated by an in-memory buffer here.
+    let mut port = RecordingPort::new(std::vec::Vec::new(), Recorder::new(file).unwrap());
+
+    let commands = [
+        Commands::SetPosition(Position { x: 123, y: 456 }),
+        Commands::QueryPosition,
+        Commands::SetTime(1234567890),
+    ];
+    for cmd in commands {
+        let frame: FrameBuf = to_vec_cobs(&cmd).unwrap();
+        std::io::Write::write_all(&mut port, &frame).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert!(port.record_error().is_none());
+    let (_, mut recorder) = port.into_parts();
+    recorder.flush().unwrap();
+    println!("Recorded capture to {}", path.display());
+
+    let records = CaptureReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+    let mut sink = std::vec::Vec::new();
+    let start = Instant::now();
+    let count = capture::replay(records, Direction::Sent, Timing::Scaled(2.0), &mut sink).unwrap();
+    println!(
+        "Replayed {} frames ({} bytes) in {:?}",
+        count,
+        sink.len(),
+        start.elapsed()
+    );
+}
+
+/// CRC protected COBS frames, the second one with a flipped bit in `Position.x`.
+fn checksums() {
+    let cmd = Commands::SetPosition(Position { x: 123, y: 456 });
+    let mut buf = [0u8; 32];
+    let msg = checksum::encode(&cmd, Checksum::Crc16, &mut buf).unwrap();
+
+    let mut wire = [0u8; 64];
+    let mut len = cobs::encode(msg, &mut wire);
+    wire[len] = 0x00;
+    len += 1;
+    msg[1] ^= 0x04;
+    len += cobs::encode(msg, &mut wire[len..]);
+    wire[len] = 0x00;
+    len += 1;
+
+    let mut decoder: FrameDecoder<32> = FrameDecoder::with_checksum(Checksum::Crc16);
+    for frame in decoder.feed(&wire[..len]) {
+        println!("CRC stream: {:?}", frame);
+    }
+}
+
+/// Messages with a version header: current, migrated from v1, and from the future.
+fn versions() {
+    let mut buf = [0u8; 32];
+    let msg = Envelope {
+        seq: 7,
+        payload: Commands::SetTime(1234567890),
+    };
+    let current = versioned::encode(&msg, &mut buf).unwrap();
+    println!(
+        "Versioned {:?} -> {:?}",
+        current,
+        versioned::decode_versioned::<Envelope<Commands>>(current)
+    );
+
+    let mut buf = [0u8; 32];
+    let header = Header {
+        version: 1,
+        schema: versioned::schema_hash(versioned::v1::SCHEMA),
+    };
+    let old = (header, versioned::v1::Commands::QueryPosition);
+    let old = postcard::to_slice(&old, &mut buf).unwrap();
+    println!(
+        "Versioned {:?} -> {:?}",
+        old,
+        versioned::decode_versioned::<Envelope<Commands>>(old)
+    );
+
+    let mut buf = [0u8; 32];
+    let header = Header {
+        version: Envelope::<Commands>::VERSION + 1,
+        schema: 0,
+    };
+    let future = postcard::to_slice(&(header, 0u8), &mut buf).unwrap();
+    match versioned::decode_versioned::<Envelope<Commands>>(future) {
+        Ok(msg) => println!("Versioned {:?} -> {:?}", future, msg),
+        Err(err) => println!("Versioned {:?} -> {}", future, err),
+    }
+}
+
+/// Send requests with sequence numbers and match the replies of the device.
+fn protocol() {
+    let mut device = Position { x: 0, y: 0 };
+    let mut requests = Requests::new(Duration::from_millis(100));
+    let now = Instant::now();
+
+    let commands = [
+        Commands::SetPosition(Position { x: 12, y: 34 }),
+        Commands::QueryPosition,
+        Commands::SetTime(1234567890),
+    ];
+    for cmd in commands {
+        let request: RequestBuf = to_vec(&requests.request(cmd, now)).unwrap();
+        let reply = respond(&request, &mut device);
+        match requests.reply(reply) {
+            Ok((cmd, resp)) => println!("Request {:?} -> {:?}", cmd, resp),
+            Err(err) => println!("Unexpected reply: {:?}", err),
+        }
+    }
+
+    // A corrupted request is answered with a NACK for its sequence number.
+    let mut request: RequestBuf = to_vec(&requests.request(Commands::QueryPosition, now)).unwrap();
+    request.truncate(1);
+    request.push(0x08).unwrap();
+    let reply = respond(&request, &mut device);
+    println!("Corrupted request -> {:?}", requests.reply(reply));
+
+    // The device never answers this one.
+    requests.request(Commands::QueryPosition, now);
+    for timed_out in requests.expire(now + Duration::from_millis(200)) {
+        println!("Timed out: {:?}", timed_out);
+    }
+}
+
+/// What the device does with a request: decode, execute, and reply.
+fn respond(request: &[u8], position: &mut Position) -> Envelope<Responses> {
+    let (seq, payload) = match decode::decode::<Envelope<Commands>>(request) {
+        Ok(envelope) => (envelope.seq, envelope.payload),
+        Err(err) => {
+            let seq = decode::decode_partial::<u16>(request).map_or(0, |(seq, _)| seq);
+            return Envelope {
+                seq,
+                payload: Responses::Nack(err.code()),
+            };
+        }
+    };
+    let payload = match payload {
+        Commands::SetPosition(pos) => {
+            *position = pos;
+            Responses::Ack
+        }
+        Commands::QueryPosition => Responses::PositionReport(position.clone()),
+        Commands::SetTime(time) => Responses::TimeReport(time),
+        Commands::SetLabel(_) | Commands::UploadChunk { .. } => Responses::Ack,
+    };
+    Envelope { seq, payload }
+}
+
+/// Decode COBS framed commands that arrive in arbitrary chunks, e.g., from a serial port.
+fn stream() {
+    let mut wire: Vec<u8, 256> = Vec::new();
+    let commands = [
+        Commands::SetPosition(Position { x: 123, y: 456 }),
+        Commands::QueryPosition,
+        Commands::SetTime(1234567890),
+    ];
+    for (it, cmd) in commands.iter().enumerate() {
+        let frame: FrameBuf = to_vec_cobs(cmd).unwrap();
+        wire.extend_from_slice(&frame).unwrap();
+        // Some line noise after the first command, and a frame that is too long.
+        if it == 0 {
+            wire.extend_from_slice(&[0x03, 0x08, 0x00]).unwrap();
+            wire.extend_from_slice(&[0x42; 100]).unwrap();
+            wire.push(0x00).unwrap();
+        }
+    }
+
+    let mut decoder: FrameDecoder<{ max_frame_size(COMMAND_MAX_SIZE) }> = FrameDecoder::new();
+    for chunk in wire.chunks(5) {
+        for frame in decoder.feed(chunk) {
+            println!("Stream: {:?}", frame);
+        }
+    }
+}
+
+fn decode(bytes: &[u8]) {
+    println!("Bytes{:?}", bytes);
+
+    let cmd: Commands = match decode::decode(bytes) {
+        Ok(cmd) => cmd,
+        Err(err) => {
+            // The device would reply with a NACK containing `err.code()`.
+            println!("Rejected command: {} (code {})", err, err.code());
+            return;
+        }
+    };
+
+    match cmd {
+        Commands::SetPosition(pos) => {
+            println!("SetPosition: {:?}", pos);
+        }
+        Commands::SetTime(time) => {
+            println!("SetTime: {:?}", time);
+        }
+        Commands::QueryPosition => {
+            println!("Query position");
+        }
+        Commands::SetLabel(label) => {
+            println!("SetLabel: {:?}", label);
+        }
+        Commands::UploadChunk { offset, data } => {
+            println!("UploadChunk: {} bytes at {}", data.len(), offset);
+        }
+    }
+}
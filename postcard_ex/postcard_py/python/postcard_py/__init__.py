@@ -0,0 +1,3 @@
+from postcard_py._lowlevel import Commands, DecodeError, Position
+
+__all__ = ["Commands", "DecodeError", "Position"]
@@ -0,0 +1,307 @@
+use std::borrow::Cow;
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use postcard::experimental::max_size::MaxSize;
+
+use postcard_ex::commands;
+use postcard_ex::decode::decode;
+use postcard_ex::stream::{max_frame_size, DELIMITER};
+
+create_exception!(_lowlevel, DecodeError, PyValueError, "Invalid wire data.");
+
+/// Maximum size of an encoded command.
+const MAX_SIZE: usize = commands::Commands::POSTCARD_MAX_SIZE;
+
+/// Position of the stage.
+#[pyclass(eq, get_all, set_all)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Position {
+    x: u32,
+    y: u32,
+}
+
+#[pymethods]
+impl Position {
+    #[new]
+    fn new(x: u32, y: u32) -> Self {
+        Self { x, y }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Position(x={}, y={})", self.x, self.y)
+    }
+
+    /// Encode as postcard bytes, COBS framed including the delimiter if `cobs`.
+    #[pyo3(signature = (cobs=false))]
+    fn to_bytes(&self, cobs: bool) -> PyResult<Cow<'static, [u8]>> {
+        encode(&commands::Position::from(self.clone()), cobs)
+    }
+
+    /// Decode from postcard bytes, COBS framed if `cobs`.
+    #[staticmethod]
+    #[pyo3(signature = (data, cobs=false))]
+    fn from_bytes(data: Vec<u8>, cobs: bool) -> PyResult<Self> {
+        decode_bytes::<commands::Position>(data, cobs).map(Self::from)
+    }
+}
+
+impl From<commands::Position> for Position {
+    fn from(pos: commands::Position) -> Self {
+        Self { x: pos.x, y: pos.y }
+    }
+}
+
+impl From<Position> for commands::Position {
+    fn from(pos: Position) -> Self {
+        Self { x: pos.x, y: pos.y }
+    }
+}
+
+/// Commands sent to the device, one class per variant.
+///
+/// E.g., `Commands.SetPosition(Position(1, 2))`, `Commands.QueryPosition()`,
+/// `Commands.SetTime(1234)`, `Commands.SetLabel("stage")`, or
+/// `Commands.UploadChunk(0, b"data")`. Labels and chunks that are too long
+/// for the device raise a `ValueError` when encoded.
+#[pyclass(eq, name = "Commands")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PyCommands {
+    SetPosition { position: Position },
+    QueryPosition {},
+    SetTime { time: u64 },
+    SetLabel { label: String },
+    UploadChunk { offset: u32, data: Vec<u8> },
+}
+
+#[pymethods]
+impl PyCommands {
+    fn __repr__(&self) -> String {
+        match self {
+            PyCommands::SetPosition { position } => {
+                format!("Commands.SetPosition({})", position.__repr__())
+            }
+            PyCommands::QueryPosition {} => "Commands.QueryPosition()".to_string(),
+            PyCommands::SetTime { time } => format!("Commands.SetTime({})", time),
+            PyCommands::SetLabel { label } => format!("Commands.SetLabel({:?})", label),
+            PyCommands::UploadChunk { offset, data } => {
+                format!("Commands.UploadChunk({}, <{} bytes>)", offset, data.len())
+            }
+        }
+    }
+
+    /// Encode as postcard bytes, COBS framed including the delimiter if `cobs`.
+    #[pyo3(signature = (cobs=false))]
+    fn to_bytes(&self, cobs: bool) -> PyResult<Cow<'static, [u8]>> {
+        encode(&commands::Commands::try_from(self.clone())?, cobs)
+    }
+
+    /// Decode from postcard bytes, COBS framed if `cobs`.
+    #[staticmethod]
+    #[pyo3(signature = (data, cobs=false))]
+    fn from_bytes(data: Vec<u8>, cobs: bool) -> PyResult<Self> {
+        decode_bytes::<commands::Commands>(data, cobs).map(Self::from)
+    }
+}
+
+impl From<commands::Commands> for PyCommands {
+    fn from(cmd: commands::Commands) -> Self {
+        match cmd {
+            commands::Commands::SetPosition(pos) => PyCommands::SetPosition {
+                position: pos.into(),
+            },
+            commands::Commands::QueryPosition => PyCommands::QueryPosition {},
+            commands::Commands::SetTime(time) => PyCommands::SetTime { time },
+            commands::Commands::SetLabel(label) => PyCommands::SetLabel {
+                label: label.to_string(),
+            },
+            commands::Commands::UploadChunk { offset, data } => PyCommands::UploadChunk {
+                offset,
+                data: data.to_vec(),
+            },
+        }
+    }
+}
+
+impl TryFrom<PyCommands> for commands::Commands {
+    type Error = PyErr;
+
+    fn try_from(cmd: PyCommands) -> PyResult<Self> {
+        Ok(match cmd {
+            PyCommands::SetPosition { position } => {
+                commands::Commands::SetPosition(position.into())
+            }
+            PyCommands::QueryPosition {} => commands::Commands::QueryPosition,
+            PyCommands::SetTime { time } => commands::Commands::SetTime(time),
+            PyCommands::SetLabel { label } => {
+                let mut bounded = heapless::String::new();
+                bounded.push_str(&label).map_err(|_| {
+                    PyValueError::new_err(format!(
+                        "label longer than {} bytes",
+                        commands::LABEL_LEN
+                    ))
+                })?;
+                commands::Commands::SetLabel(bounded)
+            }
+            PyCommands::UploadChunk { offset, data } => commands::Commands::UploadChunk {
+                offset,
+                data: heapless::Vec::from_slice(&data).map_err(|_| {
+                    PyValueError::new_err(format!(
+                        "chunk longer than {} bytes",
+                        commands::CHUNK_LEN
+                    ))
+                })?,
+            },
+        })
+    }
+}
+
+fn encode<T: serde::Serialize>(value: &T, cobs: bool) -> PyResult<Cow<'static, [u8]>> {
+    // Large enough for the COBS overhead and the delimiter of the biggest command.
+    let mut buf = [0u8; max_frame_size(MAX_SIZE)];
+    let bytes = match cobs {
+        true => postcard::to_slice_cobs(value, &mut buf),
+        false => postcard::to_slice(value, &mut buf),
+    }
+    .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(Cow::Owned(bytes.to_vec()))
+}
+
+fn decode_bytes<T: serde::de::DeserializeOwned>(mut data: Vec<u8>, cobs: bool) -> PyResult<T> {
+    if cobs {
+        if data.last() == Some(&DELIMITER) {
+            data.pop();
+        }
+        let len = cobs::decode_in_place(&mut data)
+            .map_err(|_| DecodeError::new_err("invalid COBS frame"))?;
+        data.truncate(len);
+    }
+    decode(&data).map_err(|e| DecodeError::new_err(e.to_string()))
+}
+
+/// Wire commands of `postcard_ex` for Python.
+#[pymodule]
+fn _lowlevel(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Position>()?;
+    m.add_class::<PyCommands>()?;
+    m.add("DecodeError", m.py().get_type::<DecodeError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands() -> Vec<PyCommands> {
+        vec![
+            PyCommands::SetPosition {
+                position: Position::new(123, 456),
+            },
+            PyCommands::QueryPosition {},
+            PyCommands::SetTime { time: u64::MAX },
+            PyCommands::SetLabel {
+                label: "stage 1".to_string(),
+            },
+            PyCommands::SetLabel {
+                label: "x".repeat(commands::LABEL_LEN),
+            },
+            PyCommands::UploadChunk {
+                offset: 64,
+                data: vec![0; commands::CHUNK_LEN],
+            },
+            // The biggest command, with the most COBS overhead.
+            PyCommands::UploadChunk {
+                offset: u32::MAX,
+                data: vec![1; commands::CHUNK_LEN],
+            },
+        ]
+    }
+
+    /// Whether `err` is a `DecodeError`, or only a `ValueError` otherwise.
+    fn is_decode_error(err: PyErr) -> bool {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            assert!(err.is_instance_of::<PyValueError>(py));
+            err.is_instance_of::<DecodeError>(py)
+        })
+    }
+
+    #[test]
+    fn round_trip() {
+        for cmd in commands() {
+            for cobs in [false, true] {
+                let bytes = cmd.to_bytes(cobs).unwrap();
+                if cobs {
+                    assert_eq!(
+                        bytes.iter().position(|&b| b == DELIMITER),
+                        Some(bytes.len() - 1)
+                    );
+                }
+                assert_eq!(
+                    PyCommands::from_bytes(bytes.into_owned(), cobs).unwrap(),
+                    cmd
+                );
+            }
+        }
+
+        let pos = Position::new(1, u32::MAX);
+        let bytes = pos.to_bytes(true).unwrap().into_owned();
+        assert_eq!(Position::from_bytes(bytes, true).unwrap(), pos);
+    }
+
+    #[test]
+    fn same_bytes_as_rust() {
+        let cmd = commands::Commands::SetPosition(commands::Position { x: 123, y: 456 });
+        let py_cmd = PyCommands::from(cmd.clone());
+        assert_eq!(
+            *py_cmd.to_bytes(false).unwrap(),
+            *postcard::to_slice(&cmd, &mut [0; MAX_SIZE]).unwrap()
+        );
+        assert_eq!(
+            *py_cmd.to_bytes(true).unwrap(),
+            [0x01, 0x04, 0x7b, 0xc8, 0x03, 0x00]
+        );
+        // The delimiter is optional when decoding.
+        assert_eq!(
+            PyCommands::from_bytes(vec![0x01, 0x04, 0x7b, 0xc8, 0x03], true).unwrap(),
+            py_cmd
+        );
+    }
+
+    #[test]
+    fn bad_input_raises_decode_error() {
+        for (data, cobs) in [
+            (vec![], false),
+            (vec![0x05], false),
+            (vec![0x00, 0x7b], false),
+            (vec![0x01, 0x00], false),
+            (vec![0x03, 0x21], false),
+            (vec![0x00], true),
+            (vec![0x05, 0x01, 0x00], true),
+            (vec![0x02, 0x01, 0x01, 0x00], true),
+        ] {
+            let err = PyCommands::from_bytes(data.clone(), cobs).unwrap_err();
+            assert!(is_decode_error(err), "{:02x?}, cobs={}", data, cobs);
+        }
+        let err = Position::from_bytes(vec![0x80], false).unwrap_err();
+        assert!(is_decode_error(err));
+    }
+
+    #[test]
+    fn too_long_values_raise_value_error() {
+        for cmd in [
+            PyCommands::SetLabel {
+                label: "x".repeat(commands::LABEL_LEN + 1),
+            },
+            PyCommands::UploadChunk {
+                offset: 0,
+                data: vec![0; commands::CHUNK_LEN + 1],
+            },
+        ] {
+            let err = cmd.to_bytes(false).unwrap_err();
+            assert!(!is_decode_error(err), "{:?}", cmd);
+        }
+    }
+}
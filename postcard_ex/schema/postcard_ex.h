@@ -0,0 +1,309 @@
+/* Generated by gen_schema from the postcard_ex wire types, do not edit. */
+#ifndef POSTCARD_EX_H
+#define POSTCARD_EX_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <string.h>
+
+/* postcard encodes unsigned integers as varints, signed ones zigzag encoded,
+   and floats as little endian bytes. */
+
+typedef struct {
+    uint8_t *buf;
+    size_t len;
+    size_t pos;
+    bool ok;
+} pc_writer;
+
+typedef struct {
+    const uint8_t *buf;
+    size_t len;
+    size_t pos;
+    bool ok;
+} pc_reader;
+
+static inline void pc_put(pc_writer *w, uint8_t byte) {
+    if (w->pos < w->len) {
+        w->buf[w->pos++] = byte;
+    } else {
+        w->ok = false;
+    }
+}
+
+static inline void pc_put_varint(pc_writer *w, uint64_t value) {
+    while (value >= 0x80) {
+        pc_put(w, (uint8_t)(value | 0x80));
+        value >>= 7;
+    }
+    pc_put(w, (uint8_t)value);
+}
+
+static inline uint64_t pc_zigzag(int64_t value) {
+    return ((uint64_t)value << 1) ^ (uint64_t)(value >> 63);
+}
+
+static inline void pc_put_f32(pc_writer *w, float value) {
+    uint8_t bytes[4];
+    memcpy(bytes, &value, 4);
+    for (int it = 0; it < 4; it++) {
+        pc_put(w, bytes[it]);
+    }
+}
+
+/* Length and bytes, fails if there are more than `max` bytes. */
+static inline void pc_put_bytes(pc_writer *w, const uint8_t *data, size_t len, size_t max) {
+    if (len > max) {
+        w->ok = false;
+        return;
+    }
+    pc_put_varint(w, (uint64_t)len);
+    for (size_t it = 0; it < len; it++) {
+        pc_put(w, data[it]);
+    }
+}
+
+static inline uint8_t pc_get(pc_reader *r) {
+    if (r->pos < r->len) {
+        return r->buf[r->pos++];
+    }
+    r->ok = false;
+    return 0;
+}
+
+static inline bool pc_get_bool(pc_reader *r) {
+    uint8_t byte = pc_get(r);
+    if (byte > 1) {
+        r->ok = false;
+    }
+    return byte == 1;
+}
+
+/* Varint of at most `bytes` bytes with a value of at most `max`. */
+static inline uint64_t pc_get_uint(pc_reader *r, uint8_t bytes, uint64_t max) {
+    uint64_t value = 0;
+    for (uint8_t it = 0; it < bytes; it++) {
+        uint8_t byte = pc_get(r);
+        value |= (uint64_t)(byte & 0x7f) << (7 * it);
+        if (!(byte & 0x80)) {
+            if (value > max) {
+                r->ok = false;
+            }
+            return value;
+        }
+    }
+    r->ok = false;
+    return 0;
+}
+
+static inline int64_t pc_get_int(pc_reader *r, uint8_t bytes, int64_t min, int64_t max) {
+    uint64_t raw = pc_get_uint(r, bytes, UINT64_MAX);
+    int64_t value = (int64_t)(raw >> 1) ^ -(int64_t)(raw & 1);
+    if (value < min || value > max) {
+        r->ok = false;
+    }
+    return value;
+}
+
+/* Length and bytes into `data` of `max` bytes, returns the length. */
+static inline size_t pc_get_bytes(pc_reader *r, uint8_t *data, size_t max) {
+    size_t len = (size_t)pc_get_uint(r, 5, max);
+    if (!r->ok) {
+        return 0;
+    }
+    for (size_t it = 0; it < len; it++) {
+        data[it] = pc_get(r);
+    }
+    return len;
+}
+
+static inline float pc_get_f32(pc_reader *r) {
+    uint8_t bytes[4];
+    for (int it = 0; it < 4; it++) {
+        bytes[it] = pc_get(r);
+    }
+    float value;
+    memcpy(&value, bytes, 4);
+    return value;
+}
+
+typedef struct {
+    uint32_t x;
+    uint32_t y;
+} Position;
+
+static inline void position_put(pc_writer *w, const Position *v) {
+    pc_put_varint(w, (uint64_t)v->x);
+    pc_put_varint(w, (uint64_t)v->y);
+}
+
+static inline void position_get(pc_reader *r, Position *v) {
+    v->x = (uint32_t)pc_get_uint(r, 5, UINT32_MAX);
+    v->y = (uint32_t)pc_get_uint(r, 5, UINT32_MAX);
+}
+
+/* Encode into `buf`, returns the number of bytes or 0 if `buf` is too small. */
+static inline size_t position_encode(const Position *v, uint8_t *buf, size_t len) {
+    pc_writer w = {buf, len, 0, true};
+    position_put(&w, v);
+    return w.ok ? w.pos : 0;
+}
+
+/* Decode from `buf`, returns the number of bytes used or 0 if the data is invalid. */
+static inline size_t position_decode(Position *v, const uint8_t *buf, size_t len) {
+    pc_reader r = {buf, len, 0, true};
+    position_get(&r, v);
+    return r.ok ? r.pos : 0;
+}
+
+typedef enum {
+    COMMANDS_SET_POSITION = 0,
+    COMMANDS_QUERY_POSITION = 1,
+    COMMANDS_SET_TIME = 2,
+    COMMANDS_SET_LABEL = 3,
+    COMMANDS_UPLOAD_CHUNK = 4,
+} CommandsTag;
+
+typedef struct {
+    CommandsTag tag;
+    union {
+        Position set_position;
+        uint64_t set_time;
+        struct { size_t len; char data[32]; } set_label;
+        struct {
+            uint32_t offset;
+            struct { size_t len; uint8_t data[64]; } data;
+        } upload_chunk;
+    } value;
+} Commands;
+
+static inline void commands_put(pc_writer *w, const Commands *v) {
+    pc_put_varint(w, (uint64_t)v->tag);
+    switch (v->tag) {
+    case COMMANDS_SET_POSITION:
+        position_put(w, &v->value.set_position);
+        break;
+    case COMMANDS_QUERY_POSITION:
+        break;
+    case COMMANDS_SET_TIME:
+        pc_put_varint(w, (uint64_t)v->value.set_time);
+        break;
+    case COMMANDS_SET_LABEL:
+        pc_put_bytes(w, (const uint8_t *)v->value.set_label.data, v->value.set_label.len, 32);
+        break;
+    case COMMANDS_UPLOAD_CHUNK:
+        pc_put_varint(w, (uint64_t)v->value.upload_chunk.offset);
+        pc_put_bytes(w, (const uint8_t *)v->value.upload_chunk.data.data, v->value.upload_chunk.data.len, 64);
+        break;
+    default:
+        w->ok = false;
+    }
+}
+
+static inline void commands_get(pc_reader *r, Commands *v) {
+    v->tag = (CommandsTag)pc_get_uint(r, 5, 4);
+    switch (v->tag) {
+    case COMMANDS_SET_POSITION:
+        position_get(r, &v->value.set_position);
+        break;
+    case COMMANDS_QUERY_POSITION:
+        break;
+    case COMMANDS_SET_TIME:
+        v->value.set_time = (uint64_t)pc_get_uint(r, 10, UINT64_MAX);
+        break;
+    case COMMANDS_SET_LABEL:
+        v->value.set_label.len = pc_get_bytes(r, (uint8_t *)v->value.set_label.data, 32);
+        break;
+    case COMMANDS_UPLOAD_CHUNK:
+        v->value.upload_chunk.offset = (uint32_t)pc_get_uint(r, 5, UINT32_MAX);
+        v->value.upload_chunk.data.len = pc_get_bytes(r, (uint8_t *)v->value.upload_chunk.data.data, 64);
+        break;
+    default:
+        r->ok = false;
+    }
+}
+
+/* Encode into `buf`, returns the number of bytes or 0 if `buf` is too small. */
+static inline size_t commands_encode(const Commands *v, uint8_t *buf, size_t len) {
+    pc_writer w = {buf, len, 0, true};
+    commands_put(&w, v);
+    return w.ok ? w.pos : 0;
+}
+
+/* Decode from `buf`, returns the number of bytes used or 0 if the data is invalid. */
+static inline size_t commands_decode(Commands *v, const uint8_t *buf, size_t len) {
+    pc_reader r = {buf, len, 0, true};
+    commands_get(&r, v);
+    return r.ok ? r.pos : 0;
+}
+
+typedef enum {
+    RESPONSES_POSITION_REPORT = 0,
+    RESPONSES_ACK = 1,
+    RESPONSES_NACK = 2,
+    RESPONSES_TIME_REPORT = 3,
+} ResponsesTag;
+
+typedef struct {
+    ResponsesTag tag;
+    union {
+        Position position_report;
+        uint8_t nack;
+        uint64_t time_report;
+    } value;
+} Responses;
+
+static inline void responses_put(pc_writer *w, const Responses *v) {
+    pc_put_varint(w, (uint64_t)v->tag);
+    switch (v->tag) {
+    case RESPONSES_POSITION_REPORT:
+        position_put(w, &v->value.position_report);
+        break;
+    case RESPONSES_ACK:
+        break;
+    case RESPONSES_NACK:
+        pc_put(w, (uint8_t)v->value.nack);
+        break;
+    case RESPONSES_TIME_REPORT:
+        pc_put_varint(w, (uint64_t)v->value.time_report);
+        break;
+    default:
+        w->ok = false;
+    }
+}
+
+static inline void responses_get(pc_reader *r, Responses *v) {
+    v->tag = (ResponsesTag)pc_get_uint(r, 5, 3);
+    switch (v->tag) {
+    case RESPONSES_POSITION_REPORT:
+        position_get(r, &v->value.position_report);
+        break;
+    case RESPONSES_ACK:
+        break;
+    case RESPONSES_NACK:
+        v->value.nack = pc_get(r);
+        break;
+    case RESPONSES_TIME_REPORT:
+        v->value.time_report = (uint64_t)pc_get_uint(r, 10, UINT64_MAX);
+        break;
+    default:
+        r->ok = false;
+    }
+}
+
+/* Encode into `buf`, returns the number of bytes or 0 if `buf` is too small. */
+static inline size_t responses_encode(const Responses *v, uint8_t *buf, size_t len) {
+    pc_writer w = {buf, len, 0, true};
+    responses_put(&w, v);
+    return w.ok ? w.pos : 0;
+}
+
+/* Decode from `buf`, returns the number of bytes used or 0 if the data is invalid. */
+static inline size_t responses_decode(Responses *v, const uint8_t *buf, size_t len) {
+    pc_reader r = {buf, len, 0, true};
+    responses_get(&r, v);
+    return r.ok ? r.pos : 0;
+}
+
+#endif /* POSTCARD_EX_H */
@@ -0,0 +1,25 @@
+//! Write the schemas of the wire types as JSON and as C header.
+//!
+//! Usage: `cargo run --features schema --bin gen_schema [OUT_DIR]`, the
+//! default output directory is `schema`.
+
+use std::fs;
+use std::path::PathBuf;
+
+use postcard_ex::schema;
+
+fn main() -> Result<(), String> {
+    let out_dir = PathBuf::from(
+        std::env::args()
+            .nth(1)
+            .unwrap_or_else(|| "schema".to_string()),
+    );
+    fs::create_dir_all(&out_dir).map_err(|e| e.to_string())?;
+
+    for (name, content) in schema::files()? {
+        let path = out_dir.join(name);
+        fs::write(&path, content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,494 @@
+//! Record and replay streams of frames with timestamps.
+//!
+//! A capture file starts with the magic bytes `PCEXCAP` and a format version
+//! byte, followed by one record per frame:
+//!
+//! | bytes | content                                       |
+//! |-------|-----------------------------------------------|
+//! | 8     | microseconds since the start of the recording |
+//! | 1     | direction, 0: sent by the host, 1: received   |
+//! | 4     | length `n` of the frame, at most `MAX_RECORD` |
+//! | n     | the frame as it was on the wire               |
+//!
+//! All numbers are little endian. Frames are stored as they were on the wire,
+//! e.g., COBS encoded including the delimiter, such that a replay sends
+//! exactly the same bytes.
+
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::stream::DELIMITER;
+
+const MAGIC: &[u8; 7] = b"PCEXCAP";
+const FORMAT_VERSION: u8 = 1;
+
+/// Maximum length of a recorded frame.
+///
+/// Longer writes and received data without delimiter are split into several
+/// records, such that a corrupt length cannot make the reader allocate
+/// gigabytes.
+pub const MAX_RECORD: usize = 64 * 1024;
+
+/// Who sent a frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    /// Sent by the host to the device.
+    Sent,
+    /// Received by the host from the device.
+    Received,
+}
+
+/// A recorded frame.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record {
+    /// Time since the start of the recording.
+    pub time: Duration,
+    pub direction: Direction,
+    pub frame: Vec<u8>,
+}
+
+/// Writes records to a capture file.
+pub struct Recorder<W: Write> {
+    out: W,
+    start: Instant,
+}
+
+impl<W: Write> Recorder<W> {
+    /// Start a recording, timestamps are relative to now.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[FORMAT_VERSION])?;
+        Ok(Self {
+            out,
+            start: Instant::now(),
+        })
+    }
+
+    /// Record a frame with the current time.
+    pub fn record(&mut self, direction: Direction, frame: &[u8]) -> io::Result<()> {
+        let time = self.start.elapsed();
+        self.write(&Record {
+            time,
+            direction,
+            frame: frame.to_vec(),
+        })
+    }
+
+    /// Write a record with a given timestamp, e.g., to edit a capture.
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        let micros = u64::try_from(record.time.as_micros()).unwrap_or(u64::MAX);
+        if record.frame.len() > MAX_RECORD {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame too long",
+            ));
+        }
+        let len = record.frame.len() as u32;
+        self.out.write_all(&micros.to_le_bytes())?;
+        self.out.write_all(&[match record.direction {
+            Direction::Sent => 0,
+            Direction::Received => 1,
+        }])?;
+        self.out.write_all(&len.to_le_bytes())?;
+        self.out.write_all(&record.frame)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Reads the records of a capture file.
+pub struct CaptureReader<R: Read> {
+    input: R,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Check the file header, fails if it is not a capture file.
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut header = [0u8; 8];
+        input.read_exact(&mut header)?;
+        if &header[..7] != MAGIC {
+            return Err(invalid_data("not a capture file"));
+        }
+        if header[7] != FORMAT_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported capture format version {}",
+                header[7]
+            )));
+        }
+        Ok(Self { input })
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<Record>> {
+        let mut head = [0u8; 13];
+        // A clean end of file is only allowed between records.
+        match self.input.read(&mut head[..1])? {
+            0 => return Ok(None),
+            _ => self.input.read_exact(&mut head[1..])?,
+        }
+        let micros = u64::from_le_bytes(head[..8].try_into().unwrap());
+        let direction = match head[8] {
+            0 => Direction::Sent,
+            1 => Direction::Received,
+            other => return Err(invalid_data(format!("invalid direction {}", other))),
+        };
+        let len = u32::from_le_bytes(head[9..].try_into().unwrap()) as usize;
+        if len > MAX_RECORD {
+            return Err(invalid_data(format!("record of {} bytes is too long", len)));
+        }
+        let mut frame = vec![0u8; len];
+        self.input.read_exact(&mut frame)?;
+        Ok(Some(Record {
+            time: Duration::from_micros(micros),
+            direction,
+            frame,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// A port that records all frames written to and read from it.
+///
+/// Every write is recorded as one sent frame. Received bytes are split into
+/// frames at the COBS delimiter, a frame is recorded once it is complete.
+///
+/// Failing to record does not fail the I/O on the port, which already
+/// happened. The recording stops instead, see `record_error`.
+pub struct RecordingPort<P, W: Write> {
+    port: P,
+    recorder: Recorder<W>,
+    received: Vec<u8>,
+    error: Option<io::Error>,
+}
+
+impl<P, W: Write> RecordingPort<P, W> {
+    pub fn new(port: P, recorder: Recorder<W>) -> Self {
+        Self {
+            port,
+            recorder,
+            received: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// The error that stopped the recording, if any.
+    pub fn record_error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    fn record(&mut self, direction: Direction, frame: &[u8]) {
+        if self.error.is_none() {
+            self.error = self.recorder.record(direction, frame).err();
+        }
+    }
+
+    /// Stop recording, return the port and the recorder.
+    pub fn into_parts(self) -> (P, Recorder<W>) {
+        (self.port, self.recorder)
+    }
+}
+
+impl<P: Read, W: Write> Read for RecordingPort<P, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.port.read(buf)?;
+        for &byte in &buf[..len] {
+            self.received.push(byte);
+            if byte == DELIMITER || self.received.len() == MAX_RECORD {
+                let frame = std::mem::take(&mut self.received);
+                self.record(Direction::Received, &frame);
+            }
+        }
+        Ok(len)
+    }
+}
+
+impl<P: Write, W: Write> Write for RecordingPort<P, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.port.write(buf)?;
+        for chunk in buf[..len].chunks(MAX_RECORD) {
+            self.record(Direction::Sent, chunk);
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.error.is_none() {
+            self.error = self.recorder.flush().err();
+        }
+        self.port.flush()
+    }
+}
+
+/// Timing of a replay.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timing {
+    /// Same time between frames as in the recording.
+    Original,
+    /// Time between frames multiplied by the factor, e.g., 0.5 for twice as fast.
+    Scaled(f64),
+    /// Send all frames without waiting.
+    Immediate,
+}
+
+/// Send the frames of `records` with the given `direction` to `sink`.
+///
+/// Frames are sent at their recorded time relative to the start of the replay
+/// (scaled by `timing`), such that delays do not add up. Returns the number of
+/// frames sent.
+pub fn replay<I, W>(
+    records: I,
+    direction: Direction,
+    timing: Timing,
+    sink: &mut W,
+) -> io::Result<usize>
+where
+    I: IntoIterator<Item = io::Result<Record>>,
+    W: Write,
+{
+    let start = Instant::now();
+    let mut offset = None;
+    let mut count = 0;
+    for record in records {
+        let record = record?;
+        if record.direction != direction {
+            continue;
+        }
+        // The first frame is sent right away.
+        let time = record
+            .time
+            .saturating_sub(*offset.get_or_insert(record.time));
+        let due = match timing {
+            Timing::Original => Some(time),
+            Timing::Scaled(factor) => Some(time.mul_f64(factor)),
+            Timing::Immediate => None,
+        };
+        if let Some(wait) = due.and_then(|due| due.checked_sub(start.elapsed())) {
+            thread::sleep(wait);
+        }
+        sink.write_all(&record.frame)?;
+        sink.flush()?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads from `input`, writes to `output`.
+    struct Port {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Port {
+        fn new(input: Vec<u8>) -> Self {
+            Self {
+                input: io::Cursor::new(input),
+                output: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for Port {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Port {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record {
+                time: Duration::from_micros(0),
+                direction: Direction::Sent,
+                frame: vec![0x01, 0x01, 0x00],
+            },
+            Record {
+                time: Duration::from_micros(1500),
+                direction: Direction::Received,
+                frame: vec![],
+            },
+            Record {
+                time: Duration::from_secs(3600),
+                direction: Direction::Received,
+                frame: vec![0xab; MAX_RECORD],
+            },
+        ]
+    }
+
+    fn capture(records: &[Record]) -> Vec<u8> {
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        for record in records {
+            recorder.write(record).unwrap();
+        }
+        recorder.into_inner()
+    }
+
+    fn read_all(data: &[u8]) -> io::Result<Vec<Record>> {
+        CaptureReader::new(data)?.collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = capture(&records());
+        assert_eq!(&data[..8], b"PCEXCAP\x01");
+        assert_eq!(read_all(&data).unwrap(), records());
+        assert_eq!(read_all(&capture(&[])).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn recording_port() {
+        let recorder = Recorder::new(Vec::new()).unwrap();
+        let mut port = RecordingPort::new(Port::new(vec![0x02, 0x05, 0x00, 0x03, 0x07]), recorder);
+        port.write_all(&[0x03, 0x08, 0x09, 0x00]).unwrap();
+        let mut received = Vec::new();
+        port.read_to_end(&mut received).unwrap();
+        let (port, recorder) = port.into_parts();
+        assert_eq!(port.output, [0x03, 0x08, 0x09, 0x00]);
+        assert_eq!(received, [0x02, 0x05, 0x00, 0x03, 0x07]);
+
+        let records = read_all(&recorder.into_inner()).unwrap();
+        let frames: Vec<_> = records
+            .iter()
+            .map(|record| (record.direction, record.frame.as_slice()))
+            .collect();
+        // The incomplete frame at the end is not recorded.
+        assert_eq!(
+            frames,
+            [
+                (Direction::Sent, &[0x03, 0x08, 0x09, 0x00][..]),
+                (Direction::Received, &[0x02, 0x05, 0x00][..]),
+            ]
+        );
+        assert!(records[0].time <= records[1].time);
+    }
+
+    #[test]
+    fn record_error_does_not_fail_the_port() {
+        // Room for the header only.
+        let mut capture = [0; 8];
+        let recorder = Recorder::new(&mut capture[..]).unwrap();
+        let mut port = RecordingPort::new(Port::new(vec![0x02, 0x05, 0x00]), recorder);
+        assert_eq!(port.write(&[0x03, 0x08, 0x00]).unwrap(), 3);
+        let err = port.record_error().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+
+        let mut received = [0; 8];
+        assert_eq!(port.read(&mut received).unwrap(), 3);
+        assert_eq!(received[..3], [0x02, 0x05, 0x00]);
+        assert!(port.received.is_empty());
+        port.flush().unwrap();
+        let (port, _) = port.into_parts();
+        assert_eq!(port.output, [0x03, 0x08, 0x00]);
+    }
+
+    #[test]
+    fn long_data_is_split_into_records() {
+        let recorder = Recorder::new(Vec::new()).unwrap();
+        let mut port = RecordingPort::new(Port::new(vec![0x01; MAX_RECORD + 1]), recorder);
+        port.write_all(&vec![0x02; MAX_RECORD + 1]).unwrap();
+        port.read_to_end(&mut Vec::new()).unwrap();
+        let (_, recorder) = port.into_parts();
+
+        let lengths: Vec<_> = read_all(&recorder.into_inner())
+            .unwrap()
+            .iter()
+            .map(|record| (record.direction, record.frame.len()))
+            .collect();
+        assert_eq!(
+            lengths,
+            [
+                (Direction::Sent, MAX_RECORD),
+                (Direction::Sent, 1),
+                (Direction::Received, MAX_RECORD),
+            ]
+        );
+    }
+
+    #[test]
+    fn too_long_frame_is_not_written() {
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        let err = recorder
+            .record(Direction::Sent, &vec![0; MAX_RECORD + 1])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(recorder.into_inner().len(), 8);
+    }
+
+    #[test]
+    fn invalid_header() {
+        let err = |data: &[u8]| CaptureReader::new(data).err().unwrap();
+        let e = err(b"PCEXCAP");
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        let e = err(b"PCAPFILE");
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(e.to_string(), "not a capture file");
+        let e = err(b"PCEXCAP\x02");
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(e.to_string(), "unsupported capture format version 2");
+    }
+
+    #[test]
+    fn corrupt_records() {
+        let data = capture(&records()[..1]);
+        // Each record is 8 bytes time, 1 byte direction, 4 bytes length, data.
+        assert_eq!(data.len(), 8 + 13 + 3);
+
+        for len in [9, 8 + 5, 8 + 13, 8 + 13 + 2] {
+            let err = read_all(&data[..len]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "{} bytes", len);
+        }
+
+        let mut direction = data.clone();
+        direction[8 + 8] = 2;
+        let err = read_all(&direction).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "invalid direction 2");
+
+        for len in [MAX_RECORD as u32 + 1, u32::MAX] {
+            let mut long = data.clone();
+            long[8 + 9..8 + 13].copy_from_slice(&len.to_le_bytes());
+            let err = read_all(&long).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(
+                err.to_string(),
+                format!("record of {} bytes is too long", len)
+            );
+        }
+
+        // Records before the corrupt one are still read.
+        let mut data = data;
+        data.extend_from_slice(&direction[8..]);
+        let mut reader = CaptureReader::new(&data[..]).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap(), records()[0]);
+        assert!(reader.next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,522 @@
+//! Several independent logical channels over one byte stream.
+//!
+//! Every frame carries the id of its channel in front of the message:
+//!
+//! ```text
+//! COBS(channel: u8, message) 0x00
+//! ```
+//!
+//! On the sending side, `Mux` queues the frames per channel and writes the
+//! frames of higher priority channels first, such that, e.g., a flood of
+//! telemetry does not delay the replies to commands. Each channel has its own
+//! queue limit, a full telemetry queue does not stop commands from being queued.
+//!
+//! On the receiving side, `Demux` splits the stream into frames and hands the
+//! decoded messages of each channel to its own consumer. A consumer that falls
+//! behind only loses messages of its own channel.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::decode::{decode, DecodeError};
+use crate::stream::{FrameDecoder, MAX_FRAME};
+
+/// Commands and their replies.
+pub const CONTROL: u8 = 0;
+/// Periodic measurements of the device.
+pub const TELEMETRY: u8 = 1;
+/// Log text of the device.
+pub const LOG: u8 = 2;
+
+/// Why a message could not be queued.
+#[derive(Debug)]
+pub enum MuxError {
+    /// The channel was not added to the `Mux`.
+    UnknownChannel(u8),
+    /// The queue of the channel is full, the message was dropped.
+    Full(u8),
+    /// The message could not be encoded, e.g., because it is too large.
+    Encode(postcard::Error),
+}
+
+impl fmt::Display for MuxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MuxError::UnknownChannel(id) => write!(f, "unknown channel {}", id),
+            MuxError::Full(id) => write!(f, "queue of channel {} is full", id),
+            MuxError::Encode(err) => write!(f, "cannot encode message: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MuxError {}
+
+/// Encode `msg` as COBS frame on `channel`, including the delimiter.
+pub fn encode<'a, T: Serialize>(
+    channel: u8,
+    msg: &T,
+    buf: &'a mut [u8],
+) -> postcard::Result<&'a mut [u8]> {
+    postcard::to_slice_cobs(&(channel, msg), buf)
+}
+
+/// Queued frames of a channel.
+struct Queue {
+    id: u8,
+    priority: u8,
+    capacity: usize,
+    frames: VecDeque<Vec<u8>>,
+}
+
+/// Sending side: queues frames per channel and writes them by priority.
+#[derive(Default)]
+pub struct Mux {
+    /// Sorted by priority, highest first.
+    queues: Vec<Queue>,
+}
+
+impl Mux {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a channel that queues up to `capacity` frames.
+    ///
+    /// Frames of channels with a higher `priority` are written first, channels
+    /// of equal priority in the order they were added. Adding a channel again
+    /// changes its priority and capacity.
+    pub fn add_channel(&mut self, id: u8, priority: u8, capacity: usize) -> &mut Self {
+        let frames = match self.queues.iter().position(|q| q.id == id) {
+            Some(idx) => self.queues.remove(idx).frames,
+            None => VecDeque::new(),
+        };
+        let idx = self
+            .queues
+            .iter()
+            .position(|q| q.priority < priority)
+            .unwrap_or(self.queues.len());
+        self.queues.insert(
+            idx,
+            Queue {
+                id,
+                priority,
+                capacity,
+                frames,
+            },
+        );
+        self
+    }
+
+    /// Encode `msg` and queue it on `channel`.
+    pub fn push<T: Serialize>(&mut self, channel: u8, msg: &T) -> Result<(), MuxError> {
+        let queue = self
+            .queues
+            .iter_mut()
+            .find(|q| q.id == channel)
+            .ok_or(MuxError::UnknownChannel(channel))?;
+        if queue.frames.len() >= queue.capacity {
+            return Err(MuxError::Full(channel));
+        }
+        let mut buf = [0u8; MAX_FRAME];
+        let frame = encode(channel, msg, &mut buf).map_err(MuxError::Encode)?;
+        queue.frames.push_back(frame.to_vec());
+        Ok(())
+    }
+
+    /// Take the next frame to send: the oldest one of the highest priority.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.queues.iter_mut().find_map(|q| q.frames.pop_front())
+    }
+
+    /// Write the next frame, returns `false` if there was none.
+    ///
+    /// Writing one frame at a time, and queueing new messages in between,
+    /// lets urgent frames overtake the queued ones of lower priority.
+    pub fn write_next<W: Write>(&mut self, out: &mut W) -> io::Result<bool> {
+        match self.pop() {
+            Some(frame) => {
+                out.write_all(&frame)?;
+                out.flush()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Number of queued frames of `channel`.
+    pub fn queued(&self, channel: u8) -> usize {
+        self.queues
+            .iter()
+            .find(|q| q.id == channel)
+            .map_or(0, |q| q.frames.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(|q| q.frames.is_empty())
+    }
+}
+
+/// Counters of the receiving side.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DemuxStats {
+    /// Frames handed to a consumer.
+    pub delivered: usize,
+    /// Frames that are not valid COBS, are empty, or are too large.
+    pub corrupted: usize,
+    /// Frames of channels without a consumer.
+    pub unknown: usize,
+    /// Frames dropped because the consumer of their channel fell behind.
+    pub dropped: usize,
+}
+
+/// What happened to a frame passed to a consumer.
+enum Delivery {
+    Delivered,
+    Full,
+    Closed,
+}
+
+/// Decodes a payload and passes it on.
+type Deliver = Box<dyn FnMut(&[u8]) -> Delivery + Send>;
+
+struct Consumer {
+    id: u8,
+    deliver: Deliver,
+}
+
+/// Receiving side: splits the stream into frames and passes each to the
+/// consumer of its channel.
+pub struct Demux {
+    consumers: Vec<Consumer>,
+    /// Only yields raw frames, the messages are decoded by the consumers.
+    frames: FrameDecoder<MAX_FRAME, ()>,
+    stats: DemuxStats,
+}
+
+impl Default for Demux {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Demux {
+    pub fn new() -> Self {
+        Self {
+            consumers: Vec::new(),
+            frames: FrameDecoder::new(),
+            stats: DemuxStats::default(),
+        }
+    }
+
+    /// Receive the messages of `channel`, decoded as `T`.
+    ///
+    /// Up to `capacity` messages are kept until they are received, further
+    /// messages of the channel are dropped, see `DemuxStats::dropped`.
+    /// Subscribing to a channel again replaces the previous consumer.
+    pub fn subscribe<T>(&mut self, channel: u8, capacity: usize) -> Receiver<Result<T, DecodeError>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let (tx, rx): (SyncSender<Result<T, DecodeError>>, _) = sync_channel(capacity);
+        let deliver = move |payload: &[u8]| match tx.try_send(decode(payload)) {
+            Ok(()) => Delivery::Delivered,
+            Err(TrySendError::Full(_)) => Delivery::Full,
+            Err(TrySendError::Disconnected(_)) => Delivery::Closed,
+        };
+        self.consumers.retain(|c| c.id != channel);
+        self.consumers.push(Consumer {
+            id: channel,
+            deliver: Box::new(deliver),
+        });
+        rx
+    }
+
+    pub fn stats(&self) -> DemuxStats {
+        self.stats
+    }
+
+    /// Feed a chunk of received bytes, completed frames are delivered.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        let Self {
+            consumers,
+            frames,
+            stats,
+        } = self;
+        frames.feed_raw(chunk, |frame| match frame {
+            Ok([channel, payload @ ..]) => dispatch(consumers, stats, *channel, payload),
+            Ok([]) | Err(_) => stats.corrupted += 1,
+        });
+    }
+
+    /// Read from `port` and deliver the frames until the port is closed or
+    /// fails, or all consumers are gone.
+    ///
+    /// Read timeouts are ignored, such that a serial port with a read timeout
+    /// can be used. Meant to run on a thread of its own.
+    pub fn run<R: Read>(&mut self, port: &mut R) -> io::Result<()> {
+        let mut buf = [0u8; 64];
+        loop {
+            if self.consumers.is_empty() {
+                return Ok(());
+            }
+            match port.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(len) => self.feed(&buf[..len]),
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        ErrorKind::TimedOut | ErrorKind::WouldBlock | ErrorKind::Interrupted
+                    ) => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Pass the payload of a frame to the consumer of its channel.
+fn dispatch(consumers: &mut Vec<Consumer>, stats: &mut DemuxStats, channel: u8, payload: &[u8]) {
+    let Some(idx) = consumers.iter().position(|c| c.id == channel) else {
+        stats.unknown += 1;
+        return;
+    };
+    match (consumers[idx].deliver)(payload) {
+        Delivery::Delivered => stats.delivered += 1,
+        Delivery::Full => stats.dropped += 1,
+        Delivery::Closed => {
+            consumers.remove(idx);
+            stats.unknown += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::commands::{Position, Responses};
+
+    fn frame<T: Serialize>(channel: u8, msg: &T) -> Vec<u8> {
+        let mut buf = [0u8; MAX_FRAME];
+        encode(channel, msg, &mut buf).unwrap().to_vec()
+    }
+
+    /// A port that fails with the given errors, in order, and then is closed.
+    struct Errors(Vec<ErrorKind>);
+
+    impl Read for Errors {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            match self.0.is_empty() {
+                true => Ok(0),
+                false => Err(self.0.remove(0).into()),
+            }
+        }
+    }
+
+    fn report(x: u32) -> Responses {
+        Responses::PositionReport(Position { x, y: 0 })
+    }
+
+    #[test]
+    fn mux_writes_by_priority() {
+        let mut mux = Mux::new();
+        mux.add_channel(TELEMETRY, 0, 8)
+            .add_channel(LOG, 1, 8)
+            .add_channel(CONTROL, 2, 8)
+            .add_channel(3, 1, 8);
+        mux.push(TELEMETRY, &1u32).unwrap();
+        mux.push(TELEMETRY, &2u32).unwrap();
+        mux.push(3, &3u32).unwrap();
+        mux.push(LOG, &4u32).unwrap();
+        mux.push(CONTROL, &5u32).unwrap();
+        assert_eq!(mux.queued(TELEMETRY), 2);
+
+        // Highest priority first, channels of equal priority in the order
+        // they were added, and the frames of a channel in order.
+        let mut out = Vec::new();
+        while mux.write_next(&mut out).unwrap() {}
+        let expected: Vec<u8> = [
+            (CONTROL, 5u32),
+            (LOG, 4),
+            (3, 3),
+            (TELEMETRY, 1),
+            (TELEMETRY, 2),
+        ]
+        .iter()
+        .flat_map(|(channel, msg)| frame(*channel, msg))
+        .collect();
+        assert_eq!(out, expected);
+        assert!(mux.is_empty());
+        assert_eq!(mux.pop(), None);
+    }
+
+    #[test]
+    fn mux_urgent_frames_overtake_queued_ones() {
+        let mut mux = Mux::new();
+        mux.add_channel(TELEMETRY, 0, 8).add_channel(CONTROL, 1, 8);
+        mux.push(TELEMETRY, &1u32).unwrap();
+        mux.push(TELEMETRY, &2u32).unwrap();
+        assert_eq!(mux.pop(), Some(frame(TELEMETRY, &1u32)));
+        mux.push(CONTROL, &Responses::Ack).unwrap();
+        assert_eq!(mux.pop(), Some(frame(CONTROL, &Responses::Ack)));
+        assert_eq!(mux.pop(), Some(frame(TELEMETRY, &2u32)));
+
+        // Adding a channel again changes its priority and keeps its frames.
+        mux.push(TELEMETRY, &3u32).unwrap();
+        mux.push(CONTROL, &Responses::Ack).unwrap();
+        mux.add_channel(TELEMETRY, 2, 8);
+        assert_eq!(mux.pop(), Some(frame(TELEMETRY, &3u32)));
+    }
+
+    #[test]
+    fn mux_queue_limits() {
+        let mut mux = Mux::new();
+        mux.add_channel(TELEMETRY, 0, 1).add_channel(CONTROL, 1, 2);
+        mux.push(TELEMETRY, &1u32).unwrap();
+        assert!(matches!(
+            mux.push(TELEMETRY, &2u32),
+            Err(MuxError::Full(TELEMETRY))
+        ));
+        // A full telemetry queue does not stop commands.
+        mux.push(CONTROL, &Responses::Ack).unwrap();
+        assert!(matches!(
+            mux.push(LOG, &"text"),
+            Err(MuxError::UnknownChannel(LOG))
+        ));
+        assert!(matches!(
+            mux.push(CONTROL, &&[1u8; MAX_FRAME][..]),
+            Err(MuxError::Encode(_))
+        ));
+        assert_eq!(mux.queued(TELEMETRY), 1);
+        assert_eq!(mux.queued(CONTROL), 1);
+    }
+
+    #[test]
+    fn demux_routes_split_frames() {
+        let mut demux = Demux::new();
+        let control = demux.subscribe::<Responses>(CONTROL, 8);
+        let telemetry = demux.subscribe::<u32>(TELEMETRY, 8);
+
+        let mut stream = frame(TELEMETRY, &7u32);
+        stream.extend(frame(CONTROL, &report(123)));
+        stream.extend(frame(TELEMETRY, &8u32));
+        stream.extend(frame(CONTROL, &Responses::Ack));
+
+        for split in 0..stream.len() {
+            let (first, second) = stream.split_at(split);
+            demux.feed(first);
+            demux.feed(second);
+            assert_eq!(
+                control.try_recv(),
+                Ok(Ok(report(123))),
+                "split at {}",
+                split
+            );
+            assert_eq!(control.try_recv(), Ok(Ok(Responses::Ack)));
+            assert_eq!(telemetry.try_recv(), Ok(Ok(7)));
+            assert_eq!(telemetry.try_recv(), Ok(Ok(8)));
+            assert!(control.try_recv().is_err() && telemetry.try_recv().is_err());
+        }
+
+        // One byte at a time.
+        for byte in &stream {
+            demux.feed(std::slice::from_ref(byte));
+        }
+        assert_eq!(control.try_iter().count(), 2);
+        assert_eq!(telemetry.try_iter().count(), 2);
+        assert_eq!(
+            demux.stats(),
+            DemuxStats {
+                delivered: 4 * (stream.len() + 1),
+                ..DemuxStats::default()
+            }
+        );
+    }
+
+    #[test]
+    fn demux_drops_only_for_a_slow_consumer() {
+        let mut demux = Demux::new();
+        let control = demux.subscribe::<Responses>(CONTROL, 8);
+        let telemetry = demux.subscribe::<u32>(TELEMETRY, 1);
+        for x in 0..3 {
+            demux.feed(&frame(TELEMETRY, &x));
+            demux.feed(&frame(CONTROL, &report(x)));
+        }
+        assert_eq!(telemetry.try_iter().collect::<Vec<_>>(), [Ok(0)]);
+        assert_eq!(control.try_iter().count(), 3);
+        assert_eq!(demux.stats().dropped, 2);
+        assert_eq!(demux.stats().delivered, 4);
+    }
+
+    #[test]
+    fn demux_counts_invalid_frames() {
+        let mut demux = Demux::new();
+        let control = demux.subscribe::<Responses>(CONTROL, 8);
+        let telemetry = demux.subscribe::<u32>(TELEMETRY, 8);
+
+        // Not valid COBS, empty after decoding, and too large, each followed
+        // by a valid frame.
+        demux.feed(&[0x05, 0x01, 0x00]);
+        demux.feed(&frame(CONTROL, &Responses::Ack));
+        demux.feed(&[0x01, 0x00]);
+        demux.feed(&frame(CONTROL, &Responses::Ack));
+        demux.feed(&[0x01; MAX_FRAME + 1]);
+        demux.feed(&[0x01, 0x01, 0x00]);
+        demux.feed(&frame(CONTROL, &Responses::Ack));
+        // Empty frames between the delimiters are skipped.
+        demux.feed(&[0x00, 0x00]);
+        assert_eq!(control.try_iter().count(), 3);
+        assert_eq!(demux.stats().corrupted, 3);
+
+        // A frame that does not decode is passed on as error.
+        demux.feed(&frame(CONTROL, &[0x05u8]));
+        assert!(matches!(
+            control.try_recv(),
+            Ok(Err(DecodeError::UnknownVariant { .. }))
+        ));
+
+        // Without consumer, the frames of a channel are unknown.
+        demux.feed(&frame(LOG, &"text"));
+        drop(telemetry);
+        demux.feed(&frame(TELEMETRY, &1u32));
+        demux.feed(&frame(TELEMETRY, &2u32));
+        assert_eq!(demux.stats().unknown, 3);
+        assert_eq!(demux.stats().delivered, 4);
+    }
+
+    #[test]
+    fn demux_runs_until_consumers_are_gone() {
+        let mut demux = Demux::new();
+        let control = demux.subscribe::<Responses>(CONTROL, 8);
+        let mut port: &[u8] = &frame(CONTROL, &Responses::Ack);
+        demux.run(&mut port).unwrap();
+        assert_eq!(control.try_recv(), Ok(Ok(Responses::Ack)));
+
+        // Read timeouts are ignored, other errors are returned.
+        demux
+            .run(&mut Errors(vec![
+                ErrorKind::TimedOut,
+                ErrorKind::WouldBlock,
+                ErrorKind::Interrupted,
+            ]))
+            .unwrap();
+        let err = demux
+            .run(&mut Errors(vec![
+                ErrorKind::TimedOut,
+                ErrorKind::BrokenPipe,
+            ]))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+
+        // Without consumers, the port is not read again.
+        drop(control);
+        let frame = frame(CONTROL, &Responses::Ack);
+        let mut port = (&frame[..]).chain(Errors(vec![ErrorKind::Other]));
+        demux.run(&mut port).unwrap();
+        assert_eq!(demux.stats().unknown, 1);
+    }
+}
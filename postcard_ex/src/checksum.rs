@@ -0,0 +1,196 @@
+//! CRC protected messages.
+//!
+//! Noise on the line can flip bits such that the data still decodes, e.g., to
+//! a wrong `Position.x`. With a checksum appended to the encoded message, such
+//! errors are detected before decoding and reported as `CrcError::Mismatch`.
+//!
+//! The checksum is stored little endian after the postcard data, i.e., within
+//! the COBS frame if the message is framed.
+
+use crc::{Crc, CRC_16_IBM_SDLC, CRC_32_ISO_HDLC};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::decode::{decode, DecodeError};
+
+const CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_SDLC);
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Checksum appended to a message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Checksum {
+    /// CRC-16/IBM-SDLC (X.25), 2 bytes.
+    Crc16,
+    /// CRC-32/ISO-HDLC (Ethernet, zip), 4 bytes.
+    Crc32,
+}
+
+impl Checksum {
+    /// Length of the checksum in bytes.
+    pub const fn size(self) -> usize {
+        match self {
+            Checksum::Crc16 => 2,
+            Checksum::Crc32 => 4,
+        }
+    }
+
+    /// Checksum of `data`, widened to `u32` for CRC-16.
+    pub fn compute(self, data: &[u8]) -> u32 {
+        match self {
+            Checksum::Crc16 => CRC16.checksum(data).into(),
+            Checksum::Crc32 => CRC32.checksum(data),
+        }
+    }
+}
+
+/// Why a CRC protected message was rejected.
+///
+/// A message that is shorter than the checksum is reported as truncated.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CrcError {
+    /// The data was modified on the way.
+    Mismatch { received: u32, computed: u32 },
+    /// The checksum is fine, but the data does not decode.
+    Decode(DecodeError),
+}
+
+/// Encode `msg` into `buf` and append the checksum.
+pub fn encode<'a, T: Serialize>(
+    msg: &T,
+    checksum: Checksum,
+    buf: &'a mut [u8],
+) -> postcard::Result<&'a mut [u8]> {
+    let len = postcard::to_slice(msg, buf)?.len();
+    let crc = checksum.compute(&buf[..len]).to_le_bytes();
+    let end = len + checksum.size();
+    buf.get_mut(len..end)
+        .ok_or(postcard::Error::SerializeBufferFull)?
+        .copy_from_slice(&crc[..checksum.size()]);
+    Ok(&mut buf[..end])
+}
+
+/// Verify the checksum at the end of `bytes` and decode the data before it.
+pub fn decode_checked<T: DeserializeOwned>(
+    bytes: &[u8],
+    checksum: Checksum,
+) -> Result<T, CrcError> {
+    let data = verify(bytes, checksum)?;
+    decode(data).map_err(CrcError::Decode)
+}
+
+/// Verify the checksum at the end of `bytes`, return the data before it.
+pub fn verify(bytes: &[u8], checksum: Checksum) -> Result<&[u8], CrcError> {
+    let split = bytes
+        .len()
+        .checked_sub(checksum.size())
+        .ok_or(CrcError::Decode(DecodeError::Truncated {
+            offset: bytes.len(),
+        }))?;
+    let (data, crc) = bytes.split_at(split);
+    let mut received = [0u8; 4];
+    received[..crc.len()].copy_from_slice(crc);
+    let received = u32::from_le_bytes(received);
+    let computed = checksum.compute(data);
+    match received == computed {
+        true => Ok(data),
+        false => Err(CrcError::Mismatch { received, computed }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{Commands, Position, COMMAND_MAX_SIZE};
+    use crate::stream::{max_frame_size, FrameDecoder};
+
+    fn set_position() -> Commands {
+        Commands::SetPosition(Position { x: 123, y: 456 })
+    }
+
+    #[test]
+    fn check_values() {
+        // The check values of the CRC catalogue.
+        assert_eq!(Checksum::Crc16.compute(b"123456789"), 0x906e);
+        assert_eq!(Checksum::Crc32.compute(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn round_trip() {
+        for checksum in [Checksum::Crc16, Checksum::Crc32] {
+            let mut buf = [0u8; 16];
+            let bytes = encode(&set_position(), checksum, &mut buf).unwrap();
+            assert_eq!(bytes.len(), 4 + checksum.size());
+            assert_eq!(decode_checked(bytes, checksum), Ok(set_position()));
+        }
+    }
+
+    #[test]
+    fn bit_flip_is_detected() {
+        for checksum in [Checksum::Crc16, Checksum::Crc32] {
+            let mut buf = [0u8; 16];
+            let bytes = encode(&set_position(), checksum, &mut buf).unwrap();
+            // Still a valid `SetPosition`, with a different `x`.
+            bytes[1] ^= 0x01;
+            assert!(matches!(
+                decode_checked::<Commands>(bytes, checksum),
+                Err(CrcError::Mismatch { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn too_short_for_the_checksum() {
+        assert_eq!(
+            verify(&[0x01], Checksum::Crc16),
+            Err(CrcError::Decode(DecodeError::Truncated { offset: 1 }))
+        );
+    }
+
+    #[test]
+    fn buffer_too_small_for_the_checksum() {
+        let mut buf = [0u8; 5];
+        assert!(encode(&set_position(), Checksum::Crc16, &mut buf).is_err());
+    }
+
+    #[test]
+    fn frame_decoder_with_checksum() {
+        let checksum = Checksum::Crc32;
+        let mut buf = [0u8; 16];
+        let bytes = encode(&set_position(), checksum, &mut buf).unwrap();
+        let mut frame = [0u8; 32];
+        let len = cobs::encode(bytes, &mut frame);
+        let mut wire = frame[..len].to_vec();
+        wire.push(0x00);
+
+        const N: usize = max_frame_size(COMMAND_MAX_SIZE + 4);
+        let mut decoder = FrameDecoder::<N>::with_checksum(checksum);
+        let frames: std::vec::Vec<_> = decoder.feed(&wire).collect();
+        assert_eq!(frames, [Ok(set_position())]);
+
+        // Raw frames are verified, and the checksum is removed.
+        let mut raw = std::vec::Vec::new();
+        decoder.feed_raw(&wire, |frame| raw.push(frame.map(<[u8]>::to_vec)));
+        assert_eq!(raw, [Ok(bytes[..bytes.len() - 4].to_vec())]);
+        let last = wire.len() - 2;
+        wire[last] ^= 0x80;
+        let mut raw = std::vec::Vec::new();
+        decoder.feed_raw(&wire, |frame| raw.push(frame.map(<[u8]>::to_vec)));
+        assert!(matches!(
+            raw[..],
+            [Err(crate::stream::FrameError::Checksum { .. })]
+        ));
+
+        // Without a checksum, the CRC bytes are left over.
+        let mut decoder = FrameDecoder::<N>::new();
+        let frames: std::vec::Vec<_> = decoder.feed(&wire).collect();
+        assert!(matches!(
+            frames[..],
+            [Err(crate::stream::FrameError::Decode(
+                DecodeError::TrailingBytes {
+                    offset: 4,
+                    count: 4
+                }
+            ))]
+        ));
+    }
+}
@@ -1,15 +1,179 @@
+use heapless::{String, Vec};
+use postcard::experimental::max_size::MaxSize;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+/// Maximum length of a label in bytes.
+pub const LABEL_LEN: usize = 32;
+
+/// Maximum number of bytes of an upload chunk.
+pub const CHUNK_LEN: usize = 64;
+
+/// Maximum size of an encoded command, e.g., for the receive buffer of the device.
+pub const COMMAND_MAX_SIZE: usize = Commands::POSTCARD_MAX_SIZE;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, MaxSize)]
+#[cfg_attr(feature = "schema", derive(postcard_schema::Schema))]
 pub enum Commands {
     SetPosition(Position),
     QueryPosition,
     SetTime(u64),
-    Unknown, // If something fails or user error, the Unknown variant will be used.
+    /// Name shown on the display of the device.
+    SetLabel(String<LABEL_LEN>),
+    /// Part of a file, e.g., a firmware image, starting at byte `offset`.
+    UploadChunk {
+        offset: u32,
+        #[serde(with = "bytes")]
+        data: Vec<u8, CHUNK_LEN>,
+    },
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+/// Replies of the device, one per command.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, MaxSize)]
+#[cfg_attr(feature = "schema", derive(postcard_schema::Schema))]
+pub enum Responses {
+    /// Reply to `QueryPosition`.
+    PositionReport(Position),
+    /// The command was executed.
+    Ack,
+    /// The command was rejected, e.g., with the code of a `DecodeError`.
+    Nack(u8),
+    /// Current time of the device.
+    TimeReport(u64),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, MaxSize)]
+#[cfg_attr(feature = "schema", derive(postcard_schema::Schema))]
 pub struct Position {
     pub x: u32,
     pub y: u32,
 }
+
+/// A command or response together with its sequence number.
+///
+/// The device replies with the sequence number of the command, such that the
+/// host can match replies to its requests. The sequence number comes first, so
+/// it can still be read if the payload is invalid.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, MaxSize)]
+pub struct Envelope<T> {
+    pub seq: u16,
+    pub payload: T,
+}
+
+/// Byte buffers as postcard bytes.
+///
+/// The wire format is the same as for a sequence of `u8`, but the length is
+/// checked before the data is read, such that data that is too long is
+/// reported as `DecodeError::TooLong`. Human readable formats, e.g., RON in
+/// the command line tool, keep the sequence of numbers.
+mod bytes {
+    use core::fmt;
+
+    use heapless::Vec;
+    use serde::de::{Error, Visitor};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer, const N: usize>(
+        data: &Vec<u8, N>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match serializer.is_human_readable() {
+            true => data.serialize(serializer),
+            false => serializer.serialize_bytes(data),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<Vec<u8, N>, D::Error> {
+        match deserializer.is_human_readable() {
+            true => Vec::deserialize(deserializer),
+            false => deserializer.deserialize_bytes(BytesVisitor::<N>),
+        }
+    }
+
+    struct BytesVisitor<const N: usize>;
+
+    impl<const N: usize> Visitor<'_> for BytesVisitor<N> {
+        type Value = Vec<u8, N>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "at most {} bytes", N)
+        }
+
+        fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Vec::from_slice(v).map_err(|_| E::invalid_length(v.len(), &self))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::{self, Checksum};
+    use crate::stream::{max_frame_size, FrameDecoder, DELIMITER, MAX_FRAME};
+
+    const FRAME: usize = max_frame_size(Envelope::<Commands>::POSTCARD_MAX_SIZE + 4);
+
+    fn largest_label() -> Commands {
+        // Multi-byte characters count with their bytes.
+        let mut label = String::new();
+        label.push_str(&"ä".repeat(LABEL_LEN / 2)).unwrap();
+        Commands::SetLabel(label)
+    }
+
+    fn largest_chunk() -> Commands {
+        Commands::UploadChunk {
+            offset: u32::MAX,
+            data: Vec::from_slice(&[0xff; CHUNK_LEN]).unwrap(),
+        }
+    }
+
+    /// COBS frame of `msg` with a checksum, including the delimiter.
+    fn frame<T: Serialize>(msg: &T, crc: Checksum) -> std::vec::Vec<u8> {
+        let mut buf = [0u8; MAX_FRAME];
+        let bytes = checksum::encode(msg, crc, &mut buf).unwrap();
+        let mut frame = std::vec![0u8; cobs::max_encoding_length(bytes.len())];
+        let len = cobs::encode(bytes, &mut frame);
+        frame.truncate(len);
+        frame.push(DELIMITER);
+        frame
+    }
+
+    #[test]
+    fn largest_commands_fit_into_a_frame() {
+        let mut buf = [0u8; MAX_FRAME];
+        assert_eq!(
+            postcard::to_slice(&largest_label(), &mut buf)
+                .unwrap()
+                .len(),
+            1 + 1 + LABEL_LEN
+        );
+        // The longest command, `MaxSize` is not too pessimistic.
+        assert_eq!(
+            postcard::to_slice(&largest_chunk(), &mut buf)
+                .unwrap()
+                .len(),
+            COMMAND_MAX_SIZE
+        );
+
+        for cmd in [largest_label(), largest_chunk()] {
+            for crc in [Checksum::Crc16, Checksum::Crc32] {
+                let max = max_frame_size(COMMAND_MAX_SIZE + crc.size());
+                assert!(frame(&cmd, crc).len() <= max);
+            }
+
+            let msg = Envelope {
+                seq: u16::MAX,
+                payload: cmd,
+            };
+            for crc in [Checksum::Crc16, Checksum::Crc32] {
+                let wire = frame(&msg, crc);
+                assert!(wire.len() <= FRAME, "{} > {} bytes", wire.len(), FRAME);
+
+                let mut decoder = FrameDecoder::<FRAME, Envelope<Commands>>::with_checksum(crc);
+                let frames: std::vec::Vec<_> = decoder.feed(&wire).collect();
+                assert_eq!(frames, [Ok(msg.clone())]);
+            }
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! Decode wire data with errors that tell why it was rejected.
+//!
+//! `postcard::from_bytes` only reports what went wrong, not where. Here, the
+//! input is read through a flavor that keeps track of the position, such that
+//! every `DecodeError` carries the byte offset of the offending value.
+
+use core::cell::Cell;
+use core::fmt;
+
+use postcard::de_flavors::Flavor;
+use postcard::{Deserializer, Error};
+use serde::de::DeserializeOwned;
+
+/// Why a command could not be decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The enum discriminant at `offset` does not name a known variant.
+    UnknownVariant { discriminant: u32, offset: usize },
+    /// The data ended within the value starting at `offset`, or right before
+    /// it. For strings and byte buffers, `offset` is where the length starts.
+    Truncated { offset: usize },
+    /// The value was decoded, but `count` bytes starting at `offset` are left.
+    TrailingBytes { offset: usize, count: usize },
+    /// The varint starting at `offset` does not fit into its integer type.
+    VarintOverflow { offset: usize },
+    /// Any other invalid value, e.g., a bool that is neither 0 nor 1.
+    Invalid { offset: usize },
+    /// The string or byte buffer with the length at `offset` is longer than
+    /// its type allows.
+    TooLong { offset: usize },
+}
+
+impl DecodeError {
+    /// Byte offset in the input at which decoding failed.
+    pub const fn offset(&self) -> usize {
+        match *self {
+            DecodeError::UnknownVariant { offset, .. }
+            | DecodeError::Truncated { offset }
+            | DecodeError::TrailingBytes { offset, .. }
+            | DecodeError::VarintOverflow { offset }
+            | DecodeError::Invalid { offset }
+            | DecodeError::TooLong { offset } => offset,
+        }
+    }
+
+    /// The same error for data that starts `by` bytes later in the input.
+    pub const fn shift(self, by: usize) -> Self {
+        match self {
+            DecodeError::UnknownVariant {
+                discriminant,
+                offset,
+            } => DecodeError::UnknownVariant {
+                discriminant,
+                offset: offset + by,
+            },
+            DecodeError::Truncated { offset } => DecodeError::Truncated {
+                offset: offset + by,
+            },
+            DecodeError::TrailingBytes { offset, count } => DecodeError::TrailingBytes {
+                offset: offset + by,
+                count,
+            },
+            DecodeError::VarintOverflow { offset } => DecodeError::VarintOverflow {
+                offset: offset + by,
+            },
+            DecodeError::Invalid { offset } => DecodeError::Invalid {
+                offset: offset + by,
+            },
+            DecodeError::TooLong { offset } => DecodeError::TooLong {
+                offset: offset + by,
+            },
+        }
+    }
+
+    /// Numeric code of the error kind, e.g., to report it back to the host.
+    pub const fn code(&self) -> u8 {
+        match self {
+            DecodeError::UnknownVariant { .. } => 1,
+            DecodeError::Truncated { .. } => 2,
+            DecodeError::TrailingBytes { .. } => 3,
+            DecodeError::VarintOverflow { .. } => 4,
+            DecodeError::Invalid { .. } => 5,
+            DecodeError::TooLong { .. } => 6,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownVariant {
+                discriminant,
+                offset,
+            } => write!(f, "unknown variant {} at byte {}", discriminant, offset),
+            DecodeError::Truncated { offset } => {
+                write!(f, "data ends within the value at byte {}", offset)
+            }
+            DecodeError::TrailingBytes { offset, count } => {
+                write!(f, "{} trailing bytes at byte {}", count, offset)
+            }
+            DecodeError::VarintOverflow { offset } => {
+                write!(f, "varint overflow at byte {}", offset)
+            }
+            DecodeError::Invalid { offset } => write!(f, "invalid value at byte {}", offset),
+            DecodeError::TooLong { offset } => write!(f, "value too long at byte {}", offset),
+        }
+    }
+}
+
+/// Decode a value that must use all of `bytes`.
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let (value, rest) = decode_partial(bytes)?;
+    match rest.is_empty() {
+        true => Ok(value),
+        false => Err(DecodeError::TrailingBytes {
+            offset: bytes.len() - rest.len(),
+            count: rest.len(),
+        }),
+    }
+}
+
+/// Decode a value from the start of `bytes`, return it with the unused rest.
+pub fn decode_partial<T: DeserializeOwned>(bytes: &[u8]) -> Result<(T, &[u8]), DecodeError> {
+    let pos = Cell::new(0);
+    let start = Cell::new(0);
+    let taken = Cell::new(None);
+    let flavor = Tracking {
+        input: bytes,
+        pos: &pos,
+        start: &start,
+        taken: &taken,
+        in_varint: false,
+    };
+    let mut deserializer = Deserializer::from_flavor(flavor);
+    let result = T::deserialize(&mut deserializer).and_then(|value| {
+        deserializer.finalize()?;
+        Ok(value)
+    });
+
+    let start = start.get();
+    match result {
+        Ok(value) => Ok((value, &bytes[pos.get()..])),
+        Err(Error::DeserializeUnexpectedEnd) => Err(DecodeError::Truncated { offset: start }),
+        Err(Error::DeserializeBadVarint) => Err(DecodeError::VarintOverflow { offset: start }),
+        // Bounded strings and byte buffers reject their data right after it
+        // was taken, with a custom error as well.
+        Err(Error::SerdeDeCustom) if taken.get().is_some() => Err(DecodeError::TooLong {
+            offset: taken.get().unwrap_or(start),
+        }),
+        // Serde reports an unknown variant index with a custom error, right
+        // after the discriminant was read.
+        Err(Error::SerdeDeCustom) | Err(Error::DeserializeBadEnum) => {
+            Err(match read_varint(&bytes[start..]) {
+                Some(discriminant) => DecodeError::UnknownVariant {
+                    discriminant,
+                    offset: start,
+                },
+                None => DecodeError::Invalid { offset: start },
+            })
+        }
+        Err(_) => Err(DecodeError::Invalid { offset: start }),
+    }
+}
+
+/// Read a `u32` varint, as used for enum discriminants.
+fn read_varint(bytes: &[u8]) -> Option<u32> {
+    let mut value = 0u32;
+    for (it, &byte) in bytes.iter().take(5).enumerate() {
+        value |= u32::from(byte & 0x7f).checked_shl(7 * it as u32)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Slice flavor that records the current position and where the last value
+/// started. The position lives outside, as the deserializer owns the flavor.
+struct Tracking<'de> {
+    input: &'de [u8],
+    pos: &'de Cell<usize>,
+    start: &'de Cell<usize>,
+    /// Start of the length of the data if the last read took a slice, e.g.,
+    /// the bytes of a string.
+    taken: &'de Cell<Option<usize>>,
+    in_varint: bool,
+}
+
+impl<'de> Flavor<'de> for Tracking<'de> {
+    type Remainder = &'de [u8];
+    type Source = &'de [u8];
+
+    fn pop(&mut self) -> postcard::Result<u8> {
+        let pos = self.pos.get();
+        // Single bytes are read one by one, multiple bytes belong to the same
+        // varint as long as the continuation bit is set.
+        if !self.in_varint {
+            self.start.set(pos);
+        }
+        let byte = *self.input.get(pos).ok_or(Error::DeserializeUnexpectedEnd)?;
+        self.taken.set(None);
+        self.in_varint = byte & 0x80 != 0;
+        self.pos.set(pos + 1);
+        Ok(byte)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.input.len() - self.pos.get())
+    }
+
+    /// On failure, `start` stays at the length that was read before.
+    fn try_take_n(&mut self, ct: usize) -> postcard::Result<&'de [u8]> {
+        let pos = self.pos.get();
+        let bytes = pos
+            .checked_add(ct)
+            .and_then(|end| self.input.get(pos..end))
+            .ok_or(Error::DeserializeUnexpectedEnd)?;
+        self.taken.set(Some(self.start.get()));
+        self.start.set(pos);
+        self.in_varint = false;
+        self.pos.set(pos + ct);
+        Ok(bytes)
+    }
+
+    fn finalize(self) -> postcard::Result<&'de [u8]> {
+        Ok(&self.input[self.pos.get()..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{Commands, Position};
+
+    fn decode_cmd(bytes: &[u8]) -> Result<Commands, DecodeError> {
+        decode(bytes)
+    }
+
+    #[test]
+    fn valid_command() {
+        let cmd = Commands::SetPosition(Position { x: 123, y: 456 });
+        assert_eq!(decode_cmd(&[0x00, 0x7b, 0xc8, 0x03]), Ok(cmd));
+    }
+
+    #[test]
+    fn unknown_variant() {
+        assert_eq!(
+            decode_cmd(&[0x09]),
+            Err(DecodeError::UnknownVariant {
+                discriminant: 9,
+                offset: 0
+            })
+        );
+        assert_eq!(
+            decode_cmd(&[0x80, 0x01]),
+            Err(DecodeError::UnknownVariant {
+                discriminant: 128,
+                offset: 0
+            })
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(decode_cmd(&[]), Err(DecodeError::Truncated { offset: 0 }));
+        // `y` is missing.
+        assert_eq!(
+            decode_cmd(&[0x00, 0x7b]),
+            Err(DecodeError::Truncated { offset: 2 })
+        );
+        // `y` started at byte 2, its varint is not complete.
+        assert_eq!(
+            decode_cmd(&[0x00, 0x7b, 0xc8]),
+            Err(DecodeError::Truncated { offset: 2 })
+        );
+        // The label has a length of 5 at byte 1, but only one byte follows.
+        assert_eq!(
+            decode_cmd(&[0x03, 0x05, b'a']),
+            Err(DecodeError::Truncated { offset: 1 })
+        );
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        assert_eq!(
+            decode_cmd(&[0x01, 0xaa, 0xbb]),
+            Err(DecodeError::TrailingBytes {
+                offset: 1,
+                count: 2
+            })
+        );
+        let (cmd, rest) = decode_partial::<Commands>(&[0x01, 0xaa, 0xbb]).unwrap();
+        assert_eq!((cmd, rest), (Commands::QueryPosition, &[0xaa, 0xbb][..]));
+    }
+
+    #[test]
+    fn varint_overflow() {
+        // `x` is a `u32`, i.e., at most 5 bytes.
+        assert_eq!(
+            decode_cmd(&[0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, 0x00]),
+            Err(DecodeError::VarintOverflow { offset: 1 })
+        );
+        assert_eq!(
+            decode_cmd(&[0x00, 0x01, 0xff, 0xff, 0xff, 0xff, 0x7f]),
+            Err(DecodeError::VarintOverflow { offset: 2 })
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        // The label is not UTF-8.
+        assert_eq!(
+            decode_cmd(&[0x03, 0x02, 0xff, 0xfe]),
+            Err(DecodeError::Invalid { offset: 2 })
+        );
+    }
+
+    #[test]
+    fn too_long() {
+        let mut label = vec![0x03, 33];
+        label.extend([b'a'; 33]);
+        assert_eq!(decode_cmd(&label), Err(DecodeError::TooLong { offset: 1 }));
+
+        let mut chunk = vec![0x04, 0x00, 65];
+        chunk.extend([0; 65]);
+        assert_eq!(decode_cmd(&chunk), Err(DecodeError::TooLong { offset: 2 }));
+    }
+
+    #[test]
+    fn shift_and_code() {
+        let err = DecodeError::TrailingBytes {
+            offset: 1,
+            count: 2,
+        };
+        assert_eq!(err.shift(3).offset(), 4);
+        assert_eq!(err.shift(3).code(), err.code());
+    }
+}
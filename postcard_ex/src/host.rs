@@ -0,0 +1,219 @@
+//! Host side of the request/response protocol.
+//!
+//! `Requests` hands out sequence numbers for commands and keeps track of the
+//! outstanding ones until the device replies or they time out. Sending and
+//! receiving is up to the caller, such that any transport can be used.
+
+use std::time::{Duration, Instant};
+
+use crate::commands::{Commands, Envelope, Responses};
+
+/// Why a reply could not be matched to a request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReplyError {
+    /// No request with this sequence number is outstanding, e.g., because it
+    /// already timed out.
+    UnknownSeq(u16),
+}
+
+/// A request that has not been answered yet.
+#[derive(Debug)]
+struct Pending {
+    seq: u16,
+    command: Commands,
+    deadline: Instant,
+}
+
+/// Outstanding requests of the host.
+#[derive(Debug)]
+pub struct Requests {
+    next_seq: u16,
+    timeout: Duration,
+    pending: Vec<Pending>,
+}
+
+impl Requests {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            next_seq: 0,
+            timeout,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Register `command` as sent at `now` and wrap it for sending.
+    pub fn request(&mut self, command: Commands, now: Instant) -> Envelope<Commands> {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.pending.push(Pending {
+            seq,
+            command: command.clone(),
+            deadline: now + self.timeout,
+        });
+        Envelope {
+            seq,
+            payload: command,
+        }
+    }
+
+    /// Match a reply to its request, return the request with the response.
+    pub fn reply(
+        &mut self,
+        reply: Envelope<Responses>,
+    ) -> Result<(Commands, Responses), ReplyError> {
+        let idx = self
+            .pending
+            .iter()
+            .position(|p| p.seq == reply.seq)
+            .ok_or(ReplyError::UnknownSeq(reply.seq))?;
+        let pending = self.pending.remove(idx);
+        Ok((pending.command, reply.payload))
+    }
+
+    /// Remove and return the requests that were not answered by `now`.
+    pub fn expire(&mut self, now: Instant) -> Vec<Envelope<Commands>> {
+        let mut expired = Vec::new();
+        self.pending.retain(|p| {
+            if p.deadline > now {
+                return true;
+            }
+            expired.push(Envelope {
+                seq: p.seq,
+                payload: p.command.clone(),
+            });
+            false
+        });
+        expired
+    }
+
+    /// Time at which the next request times out, if any.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.iter().map(|p| p.deadline).min()
+    }
+
+    /// Number of outstanding requests.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Position;
+    use crate::decode::{decode, decode_partial};
+
+    fn set_time(time: u64) -> Commands {
+        Commands::SetTime(time)
+    }
+
+    fn reply(seq: u16, payload: Responses) -> Envelope<Responses> {
+        Envelope { seq, payload }
+    }
+
+    #[test]
+    fn replies_are_matched_by_seq() {
+        let now = Instant::now();
+        let mut requests = Requests::new(Duration::from_millis(100));
+        let first = requests.request(set_time(1), now);
+        let second = requests.request(Commands::QueryPosition, now);
+        assert_eq!((first.seq, second.seq), (0, 1));
+        assert_eq!(requests.len(), 2);
+
+        // Out of order.
+        let pos = Position { x: 1, y: 2 };
+        assert_eq!(
+            requests.reply(reply(1, Responses::PositionReport(pos.clone()))),
+            Ok((Commands::QueryPosition, Responses::PositionReport(pos)))
+        );
+        assert_eq!(
+            requests.reply(reply(0, Responses::TimeReport(1))),
+            Ok((set_time(1), Responses::TimeReport(1)))
+        );
+        assert!(requests.is_empty());
+    }
+
+    #[test]
+    fn unknown_and_duplicate_seq() {
+        let now = Instant::now();
+        let mut requests = Requests::new(Duration::from_millis(100));
+        requests.request(set_time(1), now);
+
+        assert_eq!(
+            requests.reply(reply(7, Responses::Ack)),
+            Err(ReplyError::UnknownSeq(7))
+        );
+        assert!(requests.reply(reply(0, Responses::Ack)).is_ok());
+        assert_eq!(
+            requests.reply(reply(0, Responses::Ack)),
+            Err(ReplyError::UnknownSeq(0))
+        );
+    }
+
+    #[test]
+    fn nack_for_corrupted_request() {
+        let now = Instant::now();
+        let mut requests = Requests::new(Duration::from_millis(100));
+        let envelope = requests.request(Commands::QueryPosition, now);
+        let mut buf = [0u8; 16];
+        let bytes = postcard::to_slice(&envelope, &mut buf).unwrap();
+        // The variant after the sequence number is garbled on the way.
+        bytes[1] = 0x42;
+
+        // The device reads the sequence number even if the command is invalid.
+        let err = decode::<Envelope<Commands>>(bytes).unwrap_err();
+        let (seq, _) = decode_partial::<u16>(bytes).unwrap();
+        assert_eq!(
+            requests.reply(reply(seq, Responses::Nack(err.code()))),
+            Ok((Commands::QueryPosition, Responses::Nack(1)))
+        );
+    }
+
+    #[test]
+    fn seq_wraps_around() {
+        let now = Instant::now();
+        let mut requests = Requests::new(Duration::from_millis(100));
+        requests.next_seq = u16::MAX;
+        assert_eq!(requests.request(set_time(1), now).seq, u16::MAX);
+        assert_eq!(requests.request(set_time(2), now).seq, 0);
+
+        assert_eq!(
+            requests.reply(reply(0, Responses::Ack)),
+            Ok((set_time(2), Responses::Ack))
+        );
+        assert_eq!(
+            requests.reply(reply(u16::MAX, Responses::Ack)),
+            Ok((set_time(1), Responses::Ack))
+        );
+    }
+
+    #[test]
+    fn requests_expire() {
+        let now = Instant::now();
+        let mut requests = Requests::new(Duration::from_millis(100));
+        requests.request(set_time(1), now);
+        requests.request(set_time(2), now + Duration::from_millis(50));
+        assert_eq!(
+            requests.next_deadline(),
+            Some(now + Duration::from_millis(100))
+        );
+
+        let expired = requests.expire(now + Duration::from_millis(100));
+        assert_eq!(
+            expired,
+            [Envelope {
+                seq: 0,
+                payload: set_time(1)
+            }]
+        );
+        assert_eq!(
+            requests.reply(reply(0, Responses::Ack)),
+            Err(ReplyError::UnknownSeq(0))
+        );
+        assert_eq!(requests.len(), 1);
+    }
+}
@@ -0,0 +1,14 @@
+//! Wire commands shared between host and device.
+
+pub mod capture;
+pub mod channel;
+pub mod checksum;
+pub mod commands;
+pub mod decode;
+pub mod host;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod stream;
+#[cfg(feature = "transport")]
+pub mod transport;
+pub mod versioned;
@@ -1,52 +1,496 @@
-//! Test the postcard library
+//! Encode and decode `Commands` on the command line.
 //!
-//! Attention: Not compatible with heapless 0.8, use 0.7 instead.
+//! Commands are given as JSON or RON and printed as hex (or written as raw
+//! bytes). Hex dumps and binary capture files are decoded back into readable
+//! commands, e.g., to debug a serial capture. Capture files with timestamps
+//! (see `capture`) can be shown and replayed.
 
-use core::ops::Deref;
-use heapless::Vec;
+use std::fs::{self, File, OpenOptions};
+use std::io::BufReader;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-use postcard::{from_bytes, to_vec};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
-mod commands;
-use crate::commands::*;
+use postcard_ex::capture::{self, CaptureReader, Direction, Timing};
+use postcard_ex::checksum::{self, Checksum, CrcError};
+use postcard_ex::commands::Commands;
+use postcard_ex::decode::{decode_partial, DecodeError};
+use postcard_ex::stream::{FrameDecoder, MAX_FRAME};
 
-fn main() {
-    let position = Position { x: 123, y: 456 };
-    let send_position = Commands::SetPosition(position);
+#[derive(Parser)]
+#[command(about = "Encode and decode postcard wire commands")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encode a command, e.g., '{"SetPosition": {"x": 1, "y": 2}}' or 'SetTime(5)' with --ron.
+    Encode {
+        /// The command, read from stdin if not given.
+        command: Option<String>,
+        /// Write the raw bytes to stdout instead of hex.
+        #[arg(long)]
+        raw: bool,
+        #[command(flatten)]
+        wire: Wire,
+    },
+    /// Decode hex bytes, a hex dump file, or a binary capture file.
+    Decode {
+        /// Hex bytes, e.g., `00 7b c8 03` or `0x00,0x7b`. Read from stdin if
+        /// neither bytes nor a file are given.
+        hex: Vec<String>,
+        /// Read the data from this file, binary unless --hex-file is given.
+        #[arg(long, conflicts_with = "hex")]
+        file: Option<PathBuf>,
+        /// The file given with --file is a hex dump.
+        #[arg(long, requires = "file")]
+        hex_file: bool,
+        #[command(flatten)]
+        wire: Wire,
+    },
+    /// Show the frames of a capture file with timestamps.
+    Show {
+        capture: PathBuf,
+        #[command(flatten)]
+        wire: Wire,
+    },
+    /// Send the frames of a capture file to a port or file.
+    Replay {
+        capture: PathBuf,
+        /// Serial port (configured beforehand, e.g., with stty) or file to write to.
+        #[arg(long)]
+        to: PathBuf,
+        /// Replay the frames received by the host instead of the sent ones.
+        #[arg(long)]
+        received: bool,
+        /// Factor for the time between frames, e.g., 0.5 for twice as fast.
+        #[arg(long, value_parser = parse_speed, conflicts_with = "immediate")]
+        scale: Option<f64>,
+        /// Send all frames without waiting.
+        #[arg(long)]
+        immediate: bool,
+    },
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(f) if f.is_finite() && f >= 0.0 => Ok(f),
+        _ => Err(format!("expected a non-negative factor, got `{}`", s)),
+    }
+}
+
+/// Options that define the wire format.
+#[derive(Args)]
+struct Wire {
+    /// Commands are given and printed as RON instead of JSON.
+    #[arg(long)]
+    ron: bool,
+    /// Commands are COBS framed and delimited by 0x00.
+    #[arg(long)]
+    cobs: bool,
+    /// Commands end with a checksum.
+    #[arg(long, value_enum)]
+    crc: Option<CrcArg>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CrcArg {
+    Crc16,
+    Crc32,
+}
+
+impl From<CrcArg> for Checksum {
+    fn from(crc: CrcArg) -> Self {
+        match crc {
+            CrcArg::Crc16 => Checksum::Crc16,
+            CrcArg::Crc32 => Checksum::Crc32,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Encode { command, raw, wire } => encode(command, raw, &wire),
+        Command::Decode {
+            hex,
+            file,
+            hex_file,
+            wire,
+        } => read_input(hex, file, hex_file).and_then(|data| decode(&data, &wire)),
+        Command::Show { capture, wire } => show(capture, &wire),
+        Command::Replay {
+            capture,
+            to,
+            received,
+            scale,
+            immediate,
+        } => {
+            let direction = match received {
+                true => Direction::Received,
+                false => Direction::Sent,
+            };
+            let timing = match (scale, immediate) {
+                (_, true) => Timing::Immediate,
+                (Some(factor), false) => Timing::Scaled(factor),
+                (None, false) => Timing::Original,
+            };
+            replay(capture, to, direction, timing)
+        }
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn encode(command: Option<String>, raw: bool, wire: &Wire) -> Result<bool, String> {
+    let text = match command {
+        Some(text) => text,
+        None => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|err| err.to_string())?;
+            text
+        }
+    };
+    let bytes = encode_command(&parse_command(&text, wire)?, wire)?;
+    if raw {
+        io::stdout()
+            .write_all(&bytes)
+            .map_err(|err| err.to_string())?;
+    } else {
+        println!("{}", to_hex(&bytes));
+    }
+    Ok(true)
+}
+
+fn parse_command(text: &str, wire: &Wire) -> Result<Commands, String> {
+    match wire.ron {
+        true => ron::from_str(text).map_err(|err| format!("invalid RON: {}", err)),
+        false => serde_json::from_str(text).map_err(|err| format!("invalid JSON: {}", err)),
+    }
+}
+
+/// Encode `cmd` in the wire format, with checksum and COBS frame if given.
+fn encode_command(cmd: &Commands, wire: &Wire) -> Result<Vec<u8>, String> {
+    let mut buf = [0u8; MAX_FRAME];
+    let msg = match wire.crc {
+        Some(crc) => checksum::encode(cmd, crc.into(), &mut buf),
+        None => postcard::to_slice(cmd, &mut buf),
+    }
+    .map_err(|err| err.to_string())?;
+
+    let mut bytes = msg.to_vec();
+    if wire.cobs {
+        let mut frame = vec![0u8; cobs::max_encoding_length(bytes.len())];
+        let len = cobs::encode(&bytes, &mut frame);
+        frame.truncate(len);
+        frame.push(postcard_ex::stream::DELIMITER);
+        bytes = frame;
+    }
+    Ok(bytes)
+}
+
+/// Decode all commands in `data`, returns whether all of them were valid.
+fn decode(data: &[u8], wire: &Wire) -> Result<bool, String> {
+    let mut valid = true;
+    if wire.cobs {
+        let mut decoder: FrameDecoder<MAX_FRAME> = match wire.crc {
+            Some(crc) => FrameDecoder::with_checksum(crc.into()),
+            None => FrameDecoder::new(),
+        };
+        for (it, frame) in decoder.feed(data).enumerate() {
+            match frame {
+                Ok(cmd) => println!("{}", format_command(&cmd, wire)?),
+                Err(err) => {
+                    println!("frame {}: {:?}", it, err);
+                    valid = false;
+                }
+            }
+        }
+        if decoder.pending() > 0 {
+            println!(
+                "{} bytes of an incomplete frame at the end",
+                decoder.pending()
+            );
+            valid = false;
+        }
+        return Ok(valid);
+    }
 
-    let send_position_pc: Vec<u8, 32> = to_vec(&send_position).unwrap();
-    decode(send_position_pc.deref());
+    // Without framing, commands follow each other directly. After an error,
+    // the start of the next command is unknown and decoding stops.
+    let mut offset = 0;
+    while offset < data.len() {
+        match decode_next(&data[offset..], wire.crc.map(Checksum::from)) {
+            Ok((cmd, len)) => {
+                println!("{}", format_command(&cmd, wire)?);
+                offset += len;
+            }
+            Err(err) => {
+                println!("{}", describe(err, offset));
+                valid = false;
+                break;
+            }
+        }
+    }
+    Ok(valid)
+}
 
-    let query_position = Commands::QueryPosition;
+fn open_capture(path: PathBuf) -> Result<CaptureReader<BufReader<File>>, String> {
+    let file = File::open(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    CaptureReader::new(BufReader::new(file)).map_err(|err| format!("{}: {}", path.display(), err))
+}
 
-    let query_position_pc: Vec<u8, 32> = to_vec(&query_position).unwrap();
-    decode(query_position_pc.deref());
+/// Print every frame of a capture, commands sent by the host are decoded.
+fn show(path: PathBuf, wire: &Wire) -> Result<bool, String> {
+    for record in open_capture(path)? {
+        let record = record.map_err(|err| err.to_string())?;
+        let arrow = match record.direction {
+            Direction::Sent => "->",
+            Direction::Received => "<-",
+        };
+        print!(
+            "{:>12.6} {} {}",
+            record.time.as_secs_f64(),
+            arrow,
+            to_hex(&record.frame)
+        );
+        if record.direction == Direction::Sent {
+            print!("  ");
+            decode(&record.frame, wire)?;
+        } else {
+            println!();
+        }
+    }
+    Ok(true)
+}
 
-    let send_time = Commands::SetTime(1234567890);
-    let send_time_pc: Vec<u8, 32> = to_vec(&send_time).unwrap();
-    decode(send_time_pc.deref());
+fn replay(
+    path: PathBuf,
+    to: PathBuf,
+    direction: Direction,
+    timing: Timing,
+) -> Result<bool, String> {
+    let records = open_capture(path)?;
+    let mut sink = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&to)
+        .map_err(|err| format!("{}: {}", to.display(), err))?;
+    let count =
+        capture::replay(records, direction, timing, &mut sink).map_err(|err| err.to_string())?;
+    eprintln!("Replayed {} frames to {}", count, to.display());
+    Ok(true)
+}
 
-    // Test invalid data - should NOT panic!
-    decode(&[0x08, 0x00, 0x00, 0x00, 0x00]);
+/// Decode the command at the start of `data`, return it with its length.
+///
+/// Without framing, the checksum directly follows the data of each command.
+fn decode_next(data: &[u8], crc: Option<Checksum>) -> Result<(Commands, usize), CrcError> {
+    let (cmd, tail) = decode_partial::<Commands>(data).map_err(CrcError::Decode)?;
+    let Some(crc) = crc else {
+        return Ok((cmd, data.len() - tail.len()));
+    };
+    let len = data.len() - tail.len() + crc.size();
+    let data = data
+        .get(..len)
+        .ok_or(CrcError::Decode(DecodeError::Truncated {
+            offset: data.len(),
+        }))?;
+    checksum::decode_checked(data, crc).map(|cmd| (cmd, len))
 }
 
-fn decode(bytes: &[u8]) {
-    let cmd: Commands = from_bytes(bytes).unwrap_or(Commands::Unknown);
+/// Describe an error of the command that starts at `offset`.
+fn describe(err: CrcError, offset: usize) -> String {
+    match err {
+        CrcError::Decode(err) => err.shift(offset).to_string(),
+        CrcError::Mismatch { received, computed } => format!(
+            "checksum mismatch in command at byte {}: received {:#x}, computed {:#x}",
+            offset, received, computed
+        ),
+    }
+}
 
-    println!("Bytes{:?}", bytes);
+fn format_command(cmd: &Commands, wire: &Wire) -> Result<String, String> {
+    match wire.ron {
+        true => ron::to_string(cmd).map_err(|err| err.to_string()),
+        false => serde_json::to_string(cmd).map_err(|err| err.to_string()),
+    }
+}
 
-    match cmd {
-        Commands::SetPosition(pos) => {
-            println!("SetPosition: {:?}", pos);
+fn read_input(hex: Vec<String>, file: Option<PathBuf>, hex_file: bool) -> Result<Vec<u8>, String> {
+    let read_err = |err: io::Error| err.to_string();
+    match file {
+        Some(path) if hex_file => parse_hex(&fs::read_to_string(path).map_err(read_err)?),
+        Some(path) => fs::read(path).map_err(read_err),
+        None if !hex.is_empty() => parse_hex(&hex.join(" ")),
+        None => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text).map_err(read_err)?;
+            parse_hex(&text)
         }
-        Commands::SetTime(time) => {
-            println!("SetTime: {:?}", time);
+    }
+}
+
+/// Parse hex bytes separated by whitespace, commas, or colons, with or without
+/// `0x` prefixes. Without separators, every two digits are one byte.
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for token in text.split(|c: char| c.is_whitespace() || c == ',' || c == ':') {
+        let token = token
+            .strip_prefix("0x")
+            .or_else(|| token.strip_prefix("0X"))
+            .unwrap_or(token);
+        if let Some(c) = token.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(format!("invalid hex digit `{}` in `{}`", c, token));
         }
-        Commands::QueryPosition => {
-            println!("Query position");
+        if token.len() % 2 != 0 && token.len() > 1 {
+            return Err(format!("odd number of hex digits in `{}`", token));
         }
-        Commands::Unknown => {
-            println!("Unknown command");
+        let token = match token.len() {
+            1 => format!("0{}", token),
+            _ => token.to_string(),
+        };
+        for it in (0..token.len()).step_by(2) {
+            let byte = u8::from_str_radix(&token[it..it + 2], 16)
+                .map_err(|_| format!("invalid hex byte `{}`", &token[it..it + 2]))?;
+            bytes.push(byte);
         }
     }
+    Ok(bytes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use postcard_ex::commands::Position;
+
+    fn commands() -> Vec<Commands> {
+        vec![
+            Commands::SetPosition(Position { x: 123, y: 456 }),
+            Commands::QueryPosition,
+            Commands::SetTime(1234567890),
+            Commands::SetLabel("x".repeat(32).as_str().into()),
+            Commands::UploadChunk {
+                offset: 64,
+                data: heapless::Vec::from_slice(&[0; 64]).unwrap(),
+            },
+        ]
+    }
+
+    #[test]
+    fn parse_hex_separators() {
+        let bytes = vec![0x00, 0x7b, 0xc8, 0x03];
+        for text in [
+            "00 7b c8 03",
+            "00,7b,c8,03",
+            "00:7b:c8:03",
+            "0x00, 0x7B,\n0Xc8 0x3",
+            "007bc803",
+            "  0 7b  c8\t3\n",
+        ] {
+            assert_eq!(parse_hex(text), Ok(bytes.clone()), "{:?}", text);
+        }
+        assert_eq!(parse_hex(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn parse_hex_rejects_invalid_bytes() {
+        assert_eq!(
+            parse_hex("00 7bc"),
+            Err("odd number of hex digits in `7bc`".to_string())
+        );
+        assert_eq!(
+            parse_hex("00 7g"),
+            Err("invalid hex digit `g` in `7g`".to_string())
+        );
+        assert!(parse_hex("0x").is_ok_and(|bytes| bytes.is_empty()));
+        assert!(parse_hex("+1").is_err());
+        assert!(parse_hex("aéa").is_err());
+    }
+
+    #[test]
+    fn text_round_trip() {
+        for ron in [false, true] {
+            for cobs in [false, true] {
+                for crc in [None, Some(CrcArg::Crc16), Some(CrcArg::Crc32)] {
+                    let wire = Wire { ron, cobs, crc };
+                    for cmd in commands() {
+                        let text = format_command(&cmd, &wire).unwrap();
+                        assert_eq!(parse_command(&text, &wire), Ok(cmd.clone()), "{}", text);
+
+                        let bytes = encode_command(&cmd, &wire).unwrap();
+                        let decoded = match cobs {
+                            true => {
+                                let mut decoder: FrameDecoder<MAX_FRAME> = match crc {
+                                    Some(crc) => FrameDecoder::with_checksum(crc.into()),
+                                    None => FrameDecoder::new(),
+                                };
+                                let mut frames = decoder.feed(&bytes);
+                                let decoded = frames.next().unwrap().unwrap();
+                                assert!(frames.next().is_none());
+                                decoded
+                            }
+                            false => {
+                                let (decoded, len) =
+                                    decode_next(&bytes, crc.map(Checksum::from)).unwrap();
+                                assert_eq!(len, bytes.len());
+                                decoded
+                            }
+                        };
+                        assert_eq!(decoded, cmd);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn known_encodings() {
+        let json = Wire {
+            ron: false,
+            cobs: false,
+            crc: None,
+        };
+        let cmd = parse_command(r#"{"SetPosition": {"x": 123, "y": 456}}"#, &json).unwrap();
+        assert_eq!(to_hex(&encode_command(&cmd, &json).unwrap()), "00 7b c8 03");
+
+        let ron = Wire {
+            ron: true,
+            cobs: true,
+            crc: Some(CrcArg::Crc16),
+        };
+        let cmd = parse_command("SetTime(1234567890)", &ron).unwrap();
+        assert_eq!(
+            to_hex(&encode_command(&cmd, &ron).unwrap()),
+            "09 02 d2 85 d8 cc 04 bc 39 00"
+        );
+
+        assert!(
+            parse_command("SetTime(5)", &json).is_err_and(|err| err.starts_with("invalid JSON"))
+        );
+        assert!(parse_command(r#"{"SetTime": 5}"#, &ron)
+            .is_err_and(|err| err.starts_with("invalid RON")));
+    }
 }
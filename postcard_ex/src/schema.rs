@@ -0,0 +1,634 @@
+//! Schemas of the wire types for other languages.
+//!
+//! The types derive `postcard_schema::Schema`, from which we generate a JSON
+//! description and a header-only C encoder/decoder, e.g., for the Arduino
+//! sketches. Run `cargo run --features schema --bin gen_schema` after changing
+//! a wire type, such that the C side cannot drift from the Rust types.
+//!
+//! Only the types used on our wire are supported in C: integers, `f32`,
+//! bools, bounded strings and byte buffers, structs, and enums with unit,
+//! newtype, or struct variants.
+
+use std::fmt::Write;
+
+use postcard_schema::schema::{DataModelType, DataModelVariant, NamedType, NamedValue};
+use postcard_schema::Schema;
+use serde_json::{json, Value};
+
+use crate::commands::{Commands, Position, Responses, CHUNK_LEN, LABEL_LEN};
+
+/// Name and content of the files written by `gen_schema`: the JSON
+/// description and the C header of `Commands`, `Responses`, and `Position`.
+pub fn files() -> Result<[(&'static str, String); 2], String> {
+    let types: [&'static NamedType; 3] = [Commands::SCHEMA, Responses::SCHEMA, Position::SCHEMA];
+
+    let json = Value::Array(types.iter().map(|ty| json(ty)).collect());
+    let json = serde_json::to_string_pretty(&json).map_err(|e| e.to_string())?;
+    let bounds = [
+        ("Commands::SetLabel", LABEL_LEN),
+        ("Commands::UploadChunk::data", CHUNK_LEN),
+    ];
+    let header = c_header("POSTCARD_EX_H", &types, &bounds)?;
+    Ok([("postcard_ex.json", json), ("postcard_ex.h", header)])
+}
+
+/// JSON description of a type, including all types it contains.
+pub fn json(ty: &NamedType) -> Value {
+    let kind = |kind: &str| json!({ "name": ty.name, "type": kind });
+    match ty.ty {
+        DataModelType::Struct(fields) => json!({
+            "name": ty.name,
+            "type": "struct",
+            "fields": fields_json(fields),
+        }),
+        DataModelType::Enum(variants) => {
+            let variants: Vec<Value> = variants
+                .iter()
+                .enumerate()
+                .map(|(index, var)| {
+                    let mut value = json!({ "name": var.name, "index": index });
+                    value["type"] = match var.ty {
+                        DataModelVariant::UnitVariant => json!("unit"),
+                        DataModelVariant::NewtypeVariant(ty) => json(ty),
+                        DataModelVariant::TupleVariant(types) => {
+                            json!({ "tuple": types.iter().map(|ty| json(ty)).collect::<Vec<_>>() })
+                        }
+                        DataModelVariant::StructVariant(fields) => {
+                            json!({ "fields": fields_json(fields) })
+                        }
+                    };
+                    value
+                })
+                .collect();
+            json!({ "name": ty.name, "type": "enum", "variants": variants })
+        }
+        DataModelType::NewtypeStruct(inner) => {
+            json!({ "name": ty.name, "type": "newtype", "inner": json(inner) })
+        }
+        DataModelType::Option(inner) => {
+            json!({ "name": ty.name, "type": "option", "inner": json(inner) })
+        }
+        DataModelType::Seq(inner) => {
+            json!({ "name": ty.name, "type": "seq", "inner": json(inner) })
+        }
+        DataModelType::Tuple(types) | DataModelType::TupleStruct(types) => json!({
+            "name": ty.name,
+            "type": "tuple",
+            "items": types.iter().map(|ty| json(ty)).collect::<Vec<_>>(),
+        }),
+        DataModelType::Map { key, val } => json!({
+            "name": ty.name,
+            "type": "map",
+            "key": json(key),
+            "value": json(val),
+        }),
+        other => kind(primitive_name(other).unwrap_or("unknown")),
+    }
+}
+
+/// Description of a type in the format of `versioned::Message::SCHEMA`, e.g.,
+/// `Position{x:u32,y:u32}`, to check the hand written descriptions.
+///
+/// `bounds` are the capacities of strings and byte buffers, as for `c_header`.
+pub fn describe(ty: &NamedType, bounds: &[(&str, usize)]) -> Result<String, String> {
+    describe_at(ty, ty.name, bounds)
+}
+
+fn describe_at(ty: &NamedType, path: &str, bounds: &[(&str, usize)]) -> Result<String, String> {
+    let bound = || match bounds.iter().find(|(bounded, _)| *bounded == path) {
+        Some(&(_, max)) => Ok(max),
+        None => Err(format!("{path}: no capacity given for {}", ty.name)),
+    };
+    let fields = |path: &str, fields: &[&NamedValue]| -> Result<String, String> {
+        let fields = fields
+            .iter()
+            .map(|field| {
+                let path = format!("{path}::{}", field.name);
+                Ok(format!(
+                    "{}:{}",
+                    field.name,
+                    describe_at(field.ty, &path, bounds)?
+                ))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(fields.join(","))
+    };
+    Ok(match ty.ty {
+        DataModelType::Struct(values) => format!("{}{{{}}}", ty.name, fields(ty.name, values)?),
+        DataModelType::Enum(variants) => {
+            let variants = variants
+                .iter()
+                .map(|var| {
+                    let path = format!("{}::{}", ty.name, var.name);
+                    Ok(match var.ty {
+                        DataModelVariant::UnitVariant => var.name.to_string(),
+                        DataModelVariant::NewtypeVariant(inner) => {
+                            format!("{}({})", var.name, describe_at(inner, &path, bounds)?)
+                        }
+                        DataModelVariant::StructVariant(values) => {
+                            format!("{}{{{}}}", var.name, fields(&path, values)?)
+                        }
+                        DataModelVariant::TupleVariant(_) => {
+                            return Err(format!("{path}: tuple variants are not supported"))
+                        }
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            format!("{}{{{}}}", ty.name, variants.join(","))
+        }
+        DataModelType::String => format!("String<{}>", bound()?),
+        _ if is_bytes(ty) => format!("Vec<u8,{}>", bound()?),
+        other => match primitive_name(other) {
+            Some(name) => name.to_string(),
+            None => return Err(format!("{path}: {} cannot be described", ty.name)),
+        },
+    })
+}
+
+fn fields_json(fields: &[&NamedValue]) -> Vec<Value> {
+    fields
+        .iter()
+        .map(|field| json!({ "name": field.name, "type": json(field.ty) }))
+        .collect()
+}
+
+fn primitive_name(ty: &DataModelType) -> Option<&'static str> {
+    Some(match ty {
+        DataModelType::Bool => "bool",
+        DataModelType::I8 => "i8",
+        DataModelType::U8 => "u8",
+        DataModelType::I16 => "i16",
+        DataModelType::I32 => "i32",
+        DataModelType::I64 => "i64",
+        DataModelType::I128 => "i128",
+        DataModelType::U16 => "u16",
+        DataModelType::U32 => "u32",
+        DataModelType::U64 => "u64",
+        DataModelType::U128 => "u128",
+        DataModelType::Usize => "usize",
+        DataModelType::Isize => "isize",
+        DataModelType::F32 => "f32",
+        DataModelType::F64 => "f64",
+        DataModelType::Char => "char",
+        DataModelType::String => "string",
+        DataModelType::ByteArray => "bytes",
+        DataModelType::Unit => "unit",
+        DataModelType::UnitStruct => "unit_struct",
+        DataModelType::Schema => "schema",
+        _ => return None,
+    })
+}
+
+/// Integer types: C type, bytes of the varint, and whether it is zigzag encoded.
+fn c_int(ty: &DataModelType) -> Option<(&'static str, u8, bool)> {
+    Some(match ty {
+        DataModelType::U16 => ("uint16_t", 3, false),
+        DataModelType::U32 => ("uint32_t", 5, false),
+        DataModelType::U64 => ("uint64_t", 10, false),
+        DataModelType::I16 => ("int16_t", 3, true),
+        DataModelType::I32 => ("int32_t", 5, true),
+        DataModelType::I64 => ("int64_t", 10, true),
+        _ => return None,
+    })
+}
+
+/// Header-only C encoder and decoder for `types` and all types they contain.
+///
+/// For every struct or enum `Name`, the header contains a C type `Name` and
+/// the functions `name_encode` and `name_decode`.
+///
+/// The schema does not contain the capacity of strings and byte buffers, it
+/// is given in `bounds` by the path of the value, e.g.,
+/// `("Commands::SetLabel", 32)` for a newtype variant, or
+/// `("Commands::UploadChunk::data", 64)` for a field.
+pub fn c_header(
+    guard: &str,
+    types: &[&'static NamedType],
+    bounds: &[(&str, usize)],
+) -> Result<String, String> {
+    let mut gen = CGen {
+        bounds,
+        done: Vec::new(),
+        out: String::new(),
+    };
+    for ty in types {
+        gen.named(ty)?;
+    }
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "/* Generated by gen_schema from the postcard_ex wire types, do not edit. */"
+    )
+    .unwrap();
+    writeln!(out, "#ifndef {guard}\n#define {guard}\n").unwrap();
+    out.push_str(C_RUNTIME);
+    out.push_str(&gen.out);
+    writeln!(out, "#endif /* {guard} */").unwrap();
+    Ok(out)
+}
+
+struct CGen<'a> {
+    bounds: &'a [(&'a str, usize)],
+    done: Vec<&'static str>,
+    out: String,
+}
+
+impl CGen<'_> {
+    /// Emit the type and functions of a struct or enum, after the types it uses.
+    fn named(&mut self, ty: &'static NamedType) -> Result<(), String> {
+        if self.done.contains(&ty.name) {
+            return Ok(());
+        }
+        match ty.ty {
+            DataModelType::Struct(fields) => {
+                for field in fields.iter() {
+                    self.dependency(field.ty)?;
+                }
+                self.emit_struct(ty.name, fields)?;
+            }
+            DataModelType::Enum(variants) => {
+                for var in variants.iter() {
+                    match var.ty {
+                        DataModelVariant::UnitVariant => {}
+                        DataModelVariant::NewtypeVariant(inner) => self.dependency(inner)?,
+                        DataModelVariant::StructVariant(fields) => {
+                            for field in fields.iter() {
+                                self.dependency(field.ty)?;
+                            }
+                        }
+                        DataModelVariant::TupleVariant(_) => {
+                            return Err(format!(
+                                "{}::{}: tuple variants are not supported",
+                                ty.name, var.name
+                            ))
+                        }
+                    }
+                }
+                self.emit_enum(ty.name, variants)?;
+            }
+            _ => {
+                return Err(format!(
+                    "{}: only structs and enums can be generated",
+                    ty.name
+                ))
+            }
+        }
+        self.done.push(ty.name);
+        Ok(())
+    }
+
+    fn dependency(&mut self, ty: &'static NamedType) -> Result<(), String> {
+        match ty.ty {
+            DataModelType::Struct(_) | DataModelType::Enum(_) => self.named(ty),
+            _ => Ok(()),
+        }
+    }
+
+    fn emit_struct(&mut self, name: &str, fields: &[&NamedValue]) -> Result<(), String> {
+        let snake = snake_case(name);
+        let mut members = String::new();
+        let mut put = String::new();
+        let mut get = String::new();
+        for field in fields {
+            let max = self.bound(field.ty, &format!("{name}::{}", field.name))?;
+            let expr = format!("v->{}", field.name);
+            writeln!(members, "    {} {};", c_type(field.ty, max)?, field.name).unwrap();
+            writeln!(put, "    {}", put_stmt(field.ty, &expr, max)?).unwrap();
+            writeln!(get, "    {}", get_stmt(field.ty, &expr, max)?).unwrap();
+        }
+        writeln!(self.out, "typedef struct {{\n{members}}} {name};\n").unwrap();
+        self.emit_functions(name, &snake, &put, &get);
+        Ok(())
+    }
+
+    fn emit_enum(
+        &mut self,
+        name: &str,
+        variants: &[&postcard_schema::schema::NamedVariant],
+    ) -> Result<(), String> {
+        let snake = snake_case(name);
+        let upper = snake.to_uppercase();
+        let mut tags = String::new();
+        let mut members = String::new();
+        let mut put = String::new();
+        let mut get = String::new();
+        for (index, var) in variants.iter().enumerate() {
+            let tag = format!("{}_{}", upper, snake_case(var.name).to_uppercase());
+            writeln!(tags, "    {tag} = {index},").unwrap();
+            let member = snake_case(var.name);
+            let (mut put_var, mut get_var) = (String::new(), String::new());
+            match var.ty {
+                DataModelVariant::UnitVariant => {}
+                DataModelVariant::NewtypeVariant(ty) => {
+                    let max = self.bound(ty, &format!("{name}::{}", var.name))?;
+                    writeln!(members, "        {} {member};", c_type(ty, max)?).unwrap();
+                    let expr = format!("v->value.{member}");
+                    writeln!(put_var, "        {}", put_stmt(ty, &expr, max)?).unwrap();
+                    writeln!(get_var, "        {}", get_stmt(ty, &expr, max)?).unwrap();
+                }
+                DataModelVariant::StructVariant(fields) => {
+                    writeln!(members, "        struct {{").unwrap();
+                    for field in fields.iter() {
+                        let path = format!("{name}::{}::{}", var.name, field.name);
+                        let max = self.bound(field.ty, &path)?;
+                        writeln!(
+                            members,
+                            "            {} {};",
+                            c_type(field.ty, max)?,
+                            field.name
+                        )
+                        .unwrap();
+                        let expr = format!("v->value.{member}.{}", field.name);
+                        writeln!(put_var, "        {}", put_stmt(field.ty, &expr, max)?).unwrap();
+                        writeln!(get_var, "        {}", get_stmt(field.ty, &expr, max)?).unwrap();
+                    }
+                    writeln!(members, "        }} {member};").unwrap();
+                }
+                DataModelVariant::TupleVariant(_) => unreachable!("rejected in `named`"),
+            }
+            writeln!(put, "    case {tag}:\n{put_var}        break;").unwrap();
+            writeln!(get, "    case {tag}:\n{get_var}        break;").unwrap();
+        }
+
+        writeln!(self.out, "typedef enum {{\n{tags}}} {name}Tag;\n").unwrap();
+        // C does not allow empty unions, e.g., for enums with unit variants only.
+        let union = match members.is_empty() {
+            true => String::new(),
+            false => format!("    union {{\n{members}    }} value;\n"),
+        };
+        writeln!(
+            self.out,
+            "typedef struct {{\n    {name}Tag tag;\n{union}}} {name};\n"
+        )
+        .unwrap();
+
+        let put = format!(
+            "    pc_put_varint(w, (uint64_t)v->tag);\n    switch (v->tag) {{\n{put}    default:\n        w->ok = false;\n    }}\n"
+        );
+        let get = format!(
+            "    v->tag = ({name}Tag)pc_get_uint(r, 5, {max});\n    switch (v->tag) {{\n{get}    default:\n        r->ok = false;\n    }}\n",
+            max = variants.len().saturating_sub(1)
+        );
+        self.emit_functions(name, &snake, &put, &get);
+        Ok(())
+    }
+
+    /// Capacity of the string or byte buffer at `path`, `None` for other types.
+    fn bound(&self, ty: &NamedType, path: &str) -> Result<Option<usize>, String> {
+        if !is_bytes(ty) {
+            return Ok(None);
+        }
+        match self.bounds.iter().find(|(bounded, _)| *bounded == path) {
+            Some(&(_, max)) => Ok(Some(max)),
+            None => Err(format!("{path}: no capacity given for {}", ty.name)),
+        }
+    }
+
+    fn emit_functions(&mut self, name: &str, snake: &str, put: &str, get: &str) {
+        writeln!(
+            self.out,
+            "static inline void {snake}_put(pc_writer *w, const {name} *v) {{\n{put}}}\n\n\
+             static inline void {snake}_get(pc_reader *r, {name} *v) {{\n{get}}}\n\n\
+             /* Encode into `buf`, returns the number of bytes or 0 if `buf` is too small. */\n\
+             static inline size_t {snake}_encode(const {name} *v, uint8_t *buf, size_t len) {{\n\
+             \x20   pc_writer w = {{buf, len, 0, true}};\n\
+             \x20   {snake}_put(&w, v);\n\
+             \x20   return w.ok ? w.pos : 0;\n}}\n\n\
+             /* Decode from `buf`, returns the number of bytes used or 0 if the data is invalid. */\n\
+             static inline size_t {snake}_decode({name} *v, const uint8_t *buf, size_t len) {{\n\
+             \x20   pc_reader r = {{buf, len, 0, true}};\n\
+             \x20   {snake}_get(&r, v);\n\
+             \x20   return r.ok ? r.pos : 0;\n}}\n"
+        )
+        .unwrap();
+    }
+}
+
+/// Strings and sequences of bytes, both are encoded as length and bytes.
+fn is_bytes(ty: &NamedType) -> bool {
+    match ty.ty {
+        DataModelType::String | DataModelType::ByteArray => true,
+        DataModelType::Seq(inner) => matches!(inner.ty, DataModelType::U8),
+        _ => false,
+    }
+}
+
+/// `max` is the capacity of strings and byte buffers, see `CGen::bound`.
+fn c_type(ty: &NamedType, max: Option<usize>) -> Result<String, String> {
+    Ok(match (ty.ty, max) {
+        (DataModelType::String, Some(max)) => format!("struct {{ size_t len; char data[{max}]; }}"),
+        (_, Some(max)) => format!("struct {{ size_t len; uint8_t data[{max}]; }}"),
+        (DataModelType::Bool, _) => "bool".to_string(),
+        (DataModelType::U8, _) => "uint8_t".to_string(),
+        (DataModelType::I8, _) => "int8_t".to_string(),
+        (DataModelType::F32, _) => "float".to_string(),
+        (DataModelType::Struct(_) | DataModelType::Enum(_), _) => ty.name.to_string(),
+        (other, None) => match c_int(other) {
+            Some((c, _, _)) => c.to_string(),
+            None => return Err(format!("{}: type is not supported in C", ty.name)),
+        },
+    })
+}
+
+fn put_stmt(ty: &NamedType, expr: &str, max: Option<usize>) -> Result<String, String> {
+    if let Some(max) = max {
+        return Ok(format!(
+            "pc_put_bytes(w, (const uint8_t *){expr}.data, {expr}.len, {max});"
+        ));
+    }
+    Ok(match ty.ty {
+        DataModelType::Bool => format!("pc_put(w, {expr} ? 1 : 0);"),
+        DataModelType::U8 | DataModelType::I8 => format!("pc_put(w, (uint8_t){expr});"),
+        DataModelType::F32 => format!("pc_put_f32(w, {expr});"),
+        DataModelType::Struct(_) | DataModelType::Enum(_) => {
+            format!("{}_put(w, &{expr});", snake_case(ty.name))
+        }
+        other => match c_int(other) {
+            Some((_, _, true)) => format!("pc_put_varint(w, pc_zigzag((int64_t){expr}));"),
+            Some((_, _, false)) => format!("pc_put_varint(w, (uint64_t){expr});"),
+            None => return Err(format!("{}: type is not supported in C", ty.name)),
+        },
+    })
+}
+
+fn get_stmt(ty: &NamedType, expr: &str, max: Option<usize>) -> Result<String, String> {
+    if let Some(max) = max {
+        return Ok(format!(
+            "{expr}.len = pc_get_bytes(r, (uint8_t *){expr}.data, {max});"
+        ));
+    }
+    Ok(match ty.ty {
+        DataModelType::Bool => format!("{expr} = pc_get_bool(r);"),
+        DataModelType::U8 => format!("{expr} = pc_get(r);"),
+        DataModelType::I8 => format!("{expr} = (int8_t)pc_get(r);"),
+        DataModelType::F32 => format!("{expr} = pc_get_f32(r);"),
+        DataModelType::Struct(_) | DataModelType::Enum(_) => {
+            format!("{}_get(r, &{expr});", snake_case(ty.name))
+        }
+        other => match c_int(other) {
+            Some((c, bytes, true)) => {
+                let (min, max) = c_limits(c);
+                format!("{expr} = ({c})pc_get_int(r, {bytes}, {min}, {max});")
+            }
+            Some((c, bytes, false)) => {
+                let (_, max) = c_limits(c);
+                format!("{expr} = ({c})pc_get_uint(r, {bytes}, {max});")
+            }
+            None => return Err(format!("{}: type is not supported in C", ty.name)),
+        },
+    })
+}
+
+fn c_limits(c: &str) -> (String, String) {
+    let base = c.trim_end_matches("_t").to_uppercase();
+    match c.starts_with('u') {
+        true => ("0".to_string(), format!("{base}_MAX")),
+        false => (format!("{base}_MIN"), format!("{base}_MAX")),
+    }
+}
+
+/// `SetPosition` -> `set_position`.
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (it, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if it > 0 {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Encoding primitives shared by all generated types.
+const C_RUNTIME: &str = r#"#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <string.h>
+
+/* postcard encodes unsigned integers as varints, signed ones zigzag encoded,
+   and floats as little endian bytes. */
+
+typedef struct {
+    uint8_t *buf;
+    size_t len;
+    size_t pos;
+    bool ok;
+} pc_writer;
+
+typedef struct {
+    const uint8_t *buf;
+    size_t len;
+    size_t pos;
+    bool ok;
+} pc_reader;
+
+static inline void pc_put(pc_writer *w, uint8_t byte) {
+    if (w->pos < w->len) {
+        w->buf[w->pos++] = byte;
+    } else {
+        w->ok = false;
+    }
+}
+
+static inline void pc_put_varint(pc_writer *w, uint64_t value) {
+    while (value >= 0x80) {
+        pc_put(w, (uint8_t)(value | 0x80));
+        value >>= 7;
+    }
+    pc_put(w, (uint8_t)value);
+}
+
+static inline uint64_t pc_zigzag(int64_t value) {
+    return ((uint64_t)value << 1) ^ (uint64_t)(value >> 63);
+}
+
+static inline void pc_put_f32(pc_writer *w, float value) {
+    uint8_t bytes[4];
+    memcpy(bytes, &value, 4);
+    for (int it = 0; it < 4; it++) {
+        pc_put(w, bytes[it]);
+    }
+}
+
+/* Length and bytes, fails if there are more than `max` bytes. */
+static inline void pc_put_bytes(pc_writer *w, const uint8_t *data, size_t len, size_t max) {
+    if (len > max) {
+        w->ok = false;
+        return;
+    }
+    pc_put_varint(w, (uint64_t)len);
+    for (size_t it = 0; it < len; it++) {
+        pc_put(w, data[it]);
+    }
+}
+
+static inline uint8_t pc_get(pc_reader *r) {
+    if (r->pos < r->len) {
+        return r->buf[r->pos++];
+    }
+    r->ok = false;
+    return 0;
+}
+
+static inline bool pc_get_bool(pc_reader *r) {
+    uint8_t byte = pc_get(r);
+    if (byte > 1) {
+        r->ok = false;
+    }
+    return byte == 1;
+}
+
+/* Varint of at most `bytes` bytes with a value of at most `max`. */
+static inline uint64_t pc_get_uint(pc_reader *r, uint8_t bytes, uint64_t max) {
+    uint64_t value = 0;
+    for (uint8_t it = 0; it < bytes; it++) {
+        uint8_t byte = pc_get(r);
+        value |= (uint64_t)(byte & 0x7f) << (7 * it);
+        if (!(byte & 0x80)) {
+            if (value > max) {
+                r->ok = false;
+            }
+            return value;
+        }
+    }
+    r->ok = false;
+    return 0;
+}
+
+static inline int64_t pc_get_int(pc_reader *r, uint8_t bytes, int64_t min, int64_t max) {
+    uint64_t raw = pc_get_uint(r, bytes, UINT64_MAX);
+    int64_t value = (int64_t)(raw >> 1) ^ -(int64_t)(raw & 1);
+    if (value < min || value > max) {
+        r->ok = false;
+    }
+    return value;
+}
+
+/* Length and bytes into `data` of `max` bytes, returns the length. */
+static inline size_t pc_get_bytes(pc_reader *r, uint8_t *data, size_t max) {
+    size_t len = (size_t)pc_get_uint(r, 5, max);
+    if (!r->ok) {
+        return 0;
+    }
+    for (size_t it = 0; it < len; it++) {
+        data[it] = pc_get(r);
+    }
+    return len;
+}
+
+static inline float pc_get_f32(pc_reader *r) {
+    uint8_t bytes[4];
+    for (int it = 0; it < 4; it++) {
+        bytes[it] = pc_get(r);
+    }
+    float value;
+    memcpy(&value, bytes, 4);
+    return value;
+}
+
+"#;
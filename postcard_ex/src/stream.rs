@@ -0,0 +1,320 @@
+//! Decode COBS framed `Commands` (or other messages) from a byte stream.
+//!
+//! Data from a serial port arrives in arbitrary chunks: a chunk can contain
+//! several frames, or only part of one. The `FrameDecoder` collects the bytes
+//! until a frame delimiter (`0x00`) is found and then decodes the frame.
+//!
+//! Invalid frames and frames that are larger than the buffer are reported as
+//! errors. In the latter case, all bytes up to the next delimiter are dropped,
+//! such that decoding starts again at the beginning of the next frame.
+
+use core::marker::PhantomData;
+
+use postcard::experimental::max_size::MaxSize;
+use serde::de::DeserializeOwned;
+
+use crate::checksum::{verify, Checksum, CrcError};
+use crate::commands::{Commands, Envelope, Responses};
+use crate::decode::{decode, DecodeError};
+
+/// COBS frame delimiter.
+pub const DELIMITER: u8 = 0x00;
+
+/// Size of a COBS frame, including the delimiter, of a message of at most
+/// `max_size` bytes, e.g., of `Commands::POSTCARD_MAX_SIZE`.
+///
+/// A `FrameDecoder` with a buffer of this size never drops a valid frame.
+pub const fn max_frame_size(max_size: usize) -> usize {
+    cobs::max_encoding_length(max_size) + 1
+}
+
+/// Maximum size of a COBS frame on our serial lines, e.g., for the `transport`.
+pub const MAX_FRAME: usize = 256;
+
+// Any command or response fits, even with a CRC-32 appended.
+const _: () = assert!(max_frame_size(Envelope::<Commands>::POSTCARD_MAX_SIZE + 4) <= MAX_FRAME);
+const _: () = assert!(max_frame_size(Envelope::<Responses>::POSTCARD_MAX_SIZE + 4) <= MAX_FRAME);
+
+/// Why a frame could not be decoded.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// The frame did not fit into the buffer and was dropped.
+    Overfull,
+    /// The frame is not valid COBS.
+    Corrupted,
+    /// The checksum of the frame does not match its data.
+    Checksum { received: u32, computed: u32 },
+    /// The frame is valid COBS, but does not contain a valid command.
+    Decode(DecodeError),
+}
+
+impl From<DecodeError> for FrameError {
+    fn from(err: DecodeError) -> Self {
+        FrameError::Decode(err)
+    }
+}
+
+impl From<CrcError> for FrameError {
+    fn from(err: CrcError) -> Self {
+        match err {
+            CrcError::Mismatch { received, computed } => {
+                FrameError::Checksum { received, computed }
+            }
+            CrcError::Decode(err) => FrameError::Decode(err),
+        }
+    }
+}
+
+/// Stateful decoder for COBS frames with a buffer of `N` bytes, containing
+/// messages of type `T`.
+pub struct FrameDecoder<const N: usize, T = Commands> {
+    buf: [u8; N],
+    idx: usize,
+    discarding: bool,
+    checksum: Option<Checksum>,
+    msg: PhantomData<fn() -> T>,
+}
+
+impl<const N: usize, T: DeserializeOwned> FrameDecoder<N, T> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            idx: 0,
+            discarding: false,
+            checksum: None,
+            msg: PhantomData,
+        }
+    }
+
+    /// Decoder for frames that end with a checksum, see `checksum::encode`.
+    pub const fn with_checksum(checksum: Checksum) -> Self {
+        let mut decoder = Self::new();
+        decoder.checksum = Some(checksum);
+        decoder
+    }
+
+    /// Feed a chunk of bytes and iterate over the frames completed by it.
+    ///
+    /// Bytes of an incomplete frame at the end of the chunk are kept for the
+    /// next call. The iterator should be run to the end, otherwise the rest of
+    /// the chunk is not consumed.
+    pub fn feed<'a>(&'a mut self, chunk: &'a [u8]) -> Frames<'a, N, T> {
+        Frames {
+            decoder: self,
+            remaining: chunk,
+        }
+    }
+
+    /// Feed a chunk of bytes and pass the frames completed by it to `f`,
+    /// without decoding them.
+    ///
+    /// The frames are COBS decoded, and their checksum is verified and removed
+    /// if the decoder has one. This is for frames that are not a single
+    /// message, e.g., with a channel id in front, see `channel::Demux`.
+    pub fn feed_raw(&mut self, chunk: &[u8], mut f: impl FnMut(Result<&[u8], FrameError>)) {
+        let mut remaining = chunk;
+        while let Some(frame) = self.next_frame(&mut remaining) {
+            f(frame);
+        }
+    }
+
+    /// Number of bytes of a partially received frame.
+    pub fn pending(&self) -> usize {
+        self.idx
+    }
+
+    /// Drop a partially received frame, e.g., after a reconnect.
+    pub fn reset(&mut self) {
+        self.idx = 0;
+        self.discarding = false;
+    }
+
+    /// Collect the bytes of `remaining` up to the end of the next frame and
+    /// return its data, the bytes after the frame are left in `remaining`.
+    fn next_frame(&mut self, remaining: &mut &[u8]) -> Option<Result<&[u8], FrameError>> {
+        while !remaining.is_empty() {
+            let end = remaining.iter().position(|&b| b == DELIMITER);
+
+            if self.discarding {
+                match end {
+                    Some(end) => {
+                        self.discarding = false;
+                        *remaining = &remaining[end + 1..];
+                        continue;
+                    }
+                    None => {
+                        *remaining = &[];
+                        return None;
+                    }
+                }
+            }
+
+            let (data, rest) = match end {
+                Some(end) => (&remaining[..end], &remaining[end + 1..]),
+                None => (*remaining, &[][..]),
+            };
+            *remaining = rest;
+
+            if self.idx + data.len() > N {
+                self.idx = 0;
+                self.discarding = end.is_none();
+                return Some(Err(FrameError::Overfull));
+            }
+            self.buf[self.idx..self.idx + data.len()].copy_from_slice(data);
+            self.idx += data.len();
+
+            // Consecutive delimiters are empty frames, which we skip.
+            if end.is_some() && self.idx > 0 {
+                return Some(self.unframe());
+            }
+        }
+        None
+    }
+
+    /// COBS decode the collected frame and verify its checksum.
+    fn unframe(&mut self) -> Result<&[u8], FrameError> {
+        let frame = &mut self.buf[..self.idx];
+        self.idx = 0;
+        let len = cobs::decode_in_place(frame).map_err(|_| FrameError::Corrupted)?;
+        match self.checksum {
+            Some(checksum) => Ok(verify(&frame[..len], checksum)?),
+            None => Ok(&frame[..len]),
+        }
+    }
+}
+
+impl<const N: usize, T: DeserializeOwned> Default for FrameDecoder<N, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Iterator over the frames completed by a chunk, see `FrameDecoder::feed`.
+pub struct Frames<'a, const N: usize, T> {
+    decoder: &'a mut FrameDecoder<N, T>,
+    remaining: &'a [u8],
+}
+
+impl<const N: usize, T: DeserializeOwned> Iterator for Frames<'_, N, T> {
+    type Item = Result<T, FrameError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = self.decoder.next_frame(&mut self.remaining)?;
+        Some(frame.and_then(|frame| Ok(decode(frame)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{Position, COMMAND_MAX_SIZE};
+
+    const N: usize = max_frame_size(COMMAND_MAX_SIZE);
+
+    fn frame(cmd: &Commands) -> std::vec::Vec<u8> {
+        let mut buf = [0u8; N];
+        postcard::to_slice_cobs(cmd, &mut buf).unwrap().to_vec()
+    }
+
+    fn set_position(x: u32) -> Commands {
+        Commands::SetPosition(Position { x, y: 456 })
+    }
+
+    #[test]
+    fn frame_split_across_chunks() {
+        let bytes = frame(&set_position(123));
+        let mut decoder = FrameDecoder::<N>::new();
+        let (head, tail) = bytes.split_at(2);
+
+        assert_eq!(decoder.feed(head).count(), 0);
+        assert_eq!(decoder.pending(), 2);
+        let frames: std::vec::Vec<_> = decoder.feed(tail).collect();
+        assert_eq!(frames, [Ok(set_position(123))]);
+        assert_eq!(decoder.pending(), 0);
+    }
+
+    #[test]
+    fn several_frames_in_one_chunk() {
+        let cmds = [
+            set_position(1),
+            Commands::QueryPosition,
+            Commands::SetTime(7),
+        ];
+        let bytes: std::vec::Vec<u8> = cmds.iter().flat_map(frame).collect();
+        let mut decoder = FrameDecoder::<N>::new();
+
+        let frames: std::vec::Vec<_> = decoder.feed(&bytes).collect();
+        assert_eq!(frames, cmds.map(Ok));
+    }
+
+    #[test]
+    fn overfull_frame_is_dropped_until_the_next_delimiter() {
+        // `QueryPosition` fits into 8 bytes, the noise does not.
+        let mut decoder = FrameDecoder::<8>::new();
+
+        let frames: std::vec::Vec<_> = decoder.feed(&[0x55; 10]).collect();
+        assert_eq!(frames, [Err(FrameError::Overfull)]);
+        assert_eq!(decoder.feed(&[0x55; 10]).count(), 0);
+
+        let mut bytes = vec![0x55, 0x55, DELIMITER];
+        bytes.extend(frame(&Commands::QueryPosition));
+        let frames: std::vec::Vec<_> = decoder.feed(&bytes).collect();
+        assert_eq!(frames, [Ok(Commands::QueryPosition)]);
+    }
+
+    #[test]
+    fn overfull_frame_within_one_chunk() {
+        let mut decoder = FrameDecoder::<8>::new();
+        let mut bytes = vec![0x55; 20];
+        bytes.push(DELIMITER);
+        bytes.extend(frame(&Commands::QueryPosition));
+
+        let frames: std::vec::Vec<_> = decoder.feed(&bytes).collect();
+        assert_eq!(
+            frames,
+            [Err(FrameError::Overfull), Ok(Commands::QueryPosition)]
+        );
+    }
+
+    #[test]
+    fn raw_frames_are_not_decoded() {
+        let mut bytes = frame(&Commands::SetTime(1));
+        bytes.extend([0x05, 0x01, DELIMITER]);
+        let mut decoder = FrameDecoder::<N>::new();
+        let mut frames = std::vec::Vec::new();
+
+        let (head, tail) = bytes.split_at(1);
+        for chunk in [head, tail] {
+            decoder.feed_raw(chunk, |frame| frames.push(frame.map(<[u8]>::to_vec)));
+        }
+        assert_eq!(frames, [Ok(vec![0x02, 0x01]), Err(FrameError::Corrupted)]);
+    }
+
+    #[test]
+    fn empty_frames_are_skipped() {
+        let mut bytes = vec![DELIMITER, DELIMITER];
+        bytes.extend(frame(&Commands::SetTime(1)));
+        bytes.extend([DELIMITER, DELIMITER]);
+        let mut decoder = FrameDecoder::<N>::new();
+
+        let frames: std::vec::Vec<_> = decoder.feed(&bytes).collect();
+        assert_eq!(frames, [Ok(Commands::SetTime(1))]);
+    }
+
+    #[test]
+    fn invalid_frames_are_reported() {
+        let mut decoder = FrameDecoder::<N>::new();
+        // The COBS code points past the end of the frame.
+        let frames: std::vec::Vec<_> = decoder.feed(&[0x05, 0x01, DELIMITER]).collect();
+        assert_eq!(frames, [Err(FrameError::Corrupted)]);
+        // Valid COBS, unknown variant.
+        let frames: std::vec::Vec<_> = decoder.feed(&[0x02, 0x09, DELIMITER]).collect();
+        assert_eq!(
+            frames,
+            [Err(FrameError::Decode(DecodeError::UnknownVariant {
+                discriminant: 9,
+                offset: 0
+            }))]
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Send and receive COBS framed messages over a serial port.
+//!
+//! `Transport` works with anything that implements `Read + Write`, e.g., a
+//! serial port opened with `open_serial`, a TCP stream, or a pseudo-terminal.
+//! Reads are expected to return after the read timeout of the port, such that
+//! `receive` can give up after its own timeout.
+//!
+//! `Reconnecting` wraps a transport that is opened again after the device was
+//! unplugged, e.g., a USB serial adapter that disappears and comes back.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serialport::SerialPort;
+
+use crate::commands::Commands;
+pub use crate::stream::MAX_FRAME;
+use crate::stream::{FrameDecoder, FrameError};
+
+/// Why sending or receiving failed.
+#[derive(Debug)]
+pub enum TransportError {
+    /// No complete frame arrived within the timeout.
+    Timeout,
+    /// The port is gone, e.g., because the device was unplugged.
+    Disconnected(io::Error),
+    /// Any other error of the port.
+    Io(io::Error),
+    /// A frame arrived, but it is invalid.
+    Frame(FrameError),
+    /// The message could not be encoded, e.g., because it is too large.
+    Encode(postcard::Error),
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::Timeout => write!(f, "timeout while waiting for a frame"),
+            TransportError::Disconnected(err) => write!(f, "disconnected: {}", err),
+            TransportError::Io(err) => write!(f, "{}", err),
+            TransportError::Frame(err) => write!(f, "invalid frame: {:?}", err),
+            TransportError::Encode(err) => write!(f, "cannot encode message: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+impl From<io::Error> for TransportError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            // A pseudo-terminal or USB serial adapter that goes away reports
+            // EIO, sockets report the specific kinds.
+            ErrorKind::BrokenPipe
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::ConnectionReset
+            | ErrorKind::NotConnected
+            | ErrorKind::UnexpectedEof => TransportError::Disconnected(err),
+            _ if err.raw_os_error() == Some(libc::EIO)
+                || err.raw_os_error() == Some(libc::ENXIO) =>
+            {
+                TransportError::Disconnected(err)
+            }
+            _ => TransportError::Io(err),
+        }
+    }
+}
+
+/// Open a serial port in raw mode, reads time out after `read_timeout`.
+pub fn open_serial(
+    path: &str,
+    baud: u32,
+    read_timeout: Duration,
+) -> io::Result<Box<dyn SerialPort>> {
+    serialport::new(path, baud)
+        .timeout(read_timeout)
+        .open()
+        .map_err(io::Error::from)
+}
+
+/// Framed messages over a port: sends any message, receives messages of type `R`.
+pub struct Transport<P, R = Commands> {
+    port: P,
+    timeout: Duration,
+    decoder: FrameDecoder<MAX_FRAME, R>,
+    received: VecDeque<Result<R, FrameError>>,
+}
+
+impl<P: Read + Write, R: DeserializeOwned> Transport<P, R> {
+    /// `receive` fails with `Timeout` if no frame completes within `timeout`.
+    pub fn new(port: P, timeout: Duration) -> Self {
+        Self {
+            port,
+            timeout,
+            decoder: FrameDecoder::new(),
+            received: VecDeque::new(),
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Encode `msg` as COBS frame and write it.
+    pub fn send<S: Serialize>(&mut self, msg: &S) -> Result<(), TransportError> {
+        let mut buf = [0u8; MAX_FRAME];
+        let frame = postcard::to_slice_cobs(msg, &mut buf).map_err(TransportError::Encode)?;
+        self.port.write_all(frame)?;
+        self.port.flush()?;
+        Ok(())
+    }
+
+    /// Wait for the next frame and decode it.
+    ///
+    /// An invalid frame is returned as `TransportError::Frame`, the following
+    /// frames can still be received.
+    pub fn receive(&mut self) -> Result<R, TransportError> {
+        let deadline = Instant::now() + self.timeout;
+        let mut buf = [0u8; 64];
+        loop {
+            if let Some(frame) = self.received.pop_front() {
+                return frame.map_err(TransportError::Frame);
+            }
+            if Instant::now() >= deadline {
+                return Err(TransportError::Timeout);
+            }
+            let len = match self.port.read(&mut buf) {
+                Ok(0) => return Err(io::Error::from(ErrorKind::UnexpectedEof).into()),
+                Ok(len) => len,
+                Err(err) if is_retry(&err) => continue,
+                Err(err) => return Err(err.into()),
+            };
+            self.received.extend(self.decoder.feed(&buf[..len]));
+        }
+    }
+
+    /// Drop received bytes and frames that were not picked up yet.
+    pub fn clear(&mut self) {
+        self.decoder.reset();
+        self.received.clear();
+    }
+
+    pub fn get_mut(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+}
+
+/// Errors after which reading again is fine.
+fn is_retry(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::TimedOut | ErrorKind::WouldBlock | ErrorKind::Interrupted
+    )
+}
+
+/// A transport that opens the port again after it was disconnected.
+///
+/// The port is opened with `connect` when needed. After a `Disconnected`
+/// error, the port is dropped, and the next `send` or `receive` tries to open
+/// it again. Frames that were only partially received are lost.
+pub struct Reconnecting<C, P, R = Commands> {
+    connect: C,
+    timeout: Duration,
+    transport: Option<Transport<P, R>>,
+}
+
+impl<C, P, R> Reconnecting<C, P, R>
+where
+    C: FnMut() -> io::Result<P>,
+    P: Read + Write,
+    R: DeserializeOwned,
+{
+    /// Does not connect yet, such that the device can be plugged in later.
+    pub fn new(connect: C, timeout: Duration) -> Self {
+        Self {
+            connect,
+            timeout,
+            transport: None,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    pub fn send<S: Serialize>(&mut self, msg: &S) -> Result<(), TransportError> {
+        let result = self.transport()?.send(msg);
+        self.check(result)
+    }
+
+    pub fn receive(&mut self) -> Result<R, TransportError> {
+        let result = self.transport()?.receive();
+        self.check(result)
+    }
+
+    fn transport(&mut self) -> Result<&mut Transport<P, R>, TransportError> {
+        if self.transport.is_none() {
+            let port = (self.connect)().map_err(TransportError::Disconnected)?;
+            self.transport = Some(Transport::new(port, self.timeout));
+        }
+        Ok(self.transport.as_mut().unwrap())
+    }
+
+    /// Forget the port if it is gone.
+    fn check<T>(&mut self, result: Result<T, TransportError>) -> Result<T, TransportError> {
+        if let Err(TransportError::Disconnected(_)) = result {
+            self.transport = None;
+        }
+        result
+    }
+}
@@ -0,0 +1,374 @@
+//! Versioned messages that stay readable when the wire types change.
+//!
+//! postcard is not self-describing: after changing `Position` or adding a
+//! variant to `Commands`, old data still decodes, but to the wrong values.
+//! Every message is therefore sent with a `Header` holding the protocol
+//! version and a hash of the schema of that version.
+//!
+//! Messages of the current version are decoded directly, messages of older
+//! versions with the explicit migration of the `Message` type. Newer versions
+//! and unknown schemas are rejected.
+//!
+//! When changing a wire type, increase its `VERSION`, update its `SCHEMA`,
+//! and move the old types into a module that `migrate` can decode from. The
+//! tests (with the `schema` feature) compare every `SCHEMA` to the derived
+//! `postcard_schema::Schema` of the types and fail if one was forgotten.
+
+use core::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::{Commands, Envelope, Position, Responses};
+use crate::decode::{decode, decode_partial, DecodeError};
+
+/// Prepended to every message.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Header {
+    pub version: u8,
+    pub schema: u32,
+}
+
+/// Why a versioned message could not be decoded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VersionError {
+    /// The header or the payload is invalid.
+    Decode(DecodeError),
+    /// The message was sent with a newer version than we know.
+    FutureVersion { version: u8, current: u8 },
+    /// The message was sent with an old version that cannot be migrated.
+    UnsupportedVersion(u8),
+    /// The schema hash does not belong to the version of the message.
+    SchemaMismatch {
+        version: u8,
+        expected: u32,
+        found: u32,
+    },
+}
+
+impl From<DecodeError> for VersionError {
+    fn from(err: DecodeError) -> Self {
+        VersionError::Decode(err)
+    }
+}
+
+impl fmt::Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionError::Decode(err) => write!(f, "{}", err),
+            VersionError::FutureVersion { version, current } => write!(
+                f,
+                "protocol version {} is newer than supported version {}",
+                version, current
+            ),
+            VersionError::UnsupportedVersion(version) => {
+                write!(f, "protocol version {} is no longer supported", version)
+            }
+            VersionError::SchemaMismatch {
+                version,
+                expected,
+                found,
+            } => write!(
+                f,
+                "schema {:#010x} does not match {:#010x} of protocol version {}",
+                found, expected, version
+            ),
+        }
+    }
+}
+
+/// A message type with a versioned wire format.
+pub trait Message: Serialize + DeserializeOwned {
+    /// Version of the current wire format.
+    const VERSION: u8;
+    /// Description of the current wire format, changes with every version,
+    /// see `schema::describe`.
+    const SCHEMA: &'static str;
+
+    /// Decode the `payload` of the older version in `header` and convert it.
+    fn migrate(header: Header, payload: &[u8]) -> Result<Self, VersionError> {
+        let _ = payload;
+        Err(VersionError::UnsupportedVersion(header.version))
+    }
+
+    fn header() -> Header {
+        Header {
+            version: Self::VERSION,
+            schema: schema_hash(Self::SCHEMA),
+        }
+    }
+}
+
+/// Encode `msg` with the header of the current version into `buf`.
+pub fn encode<'a, M: Message>(msg: &M, buf: &'a mut [u8]) -> postcard::Result<&'a mut [u8]> {
+    postcard::to_slice(&(M::header(), msg), buf)
+}
+
+/// Decode a message of the current or an older version.
+pub fn decode_versioned<M: Message>(bytes: &[u8]) -> Result<M, VersionError> {
+    let (header, payload) = decode_partial::<Header>(bytes)?;
+    let header_len = bytes.len() - payload.len();
+    let current = M::header();
+
+    let result = if header.version > current.version {
+        return Err(VersionError::FutureVersion {
+            version: header.version,
+            current: current.version,
+        });
+    } else if header.version < current.version {
+        M::migrate(header, payload)
+    } else if header.schema != current.schema {
+        return Err(VersionError::SchemaMismatch {
+            version: header.version,
+            expected: current.schema,
+            found: header.schema,
+        });
+    } else {
+        decode(payload).map_err(VersionError::from)
+    };
+    // Offsets of errors in the payload refer to the whole message.
+    result.map_err(|err| match err {
+        VersionError::Decode(err) => VersionError::Decode(err.shift(header_len)),
+        err => err,
+    })
+}
+
+/// Check the schema hash of an old message before migrating it.
+pub fn check_schema(header: Header, schema: &str) -> Result<(), VersionError> {
+    let expected = schema_hash(schema);
+    match header.schema == expected {
+        true => Ok(()),
+        false => Err(VersionError::SchemaMismatch {
+            version: header.version,
+            expected,
+            found: header.schema,
+        }),
+    }
+}
+
+/// 32 bit FNV-1a hash of a schema description.
+pub const fn schema_hash(schema: &str) -> u32 {
+    let bytes = schema.as_bytes();
+    let mut hash = 0x811c_9dc5_u32;
+    let mut it = 0;
+    while it < bytes.len() {
+        hash ^= bytes[it] as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+        it += 1;
+    }
+    hash
+}
+
+impl Message for Envelope<Commands> {
+    const VERSION: u8 = 3;
+    const SCHEMA: &'static str = "Envelope{seq:u16,payload:Commands{\
+        SetPosition(Position{x:u32,y:u32}),QueryPosition,SetTime(u64),\
+        SetLabel(String<32>),UploadChunk{offset:u32,data:Vec<u8,64>}}}";
+
+    fn migrate(header: Header, payload: &[u8]) -> Result<Self, VersionError> {
+        match header.version {
+            // Only variants were added since, which leaves the old ones as they were.
+            2 => {
+                check_schema(header, v2::SCHEMA)?;
+                Ok(decode(payload)?)
+            }
+            1 => {
+                check_schema(header, v1::SCHEMA)?;
+                let payload = match decode(payload)? {
+                    v1::Commands::SetPosition(v1::Position { x, y }) => {
+                        Commands::SetPosition(Position { x, y })
+                    }
+                    v1::Commands::QueryPosition => Commands::QueryPosition,
+                    v1::Commands::SetTime(time) => Commands::SetTime(time),
+                    // Never meant to be sent, treat it like any unknown variant.
+                    v1::Commands::Unknown => {
+                        return Err(VersionError::Decode(DecodeError::UnknownVariant {
+                            discriminant: 3,
+                            offset: 0,
+                        }))
+                    }
+                };
+                // The reply to it has sequence number 0, as there was none.
+                Ok(Envelope { seq: 0, payload })
+            }
+            _ => Err(VersionError::UnsupportedVersion(header.version)),
+        }
+    }
+}
+
+impl Message for Envelope<Responses> {
+    const VERSION: u8 = 1;
+    const SCHEMA: &'static str = "Envelope{seq:u16,payload:Responses{\
+        PositionReport(Position{x:u32,y:u32}),Ack,Nack(u8),TimeReport(u64)}}";
+}
+
+/// Version 2: commands in an `Envelope`, without labels and uploads.
+pub mod v2 {
+    pub const SCHEMA: &str = "Envelope{seq:u16,payload:Commands{\
+        SetPosition(Position{x:u32,y:u32}),QueryPosition,SetTime(u64)}}";
+}
+
+/// Version 1: the commands of the original protocol, before versioning, i.e.,
+/// without `Envelope` and without `Header`.
+///
+/// Senders of this layout never prepend a header, so `decode_versioned` cannot
+/// tell their messages apart on its own. A host that knows it talks to such a
+/// sender prepends `Header { version: 1, schema: schema_hash(v1::SCHEMA) }`
+/// to each message before decoding it.
+pub mod v1 {
+    use serde::{Deserialize, Serialize};
+
+    pub const SCHEMA: &str = "Commands{SetPosition(Position{x:u32,y:u32}),\
+        QueryPosition,SetTime(u64),Unknown}";
+
+    #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+    pub enum Commands {
+        SetPosition(Position),
+        QueryPosition,
+        SetTime(u64),
+        Unknown,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+    pub struct Position {
+        pub x: u32,
+        pub y: u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_with<T: Serialize>(header: Header, payload: &T) -> std::vec::Vec<u8> {
+        let mut buf = [0u8; 128];
+        postcard::to_slice(&(header, payload), &mut buf)
+            .unwrap()
+            .to_vec()
+    }
+
+    fn header(version: u8, schema: &str) -> Header {
+        Header {
+            version,
+            schema: schema_hash(schema),
+        }
+    }
+
+    #[test]
+    fn current_version() {
+        let msg = Envelope {
+            seq: 7,
+            payload: Commands::SetTime(42),
+        };
+        let mut buf = [0u8; 64];
+        let bytes = encode(&msg, &mut buf).unwrap();
+        assert_eq!(decode_versioned::<Envelope<Commands>>(bytes), Ok(msg));
+    }
+
+    #[test]
+    fn v2_migrates() {
+        let msg = Envelope {
+            seq: 7,
+            payload: Commands::QueryPosition,
+        };
+        let bytes = encode_with(header(2, v2::SCHEMA), &msg);
+        assert_eq!(decode_versioned::<Envelope<Commands>>(&bytes), Ok(msg));
+    }
+
+    #[test]
+    fn v1_migrates() {
+        let old = v1::Commands::SetPosition(v1::Position { x: 1, y: 2 });
+        let bytes = encode_with(header(1, v1::SCHEMA), &old);
+        assert_eq!(
+            decode_versioned::<Envelope<Commands>>(&bytes),
+            Ok(Envelope {
+                seq: 0,
+                payload: Commands::SetPosition(Position { x: 1, y: 2 })
+            })
+        );
+
+        let bytes = encode_with(header(1, v1::SCHEMA), &v1::Commands::Unknown);
+        let header_len = bytes.len() - 1;
+        assert_eq!(
+            decode_versioned::<Envelope<Commands>>(&bytes),
+            Err(VersionError::Decode(DecodeError::UnknownVariant {
+                discriminant: 3,
+                offset: header_len
+            }))
+        );
+    }
+
+    #[test]
+    fn rejected_versions() {
+        let msg = Commands::QueryPosition;
+        let current = Envelope::<Commands>::VERSION;
+
+        let bytes = encode_with(header(current + 1, "future"), &msg);
+        assert_eq!(
+            decode_versioned::<Envelope<Commands>>(&bytes),
+            Err(VersionError::FutureVersion {
+                version: current + 1,
+                current
+            })
+        );
+        let bytes = encode_with(header(0, ""), &msg);
+        assert_eq!(
+            decode_versioned::<Envelope<Commands>>(&bytes),
+            Err(VersionError::UnsupportedVersion(0))
+        );
+        let bytes = encode_with(header(2, v1::SCHEMA), &msg);
+        assert_eq!(
+            decode_versioned::<Envelope<Commands>>(&bytes),
+            Err(VersionError::SchemaMismatch {
+                version: 2,
+                expected: schema_hash(v2::SCHEMA),
+                found: schema_hash(v1::SCHEMA)
+            })
+        );
+    }
+
+    #[test]
+    fn schema_hash_is_fnv1a() {
+        assert_eq!(schema_hash(""), 0x811c_9dc5);
+        assert_eq!(schema_hash("a"), 0xe40c_292c);
+    }
+
+    /// Fails if a `SCHEMA` was changed. Then increase the `VERSION`, append
+    /// the new pair here, and keep the old `SCHEMA` for `migrate`.
+    #[test]
+    fn every_schema_has_its_version() {
+        let commands = [(1, 0x70e5_df27), (2, 0xa53f_ed68), (3, 0x1211_c4d9)];
+        assert_eq!(schema_hash(v1::SCHEMA), commands[0].1);
+        assert_eq!(schema_hash(v2::SCHEMA), commands[1].1);
+        let current = Envelope::<Commands>::header();
+        assert_eq!(commands.last(), Some(&(current.version, current.schema)));
+
+        let responses = [(1, 0xa50a_8795)];
+        let current = Envelope::<Responses>::header();
+        assert_eq!(responses.last(), Some(&(current.version, current.schema)));
+    }
+
+    /// Fails if a wire type was changed without updating its `SCHEMA`.
+    #[cfg(feature = "schema")]
+    #[test]
+    fn schemas_match_the_types() {
+        use postcard_schema::Schema;
+
+        use crate::commands::{CHUNK_LEN, LABEL_LEN};
+        use crate::schema::describe;
+
+        let bounds = [
+            ("Commands::SetLabel", LABEL_LEN),
+            ("Commands::UploadChunk::data", CHUNK_LEN),
+        ];
+        let envelope = |payload: String| format!("Envelope{{seq:u16,payload:{}}}", payload);
+        assert_eq!(
+            envelope(describe(Commands::SCHEMA, &bounds).unwrap()),
+            <Envelope<Commands> as Message>::SCHEMA
+        );
+        assert_eq!(
+            envelope(describe(Responses::SCHEMA, &[]).unwrap()),
+            <Envelope<Responses> as Message>::SCHEMA
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Check the generated schema files: they are up to date with the wire types,
+//! and the C header compiles and decodes what the Rust side encodes.
+
+use std::fmt::Write;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+
+use postcard_ex::commands::{Commands, Position, Responses, CHUNK_LEN, LABEL_LEN};
+use postcard_ex::schema;
+
+/// Test program for the C header, `CASES` is filled in by `c_program`.
+const C_MAIN: &str = r#"
+#include <stdio.h>
+
+#include "postcard_ex.h"
+
+static void print_commands(const Commands *cmd) {
+    switch (cmd->tag) {
+    case COMMANDS_SET_POSITION:
+        printf("SetPosition %u %u", cmd->value.set_position.x, cmd->value.set_position.y);
+        break;
+    case COMMANDS_QUERY_POSITION:
+        printf("QueryPosition");
+        break;
+    case COMMANDS_SET_TIME:
+        printf("SetTime %llu", (unsigned long long)cmd->value.set_time);
+        break;
+    case COMMANDS_SET_LABEL:
+        printf("SetLabel %.*s", (int)cmd->value.set_label.len, cmd->value.set_label.data);
+        break;
+    case COMMANDS_UPLOAD_CHUNK:
+        printf("UploadChunk %u", cmd->value.upload_chunk.offset);
+        for (size_t it = 0; it < cmd->value.upload_chunk.data.len; it++) {
+            printf(" %02x", cmd->value.upload_chunk.data.data[it]);
+        }
+        break;
+    }
+}
+
+static void print_responses(const Responses *resp) {
+    switch (resp->tag) {
+    case RESPONSES_POSITION_REPORT:
+        printf("PositionReport %u %u", resp->value.position_report.x,
+               resp->value.position_report.y);
+        break;
+    case RESPONSES_ACK:
+        printf("Ack");
+        break;
+    case RESPONSES_NACK:
+        printf("Nack %u", resp->value.nack);
+        break;
+    case RESPONSES_TIME_REPORT:
+        printf("TimeReport %llu", (unsigned long long)resp->value.time_report);
+        break;
+    }
+}
+
+/* Decode, print, and encode again, which must give the same bytes. */
+#define CHECK(type, name, ...)                                                  \
+    do {                                                                        \
+        const uint8_t bytes[1 + 255] = {__VA_ARGS__};                           \
+        type value;                                                             \
+        uint8_t again[255];                                                     \
+        size_t len = name##_decode(&value, bytes + 1, bytes[0]);                \
+        if (len == 0) {                                                         \
+            printf("invalid");                                                  \
+        } else {                                                                \
+            print_##name(&value);                                               \
+            if (len != bytes[0]) {                                              \
+                printf(" (%zu of %u bytes)", len, bytes[0]);                    \
+            }                                                                   \
+            if (name##_encode(&value, again, bytes[0]) != len ||                \
+                memcmp(again, bytes + 1, len) != 0) {                           \
+                printf(" (encoded differently)");                               \
+            }                                                                   \
+        }                                                                       \
+        printf("\n");                                                           \
+    } while (0)
+
+int main(void) {
+CASES
+    return 0;
+}
+"#;
+
+/// Every command, including the longest label and chunk.
+fn commands() -> Vec<Commands> {
+    vec![
+        Commands::SetPosition(Position {
+            x: 123,
+            y: u32::MAX,
+        }),
+        Commands::QueryPosition,
+        Commands::SetTime(u64::MAX),
+        Commands::SetLabel("stage 1".into()),
+        Commands::SetLabel("x".repeat(LABEL_LEN).as_str().into()),
+        Commands::UploadChunk {
+            offset: 64,
+            data: heapless::Vec::from_slice(&[0x00, 0x7f, 0xff]).unwrap(),
+        },
+        Commands::UploadChunk {
+            offset: 0,
+            data: heapless::Vec::from_slice(&[0xab; CHUNK_LEN]).unwrap(),
+        },
+    ]
+}
+
+fn responses() -> Vec<Responses> {
+    vec![
+        Responses::PositionReport(Position { x: 1, y: 2 }),
+        Responses::Ack,
+        Responses::Nack(255),
+        Responses::TimeReport(1234567890),
+    ]
+}
+
+/// What the C program prints for a command.
+fn expected_command(cmd: &Commands) -> String {
+    match cmd {
+        Commands::SetPosition(pos) => format!("SetPosition {} {}", pos.x, pos.y),
+        Commands::QueryPosition => "QueryPosition".to_string(),
+        Commands::SetTime(time) => format!("SetTime {}", time),
+        Commands::SetLabel(label) => format!("SetLabel {}", label),
+        Commands::UploadChunk { offset, data } => data
+            .iter()
+            .fold(format!("UploadChunk {}", offset), |out, byte| {
+                out + &format!(" {:02x}", byte)
+            }),
+    }
+}
+
+fn expected_response(resp: &Responses) -> String {
+    match resp {
+        Responses::PositionReport(pos) => format!("PositionReport {} {}", pos.x, pos.y),
+        Responses::Ack => "Ack".to_string(),
+        Responses::Nack(code) => format!("Nack {}", code),
+        Responses::TimeReport(time) => format!("TimeReport {}", time),
+    }
+}
+
+/// A `CHECK` of `data`, the length is prepended to the bytes.
+fn check(out: &mut String, ty: &str, name: &str, data: &[u8]) {
+    let bytes = std::iter::once(data.len() as u8)
+        .chain(data.iter().copied())
+        .map(|byte| format!("{:#04x}", byte))
+        .collect::<Vec<_>>();
+    writeln!(out, "    CHECK({ty}, {name}, {});", bytes.join(", ")).unwrap();
+}
+
+fn encode<T: serde::Serialize>(value: &T) -> Vec<u8> {
+    postcard::to_slice(value, &mut [0; 256]).unwrap().to_vec()
+}
+
+/// The C test program and the output it should print.
+fn c_program() -> (String, String) {
+    let mut cases = String::new();
+    let mut expected = String::new();
+    for cmd in commands() {
+        check(&mut cases, "Commands", "commands", &encode(&cmd));
+        writeln!(expected, "{}", expected_command(&cmd)).unwrap();
+    }
+    for resp in responses() {
+        check(&mut cases, "Responses", "responses", &encode(&resp));
+        writeln!(expected, "{}", expected_response(&resp)).unwrap();
+    }
+
+    let label = encode(&Commands::SetLabel("x".repeat(LABEL_LEN).as_str().into()));
+    let mut too_long = label.clone();
+    // The length of the label is at byte 1, one byte more than allowed.
+    too_long[1] += 1;
+    too_long.push(b'x');
+    let invalid: [&[u8]; 6] = [
+        &[],
+        &[0x05],
+        &[0x00, 0x7b],
+        &[
+            0x02, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ],
+        &label[..label.len() - 1],
+        &too_long,
+    ];
+    for data in invalid {
+        check(&mut cases, "Commands", "commands", data);
+        writeln!(expected, "invalid").unwrap();
+    }
+    // Trailing bytes are left for the next command.
+    check(&mut cases, "Commands", "commands", &[0x01, 0x01]);
+    writeln!(expected, "QueryPosition (1 of 2 bytes)").unwrap();
+
+    (C_MAIN.replace("CASES", &cases), expected)
+}
+
+#[test]
+fn generated_files_are_up_to_date() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema");
+    for (name, content) in schema::files().unwrap() {
+        let committed = fs::read_to_string(dir.join(name)).unwrap();
+        assert!(
+            committed == content,
+            "schema/{} is outdated, run `cargo run --features schema --bin gen_schema`",
+            name
+        );
+    }
+}
+
+#[test]
+fn c_header_decodes_rust_encodings() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("c_header");
+    fs::create_dir_all(&dir).unwrap();
+    for (name, content) in schema::files().unwrap() {
+        fs::write(dir.join(name), content).unwrap();
+    }
+    let (program, expected) = c_program();
+    fs::write(dir.join("main.c"), program).unwrap();
+
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&cc)
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-o"])
+        .arg(dir.join("main"))
+        .arg(dir.join("main.c"))
+        .status();
+    match status {
+        Ok(status) => assert!(status.success(), "{} failed to compile the header", cc),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            eprintln!("No C compiler `{}` found, skipping the test", cc);
+            return;
+        }
+        Err(err) => panic!("{}: {}", cc, err),
+    }
+
+    let output = Command::new(dir.join("main")).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
@@ -0,0 +1,159 @@
+//! Run the transport over Linux pseudo-terminal pairs: the master side plays
+//! the device, the slave side is opened like a real serial port.
+
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::{AsFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nix::pty::openpty;
+use nix::sys::termios::{self, SetArg};
+use nix::unistd::ttyname;
+
+use postcard_ex::commands::{Commands, Position, Responses};
+use postcard_ex::stream::FrameError;
+use postcard_ex::transport::{open_serial, Reconnecting, Transport, TransportError};
+
+const BAUD: u32 = 115_200;
+const READ_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// A pseudo-terminal in raw mode: the master as file and the path of the slave.
+fn pty() -> (File, PathBuf) {
+    let pty = openpty(None, None).unwrap();
+    let mut tio = termios::tcgetattr(pty.slave.as_fd()).unwrap();
+    termios::cfmakeraw(&mut tio);
+    termios::tcsetattr(pty.slave.as_fd(), SetArg::TCSANOW, &tio).unwrap();
+    let path = ttyname(pty.slave.as_fd()).unwrap();
+    // Keep the slave open, such that the master does not see a hangup before
+    // the port is opened.
+    std::mem::forget::<OwnedFd>(pty.slave);
+    (File::from(pty.master), path)
+}
+
+fn open(path: &Path) -> std::io::Result<Box<dyn serialport::SerialPort>> {
+    open_serial(path.to_str().unwrap(), BAUD, READ_TIMEOUT)
+}
+
+#[test]
+fn commands_and_responses_round_trip() {
+    let (master, path) = pty();
+    let mut device: Transport<File, Commands> = Transport::new(master, Duration::from_secs(2));
+    let mut host: Transport<_, Responses> =
+        Transport::new(open(&path).unwrap(), Duration::from_secs(2));
+
+    let cmds = [
+        Commands::SetPosition(Position { x: 123, y: 456 }),
+        Commands::QueryPosition,
+        Commands::SetTime(1234567890),
+    ];
+    for cmd in cmds {
+        host.send(&cmd).unwrap();
+        assert_eq!(device.receive().unwrap(), cmd);
+    }
+
+    let reply = Responses::PositionReport(Position { x: 1, y: 2 });
+    device.send(&reply).unwrap();
+    assert_eq!(host.receive().unwrap(), reply);
+}
+
+#[test]
+fn receive_times_out() {
+    let (_master, path) = pty();
+    let mut host: Transport<_, Responses> =
+        Transport::new(open(&path).unwrap(), Duration::from_millis(200));
+
+    let start = Instant::now();
+    assert!(matches!(host.receive(), Err(TransportError::Timeout)));
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn invalid_frame_does_not_stop_receiving() {
+    let (mut master, path) = pty();
+    let mut host: Transport<_, Commands> =
+        Transport::new(open(&path).unwrap(), Duration::from_secs(2));
+
+    // Unknown variant 8, then a valid command, in one write.
+    master
+        .write_all(&[0x02, 0x08, 0x00, 0x02, 0x01, 0x00])
+        .unwrap();
+    match host.receive() {
+        Err(TransportError::Frame(FrameError::Decode(err))) => assert_eq!(err.offset(), 0),
+        other => panic!("expected a decode error, got {:?}", other),
+    }
+    assert_eq!(host.receive().unwrap(), Commands::QueryPosition);
+}
+
+#[test]
+fn frames_split_across_writes() {
+    let (mut master, path) = pty();
+    let mut host: Transport<_, Commands> =
+        Transport::new(open(&path).unwrap(), Duration::from_secs(2));
+
+    let frame = [0x07, 0x02, 0xd2, 0x85, 0xd8, 0xcc, 0x04, 0x00];
+    let writer = thread::spawn(move || {
+        for chunk in frame.chunks(3) {
+            master.write_all(chunk).unwrap();
+            thread::sleep(Duration::from_millis(30));
+        }
+        master
+    });
+    assert_eq!(host.receive().unwrap(), Commands::SetTime(1234567890));
+    writer.join().unwrap();
+}
+
+#[test]
+fn reconnects_after_unplug() {
+    let dir = std::env::temp_dir().join(format!("postcard_ex_transport_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let link = dir.join("ttyUSB0");
+    let _ = std::fs::remove_file(&link);
+
+    let (mut master, path) = pty();
+    std::os::unix::fs::symlink(&path, &link).unwrap();
+
+    let connect_link = link.clone();
+    let mut host: Reconnecting<_, _, Commands> =
+        Reconnecting::new(move || open(&connect_link), Duration::from_millis(500));
+    assert!(!host.is_connected());
+
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        master.write_all(&[0x02, 0x01, 0x00]).unwrap();
+        master
+    });
+    assert_eq!(host.receive().unwrap(), Commands::QueryPosition);
+    assert!(host.is_connected());
+
+    // Unplug: the device side goes away.
+    drop(writer.join().unwrap());
+    assert!(matches!(
+        host.receive(),
+        Err(TransportError::Disconnected(_))
+    ));
+    assert!(!host.is_connected());
+
+    // While unplugged, connecting fails.
+    std::fs::remove_file(&link).unwrap();
+    assert!(matches!(
+        host.receive(),
+        Err(TransportError::Disconnected(_))
+    ));
+
+    // Plug in again, the port shows up under the same name.
+    let (mut master, path) = pty();
+    std::os::unix::fs::symlink(&path, &link).unwrap();
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        master.write_all(&[0x02, 0x01, 0x00]).unwrap();
+        master
+    });
+    assert_eq!(host.receive().unwrap(), Commands::QueryPosition);
+    assert!(host.is_connected());
+    writer.join().unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
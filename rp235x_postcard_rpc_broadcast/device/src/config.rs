@@ -0,0 +1,73 @@
+//! The configuration the device starts with.
+//!
+//! The board stores it, see `Board::store_config`. `ConfigControl` keeps the
+//! current one for the handlers and for the tasks that use the calibration.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use template_icd::{Calibration, ConfigResult, DeviceConfig};
+
+use crate::Context;
+
+pub struct ConfigControl {
+    config: Mutex<CriticalSectionRawMutex, RefCell<DeviceConfig>>,
+}
+
+impl ConfigControl {
+    pub const fn new() -> Self {
+        Self {
+            config: Mutex::new(RefCell::new(DeviceConfig::DEFAULT)),
+        }
+    }
+
+    pub fn get(&self) -> DeviceConfig {
+        self.config.lock(|config| config.borrow().clone())
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        self.config.lock(|config| config.borrow().calibration)
+    }
+}
+
+impl Default for ConfigControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Start with the stored configuration, or the default. Called once at boot.
+pub fn load(context: &mut Context) {
+    let config = context
+        .board
+        .load_config()
+        .filter(|config| config.validate().is_ok())
+        .unwrap_or(DeviceConfig::DEFAULT);
+    apply(context, config);
+}
+
+/// Check and store `config`, then apply it.
+pub fn store(context: &mut Context, config: DeviceConfig) -> ConfigResult {
+    config.validate()?;
+    context.board.store_config(&config)?;
+    apply(context, config);
+    Ok(())
+}
+
+/// Erase the stored configuration, then apply the default.
+pub fn reset(context: &mut Context) -> ConfigResult {
+    context.board.erase_config()?;
+    apply(context, DeviceConfig::DEFAULT);
+    Ok(())
+}
+
+/// The broadcast and the LED follow the configuration until they are
+/// changed by their own endpoints.
+fn apply(context: &mut Context, config: DeviceConfig) {
+    context.broadcast.set_config(config.broadcast);
+    context.led.set_mode(config.led);
+    context
+        .config
+        .config
+        .lock(|current| *current.borrow_mut() = config);
+}
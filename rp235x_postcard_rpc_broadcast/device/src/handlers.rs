@@ -0,0 +1,121 @@
+//! Endpoint handlers that do not depend on the executor.
+//!
+//! SPAWN handlers are tasks of the executor, they live in the firmware and
+//! in the simulator. Every endpoint handler counts its request in `Stats`.
+
+use postcard_rpc::header::VarHeader;
+use postcard_rpc::server::{Sender, WireTx};
+use template_icd::{
+    AdcResult, BroadcastConfig, BroadcastInterval, BroadcastIntervalResult, ConfigResult,
+    DeviceConfig, GpioResult, LedMode, LedModeResult, LedState, PinConfig, PinLevelResult,
+    PinWrite, Setpoint,
+};
+
+use crate::{config, Context};
+
+/// This is an example of a BLOCKING handler.
+pub fn unique_id(context: &mut Context, _header: VarHeader, _arg: ()) -> u64 {
+    context.stats.count_request();
+    context.unique_id
+}
+
+/// Also a BLOCKING handler
+pub fn picoboot_reset(context: &mut Context, _header: VarHeader, _arg: ()) {
+    context.stats.count_request();
+    context.board.reboot_to_picoboot();
+}
+
+/// Also a BLOCKING handler
+pub fn set_led(context: &mut Context, _header: VarHeader, arg: LedState) {
+    context.stats.count_request();
+    context.led.set_mode(arg.into());
+}
+
+pub fn set_led_mode(context: &mut Context, _header: VarHeader, arg: LedMode) -> LedModeResult {
+    context.stats.count_request();
+    arg.validate()?;
+    context.led.set_mode(arg);
+    Ok(())
+}
+
+pub fn get_led(context: &mut Context, _header: VarHeader, _arg: ()) -> LedMode {
+    context.stats.count_request();
+    context.led.mode()
+}
+
+pub fn start_broadcast(context: &mut Context, _header: VarHeader, _arg: ()) {
+    context.stats.count_request();
+    context.broadcast.set_enabled(true);
+}
+
+pub fn stop_broadcast(context: &mut Context, _header: VarHeader, _arg: ()) {
+    context.stats.count_request();
+    context.broadcast.set_enabled(false);
+}
+
+pub fn set_broadcast_interval(
+    context: &mut Context,
+    _header: VarHeader,
+    arg: BroadcastInterval,
+) -> BroadcastIntervalResult {
+    context.stats.count_request();
+    context.broadcast.set_interval(arg.millis)
+}
+
+pub fn get_broadcast_config(
+    context: &mut Context,
+    _header: VarHeader,
+    _arg: (),
+) -> BroadcastConfig {
+    context.stats.count_request();
+    context.broadcast.config()
+}
+
+pub fn configure_pin(context: &mut Context, _header: VarHeader, arg: PinConfig) -> GpioResult {
+    context.stats.count_request();
+    context.board.configure_pin(arg.pin, arg.mode)
+}
+
+pub fn set_pin(context: &mut Context, _header: VarHeader, arg: PinWrite) -> GpioResult {
+    context.stats.count_request();
+    context.board.set_pin(arg.pin, arg.level)
+}
+
+pub fn get_pin(context: &mut Context, _header: VarHeader, arg: u8) -> PinLevelResult {
+    context.stats.count_request();
+    context.board.pin_level(arg)
+}
+
+pub fn read_adc(context: &mut Context, _header: VarHeader, arg: u8) -> AdcResult {
+    context.stats.count_request();
+    context.board.adc(arg)
+}
+
+pub fn get_config(context: &mut Context, _header: VarHeader, _arg: ()) -> DeviceConfig {
+    context.stats.count_request();
+    context.config.get()
+}
+
+/// Writes the flash, which stalls the device for a few milliseconds.
+pub fn set_config(context: &mut Context, _header: VarHeader, arg: DeviceConfig) -> ConfigResult {
+    context.stats.count_request();
+    config::store(context, arg)
+}
+
+pub fn reset_config(context: &mut Context, _header: VarHeader, _arg: ()) -> ConfigResult {
+    context.stats.count_request();
+    config::reset(context)
+}
+
+/// A BLOCKING topic handler, the host does not get a reply.
+///
+/// Setpoints of unknown channels are dropped, the host sees the applied ones
+/// in `CtrlStatus::setpoints`.
+pub fn setpoint<Tx: WireTx>(
+    context: &mut Context,
+    _header: VarHeader,
+    msg: Setpoint,
+    _sender: &Sender<Tx>,
+) {
+    context.setpoints.set(msg);
+}
@@ -0,0 +1,127 @@
+//! The mode of the LED, and the brightness steps it consists of.
+//!
+//! The handlers change the mode in `LedControl`, the LED task of the firmware
+//! (or of the simulator) plays the `Pattern` of the mode and starts over when
+//! the mode changes.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_sync::signal::Signal;
+use template_icd::LedMode;
+
+/// Duration of one beat of `LedMode::Heartbeat`.
+const BEAT_MS: u32 = 100;
+
+/// The mode of the LED, shared by the handlers and the LED task.
+pub struct LedControl {
+    mode: Mutex<CriticalSectionRawMutex, Cell<LedMode>>,
+    changed: Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl LedControl {
+    pub const fn new(mode: LedMode) -> Self {
+        Self {
+            mode: Mutex::new(Cell::new(mode)),
+            changed: Signal::new(),
+        }
+    }
+
+    pub fn mode(&self) -> LedMode {
+        self.mode.lock(|mode| mode.get())
+    }
+
+    /// The mode must be valid, see `LedMode::validate`.
+    pub fn set_mode(&self, mode: LedMode) {
+        self.mode.lock(|cell| cell.set(mode));
+        self.changed.signal(());
+    }
+
+    /// Called by the LED task when the pattern of `mode` ended: switches the
+    /// LED off, unless the mode was changed in the meantime.
+    pub fn finished(&self, mode: LedMode) {
+        self.mode.lock(|cell| {
+            if cell.get() == mode {
+                cell.set(LedMode::OFF);
+            }
+        });
+    }
+
+    /// Wait until the mode changes. Only the LED task may wait.
+    pub async fn changed(&self) {
+        self.changed.wait().await
+    }
+}
+
+/// Keep the LED at `brightness` percent for `duration_ms`, or until the mode
+/// changes if `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    pub brightness: u8,
+    pub duration_ms: Option<u32>,
+}
+
+/// The steps of a mode, ends after the last pulse of `LedMode::Pulses`.
+pub struct Pattern {
+    mode: LedMode,
+    index: u32,
+}
+
+impl Pattern {
+    pub fn new(mode: LedMode) -> Self {
+        Self { mode, index: 0 }
+    }
+}
+
+impl Iterator for Pattern {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Step> {
+        let index = self.index;
+        self.index = self.index.wrapping_add(1);
+        match self.mode {
+            LedMode::Steady { brightness } => (index == 0).then_some(Step {
+                brightness,
+                duration_ms: None,
+            }),
+            LedMode::Blink {
+                brightness,
+                period_ms,
+                duty,
+            } => Some(blink(brightness, period_ms, duty, index)),
+            LedMode::Pulses {
+                brightness,
+                period_ms,
+                duty,
+                count,
+            } => (index < 2 * u32::from(count)).then(|| blink(brightness, period_ms, duty, index)),
+            LedMode::Heartbeat {
+                brightness,
+                period_ms,
+            } => {
+                let (brightness, duration_ms) = match index % 4 {
+                    0 | 2 => (brightness, BEAT_MS),
+                    1 => (0, BEAT_MS),
+                    _ => (0, u32::from(period_ms).saturating_sub(3 * BEAT_MS)),
+                };
+                Some(Step {
+                    brightness,
+                    duration_ms: Some(duration_ms),
+                })
+            }
+        }
+    }
+}
+
+/// On for the duty cycle, then off for the rest of the period.
+fn blink(brightness: u8, period_ms: u16, duty: u8, index: u32) -> Step {
+    let on_ms = u32::from(period_ms) * u32::from(duty) / 100;
+    let (brightness, duration_ms) = match index % 2 {
+        0 => (brightness, on_ms),
+        _ => (0, u32::from(period_ms) - on_ms),
+    };
+    Step {
+        brightness,
+        duration_ms: Some(duration_ms),
+    }
+}
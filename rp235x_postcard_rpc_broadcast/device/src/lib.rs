@@ -0,0 +1,197 @@
+//! Hardware independent part of the device: the context and the handlers.
+//!
+//! The firmware in `rp2350` runs them on the Pico 2 board, the simulator in
+//! `sim` on a std host. Everything the handlers need from the hardware goes
+//! through the `Board` trait, except for the LED, which is driven by a task
+//! following `LedControl`.
+
+#![no_std]
+
+pub mod config;
+pub mod handlers;
+pub mod led;
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use postcard_rpc::server::SpawnContext;
+use template_icd::{
+    check_broadcast_interval, AdcResult, BroadcastConfig, BroadcastIntervalResult, ConfigResult,
+    DeviceConfig, GpioResult, PinLevel, PinLevelResult, PinMode, Setpoint, SETPOINT_CHANNELS,
+};
+
+use crate::config::ConfigControl;
+use crate::led::LedControl;
+
+/// The hardware, as seen by the handlers.
+pub trait Board {
+    /// Reboot into the USB bootloader. Does not return on real hardware.
+    fn reboot_to_picoboot(&mut self);
+
+    /// Fails with `GpioError::NotAllowed` for pins that are not on the
+    /// allowlist of the board, as do the other pin functions.
+    fn configure_pin(&mut self, pin: u8, mode: PinMode) -> GpioResult;
+    /// Fails with `GpioError::NotAnOutput` for inputs.
+    fn set_pin(&mut self, pin: u8, level: PinLevel) -> GpioResult;
+    /// The level at the pin, of inputs and outputs.
+    fn pin_level(&self, pin: u8) -> PinLevelResult;
+    /// The latest reading of an ADC channel.
+    fn adc(&self, channel: u8) -> AdcResult;
+
+    /// The stored configuration, `None` if there is none or it cannot be read.
+    fn load_config(&mut self) -> Option<DeviceConfig>;
+    fn store_config(&mut self, config: &DeviceConfig) -> ConfigResult;
+    /// Remove the stored configuration, the device starts with the default.
+    fn erase_config(&mut self) -> ConfigResult;
+}
+
+/// Context contains the data that we will pass (as a mutable reference)
+/// to each endpoint or topic handler
+pub struct Context {
+    /// We'll use this unique ID to identify ourselves to the poststation
+    /// server. This should be unique per device.
+    pub unique_id: u64,
+    pub board: &'static mut (dyn Board + Send),
+    pub stats: &'static Stats,
+    pub broadcast: &'static BroadcastControl,
+    pub setpoints: &'static Setpoints,
+    pub led: &'static LedControl,
+    pub config: &'static ConfigControl,
+}
+
+impl SpawnContext for Context {
+    type SpawnCtxt = TaskContext;
+
+    fn spawn_ctxt(&mut self) -> Self::SpawnCtxt {
+        TaskContext {
+            unique_id: self.unique_id,
+            stats: self.stats,
+        }
+    }
+}
+
+pub struct TaskContext {
+    pub unique_id: u64,
+    pub stats: &'static Stats,
+}
+
+/// Counters for the status broadcast, shared by the handlers and the
+/// broadcasting task.
+#[derive(Debug, Default)]
+pub struct Stats {
+    requests: AtomicU32,
+}
+
+impl Stats {
+    pub const fn new() -> Self {
+        Self {
+            requests: AtomicU32::new(0),
+        }
+    }
+
+    /// Called by every handler.
+    pub fn count_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of requests handled since boot.
+    pub fn requests(&self) -> u32 {
+        self.requests.load(Ordering::Relaxed)
+    }
+}
+
+/// Configuration of the status broadcast, changed by the handlers while the
+/// broadcasting task runs.
+pub struct BroadcastControl {
+    enabled: AtomicBool,
+    interval_ms: AtomicU32,
+    changed: Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl BroadcastControl {
+    pub const fn new(config: BroadcastConfig) -> Self {
+        Self {
+            enabled: AtomicBool::new(config.enabled),
+            interval_ms: AtomicU32::new(config.interval_ms),
+            changed: Signal::new(),
+        }
+    }
+
+    pub fn config(&self) -> BroadcastConfig {
+        BroadcastConfig {
+            enabled: self.enabled.load(Ordering::Relaxed),
+            interval_ms: self.interval_ms.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The interval must be valid, see `check_broadcast_interval`.
+    pub fn set_config(&self, config: BroadcastConfig) {
+        self.enabled.store(config.enabled, Ordering::Relaxed);
+        self.interval_ms
+            .store(config.interval_ms, Ordering::Relaxed);
+        self.changed.signal(());
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        self.changed.signal(());
+    }
+
+    /// Fails if `millis` is not within `BROADCAST_INTERVAL_MIN_MS` and
+    /// `BROADCAST_INTERVAL_MAX_MS`.
+    pub fn set_interval(&self, millis: u32) -> BroadcastIntervalResult {
+        check_broadcast_interval(millis)?;
+        self.interval_ms.store(millis, Ordering::Relaxed);
+        self.changed.signal(());
+        Ok(self.config())
+    }
+
+    /// Wait until the configuration changes. Only the broadcasting task may
+    /// wait, it restarts its timer with the new `config()`.
+    pub async fn changed(&self) {
+        self.changed.wait().await
+    }
+}
+
+/// The latest setpoint per channel, written by the topic handler and read by
+/// the control loops and the status broadcast.
+pub struct Setpoints {
+    /// The bits of the `f32` values.
+    values: [AtomicU32; SETPOINT_CHANNELS],
+}
+
+impl Default for Setpoints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Setpoints {
+    /// All channels start at zero.
+    pub const fn new() -> Self {
+        Self {
+            values: [const { AtomicU32::new(0) }; SETPOINT_CHANNELS],
+        }
+    }
+
+    /// Returns `false` if the channel does not exist.
+    pub fn set(&self, setpoint: Setpoint) -> bool {
+        match self.values.get(usize::from(setpoint.channel)) {
+            Some(value) => {
+                value.store(setpoint.value.to_bits(), Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, channel: usize) -> Option<f32> {
+        let value = self.values.get(channel)?;
+        Some(f32::from_bits(value.load(Ordering::Relaxed)))
+    }
+
+    pub fn all(&self) -> [f32; SETPOINT_CHANNELS] {
+        core::array::from_fn(|channel| f32::from_bits(self.values[channel].load(Ordering::Relaxed)))
+    }
+}
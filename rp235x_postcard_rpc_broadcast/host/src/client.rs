@@ -0,0 +1,188 @@
+//! Typed access to the endpoints and topics of the `template-icd`.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use postcard_rpc::header::{VarSeq, VarSeqKind};
+use postcard_rpc::host_client::{HostClient, HostErr, MultiSubscription};
+use postcard_rpc::standard_icd::{LoggingTopic, WireError, ERROR_PATH};
+use template_icd::{
+    AdcResult, BcCtrlStatus, BroadcastConfig, BroadcastInterval, BroadcastIntervalResult,
+    ConfigResult, ConfigurePinEndpoint, CtrlStatus, DeviceConfig, GetBroadcastConfigEndpoint,
+    GetConfigEndpoint, GetLedEndpoint, GetPinEndpoint, GetUniqueIdEndpoint, GpioResult, LedMode,
+    LedModeResult, LedState, PinConfig, PinLevel, PinLevelResult, PinMode, PinWrite,
+    ReadAdcEndpoint, RebootToPicoBoot, ResetConfigEndpoint, SetBroadcastIntervalEndpoint,
+    SetConfigEndpoint, SetLedEndpoint, SetLedModeEndpoint, SetPinEndpoint, Setpoint, SetpointTopic,
+    SleepEndpoint, SleepMillis, StartBroadcastEndpoint, StopBroadcastEndpoint,
+};
+
+/// USB vendor and product id of the firmware, see `usb_config` in `rp2350/src/main.rs`.
+const VID: u16 = 0x16c0;
+const PID: u16 = 0x27dd;
+
+/// Number of messages kept per subscription until they are received.
+const SUBSCRIPTION_DEPTH: usize = 64;
+
+pub type ClientError = HostErr<WireError>;
+
+pub struct Client {
+    pub client: HostClient<WireError>,
+    /// Sequence number of the next published message.
+    seq: AtomicU32,
+}
+
+impl Client {
+    pub fn new(client: HostClient<WireError>) -> Self {
+        Self {
+            client,
+            seq: AtomicU32::new(0),
+        }
+    }
+
+    /// Connect to the first device, or to the one with the given serial number.
+    ///
+    /// The serial number is the unique id in hex, as shown by `unique-id`.
+    pub fn connect(serial: Option<&str>) -> Result<Self, String> {
+        let client = HostClient::try_new_raw_nusb(
+            |dev| {
+                dev.vendor_id() == VID
+                    && dev.product_id() == PID
+                    && serial.is_none_or(|serial| dev.serial_number() == Some(serial))
+            },
+            ERROR_PATH,
+            8,
+            VarSeqKind::Seq2,
+        )?;
+        Ok(Self::new(client))
+    }
+
+    pub async fn unique_id(&self) -> Result<u64, ClientError> {
+        self.client.send_resp::<GetUniqueIdEndpoint>(&()).await
+    }
+
+    pub async fn set_led(&self, state: LedState) -> Result<(), ClientError> {
+        self.client.send_resp::<SetLedEndpoint>(&state).await
+    }
+
+    /// Brightness or blink pattern, the device rejects modes that fail
+    /// `LedMode::validate`.
+    pub async fn set_led_mode(&self, mode: LedMode) -> Result<LedModeResult, ClientError> {
+        self.client.send_resp::<SetLedModeEndpoint>(&mode).await
+    }
+
+    pub async fn get_led(&self) -> Result<LedMode, ClientError> {
+        self.client.send_resp::<GetLedEndpoint>(&()).await
+    }
+
+    /// Let the device sleep, returns how long it actually slept.
+    pub async fn sleep(&self, millis: u16) -> Result<u16, ClientError> {
+        let slept = self
+            .client
+            .send_resp::<SleepEndpoint>(&SleepMillis { millis })
+            .await?;
+        Ok(slept.millis)
+    }
+
+    /// Reboot into the USB bootloader.
+    ///
+    /// The device reboots without replying, so the request only fails if it
+    /// could not be sent. Waits up to `timeout` for a reply anyway.
+    pub async fn picoboot_reset(&self, timeout: Duration) -> Result<(), ClientError> {
+        let reply = self.client.send_resp::<RebootToPicoBoot>(&());
+        match tokio::time::timeout(timeout, reply).await {
+            // Timed out, or the device disconnected while rebooting.
+            Err(_) | Ok(Err(HostErr::Closed)) => Ok(()),
+            Ok(result) => result,
+        }
+    }
+
+    pub async fn start_broadcast(&self) -> Result<(), ClientError> {
+        self.client.send_resp::<StartBroadcastEndpoint>(&()).await
+    }
+
+    pub async fn stop_broadcast(&self) -> Result<(), ClientError> {
+        self.client.send_resp::<StopBroadcastEndpoint>(&()).await
+    }
+
+    /// Change the interval of the status broadcast, the device rejects
+    /// intervals out of `BROADCAST_INTERVAL_MIN_MS..=BROADCAST_INTERVAL_MAX_MS`.
+    pub async fn set_broadcast_interval(
+        &self,
+        millis: u32,
+    ) -> Result<BroadcastIntervalResult, ClientError> {
+        self.client
+            .send_resp::<SetBroadcastIntervalEndpoint>(&BroadcastInterval { millis })
+            .await
+    }
+
+    pub async fn broadcast_config(&self) -> Result<BroadcastConfig, ClientError> {
+        self.client
+            .send_resp::<GetBroadcastConfigEndpoint>(&())
+            .await
+    }
+
+    /// The device only allows the pins on its allowlist.
+    pub async fn configure_pin(&self, pin: u8, mode: PinMode) -> Result<GpioResult, ClientError> {
+        self.client
+            .send_resp::<ConfigurePinEndpoint>(&PinConfig { pin, mode })
+            .await
+    }
+
+    /// The pin must be configured as output.
+    pub async fn set_pin(&self, pin: u8, level: PinLevel) -> Result<GpioResult, ClientError> {
+        self.client
+            .send_resp::<SetPinEndpoint>(&PinWrite { pin, level })
+            .await
+    }
+
+    pub async fn get_pin(&self, pin: u8) -> Result<PinLevelResult, ClientError> {
+        self.client.send_resp::<GetPinEndpoint>(&pin).await
+    }
+
+    /// The latest raw reading of ADC `channel`, sampled every 100 ms.
+    pub async fn read_adc(&self, channel: u8) -> Result<AdcResult, ClientError> {
+        self.client.send_resp::<ReadAdcEndpoint>(&channel).await
+    }
+
+    /// The configuration the device starts with.
+    pub async fn get_config(&self) -> Result<DeviceConfig, ClientError> {
+        self.client.send_resp::<GetConfigEndpoint>(&()).await
+    }
+
+    /// Store the configuration in the flash of the device, and apply it.
+    pub async fn set_config(&self, config: &DeviceConfig) -> Result<ConfigResult, ClientError> {
+        self.client.send_resp::<SetConfigEndpoint>(config).await
+    }
+
+    /// Erase the configuration in the flash, and apply the default.
+    pub async fn reset_config(&self) -> Result<ConfigResult, ClientError> {
+        self.client.send_resp::<ResetConfigEndpoint>(&()).await
+    }
+
+    /// Send a new setpoint to the device, without waiting for a reply.
+    ///
+    /// Setpoints of channels the device does not have are dropped by the
+    /// device, see `CtrlStatus::setpoints` for the applied ones.
+    pub async fn publish_setpoint(&self, channel: u8, value: f32) -> Result<(), ClientError> {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        self.client
+            .publish::<SetpointTopic>(VarSeq::Seq4(seq), &Setpoint { channel, value })
+            .await
+            .map_err(|_| HostErr::Closed)
+    }
+
+    pub async fn subscribe_status(&self) -> Result<MultiSubscription<CtrlStatus>, ClientError> {
+        self.client
+            .subscribe_multi::<BcCtrlStatus>(SUBSCRIPTION_DEPTH)
+            .await
+            .map_err(|_| HostErr::Closed)
+    }
+
+    /// Log strings of the device, e.g., from `sender.log_str`.
+    pub async fn subscribe_logs(&self) -> Result<MultiSubscription<String>, ClientError> {
+        self.client
+            .subscribe_multi::<LoggingTopic>(SUBSCRIPTION_DEPTH)
+            .await
+            .map_err(|_| HostErr::Closed)
+    }
+}
@@ -0,0 +1,4 @@
+//! Client for the `template-rp2350` firmware, used by the command line tool
+//! and by the tests of the simulator.
+
+pub mod client;
@@ -0,0 +1,534 @@
+//! Command line client for the `template-rp2350` firmware, talks to the
+//! device directly over USB, without poststation.
+
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use template_host::client::{self, Client};
+use template_icd::{BroadcastConfig, DeviceConfig, LedMode, LedState, PinLevel, PinMode};
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Serial number of the device, the first one found is used if not given
+    #[arg(long, global = true)]
+    serial: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the unique id of the device
+    UniqueId,
+    /// Switch the LED on or off, dim it, let it blink, or print its mode
+    Led {
+        #[command(subcommand)]
+        command: LedCommand,
+    },
+    /// Let the device sleep and print how long it slept
+    Sleep {
+        /// Milliseconds to sleep
+        millis: u16,
+    },
+    /// Start, stop or configure the status broadcasts
+    Broadcast {
+        #[command(subcommand)]
+        command: BroadcastCommand,
+    },
+    /// Configure, set or read a GPIO of the allowlist of the device
+    Gpio {
+        #[command(subcommand)]
+        command: GpioCommand,
+    },
+    /// Print the latest reading of an ADC channel
+    Adc {
+        /// Channel 0 to 2, on GPIO 26 to 28
+        channel: u8,
+    },
+    /// Print, change or reset the configuration the device starts with
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Send a setpoint to the device, it does not reply
+    Setpoint {
+        /// Channel of the setpoint, from 0
+        channel: u8,
+        /// The new value
+        #[arg(allow_negative_numbers = true)]
+        value: f32,
+    },
+    /// Reboot the device into the USB bootloader, e.g., to flash it with picotool
+    Picoboot,
+    /// Print status broadcasts and log messages of the device until Ctrl-C
+    Listen {
+        /// Only print the status broadcasts
+        #[arg(long, conflicts_with = "logs_only")]
+        status_only: bool,
+        /// Only print the log messages
+        #[arg(long)]
+        logs_only: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum LedCommand {
+    /// Switch the LED
+    Set { state: Led },
+    #[command(flatten)]
+    Mode(LedModeCommand),
+    /// Print the mode of the LED
+    Get,
+}
+
+#[derive(Subcommand)]
+enum LedModeCommand {
+    /// Keep the LED on at the given brightness
+    Brightness {
+        /// Percent, zero is off
+        percent: u8,
+    },
+    /// Blink until the mode is changed
+    Blink {
+        #[command(flatten)]
+        blink: BlinkArgs,
+    },
+    /// Blink a number of times, then switch off
+    Pulses {
+        /// Number of pulses
+        count: u16,
+        #[command(flatten)]
+        blink: BlinkArgs,
+    },
+    /// Two short beats per period
+    Heartbeat {
+        /// Brightness in percent
+        #[arg(long, default_value_t = 100)]
+        brightness: u8,
+        /// Milliseconds from one heartbeat to the next
+        #[arg(long, default_value_t = 1000)]
+        period_ms: u16,
+    },
+}
+
+#[derive(Args)]
+struct BlinkArgs {
+    /// Brightness in percent
+    #[arg(long, default_value_t = 100)]
+    brightness: u8,
+    /// Milliseconds from one pulse to the next
+    #[arg(long, default_value_t = 500)]
+    period_ms: u16,
+    /// Percent of the period the LED is on
+    #[arg(long, default_value_t = 50)]
+    duty: u8,
+}
+
+#[derive(Subcommand)]
+enum GpioCommand {
+    /// Configure the pin as input
+    Input {
+        pin: u8,
+        #[arg(long, value_enum, default_value_t = Pull::None)]
+        pull: Pull,
+    },
+    /// Configure the pin as output
+    Output { pin: u8, level: Level },
+    /// Set the level of an output
+    Set { pin: u8, level: Level },
+    /// Print the level at the pin
+    Get { pin: u8 },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Level {
+    Low,
+    High,
+}
+
+impl From<Level> for PinLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Low => PinLevel::Low,
+            Level::High => PinLevel::High,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Pull {
+    None,
+    Up,
+    Down,
+}
+
+impl From<Pull> for template_icd::Pull {
+    fn from(pull: Pull) -> Self {
+        match pull {
+            Pull::None => template_icd::Pull::None,
+            Pull::Up => template_icd::Pull::Up,
+            Pull::Down => template_icd::Pull::Down,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the configuration
+    Get,
+    /// Change the given settings, and keep the others
+    Set(ConfigArgs),
+    /// Go back to the default configuration
+    Reset,
+}
+
+#[derive(Args)]
+struct ConfigArgs {
+    /// Name of the device, up to 32 bytes
+    #[arg(long)]
+    nickname: Option<String>,
+    /// Whether the device broadcasts its status after a reboot
+    #[arg(long)]
+    broadcast: Option<bool>,
+    /// Milliseconds between two status broadcasts
+    #[arg(long)]
+    broadcast_interval_ms: Option<u32>,
+    /// Brightness of the LED after a reboot, in percent
+    #[arg(long)]
+    led_brightness: Option<u8>,
+    /// Added to the measured chip temperature, in degrees Celsius
+    #[arg(long, allow_negative_numbers = true)]
+    temperature_offset: Option<f32>,
+    /// Gains of ADC0 to ADC2, e.g., 1.0,1.0,0.5
+    #[arg(long, value_delimiter = ',')]
+    adc_gain: Option<Vec<f32>>,
+    /// Offsets of ADC0 to ADC2
+    // `allow_negative_numbers` does not accept a list like -1,0,2.
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    adc_offset: Option<Vec<f32>>,
+}
+
+impl ConfigArgs {
+    fn apply(self, config: &mut DeviceConfig) -> Result<(), String> {
+        if let Some(nickname) = self.nickname {
+            config.nickname = nickname
+                .as_str()
+                .try_into()
+                .map_err(|_| "the nickname is too long".to_string())?;
+        }
+        if let Some(enabled) = self.broadcast {
+            config.broadcast.enabled = enabled;
+        }
+        if let Some(millis) = self.broadcast_interval_ms {
+            config.broadcast.interval_ms = millis;
+        }
+        if let Some(brightness) = self.led_brightness {
+            config.led = LedMode::Steady { brightness };
+        }
+        if let Some(offset) = self.temperature_offset {
+            config.calibration.temperature_offset = offset;
+        }
+        if let Some(gain) = self.adc_gain {
+            config.calibration.adc_gain = three(gain)?;
+        }
+        if let Some(offset) = self.adc_offset {
+            config.calibration.adc_offset = three(offset)?;
+        }
+        Ok(())
+    }
+}
+
+/// One value per ADC channel.
+fn three(values: Vec<f32>) -> Result<[f32; 3], String> {
+    values
+        .try_into()
+        .map_err(|_| "expected three values, one per ADC channel".to_string())
+}
+
+#[derive(Subcommand)]
+enum BroadcastCommand {
+    /// Start broadcasting
+    Start,
+    /// Stop broadcasting
+    Stop,
+    /// Set the interval between two broadcasts
+    Interval {
+        /// Milliseconds between two broadcasts
+        millis: u32,
+    },
+    /// Print whether the device broadcasts, and how often
+    Get,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Led {
+    Off,
+    On,
+}
+
+impl From<Led> for LedState {
+    fn from(led: Led) -> Self {
+        match led {
+            Led::Off => LedState::Off,
+            Led::On => LedState::On,
+        }
+    }
+}
+
+impl From<LedModeCommand> for LedMode {
+    fn from(command: LedModeCommand) -> Self {
+        match command {
+            LedModeCommand::Brightness { percent } => LedMode::Steady {
+                brightness: percent,
+            },
+            LedModeCommand::Blink { blink } => LedMode::Blink {
+                brightness: blink.brightness,
+                period_ms: blink.period_ms,
+                duty: blink.duty,
+            },
+            LedModeCommand::Pulses { count, blink } => LedMode::Pulses {
+                brightness: blink.brightness,
+                period_ms: blink.period_ms,
+                duty: blink.duty,
+                count,
+            },
+            LedModeCommand::Heartbeat {
+                brightness,
+                period_ms,
+            } => LedMode::Heartbeat {
+                brightness,
+                period_ms,
+            },
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let client = match Client::connect(cli.serial.as_deref()) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("Error: cannot connect to the device: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&client, cli.command).await {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(client: &Client, command: Command) -> Result<ExitCode, client::ClientError> {
+    match command {
+        Command::UniqueId => println!("{:016X}", client.unique_id().await?),
+        Command::Led { command } => match command {
+            LedCommand::Set { state } => client.set_led(state.into()).await?,
+            LedCommand::Mode(mode) => {
+                if let Err(err) = client.set_led_mode(mode.into()).await? {
+                    eprintln!("Error: invalid LED mode: {:?}", err);
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+            LedCommand::Get => println!("{:?}", client.get_led().await?),
+        },
+        Command::Sleep { millis } => {
+            println!("Slept {} ms", client.sleep(millis).await?);
+        }
+        Command::Broadcast { command } => match command {
+            BroadcastCommand::Start => client.start_broadcast().await?,
+            BroadcastCommand::Stop => client.stop_broadcast().await?,
+            BroadcastCommand::Interval { millis } => {
+                match client.set_broadcast_interval(millis).await? {
+                    Ok(config) => print_broadcast(&config),
+                    Err(range) => {
+                        eprintln!(
+                            "Error: the interval must be between {} and {} ms",
+                            range.min_ms, range.max_ms
+                        );
+                        return Ok(ExitCode::FAILURE);
+                    }
+                }
+            }
+            BroadcastCommand::Get => print_broadcast(&client.broadcast_config().await?),
+        },
+        Command::Gpio { command } => {
+            let result = match command {
+                GpioCommand::Input { pin, pull } => {
+                    let mode = PinMode::Input(pull.into());
+                    client.configure_pin(pin, mode).await?
+                }
+                GpioCommand::Output { pin, level } => {
+                    let mode = PinMode::Output(level.into());
+                    client.configure_pin(pin, mode).await?
+                }
+                GpioCommand::Set { pin, level } => client.set_pin(pin, level.into()).await?,
+                GpioCommand::Get { pin } => client
+                    .get_pin(pin)
+                    .await?
+                    .map(|level| println!("{:?}", level)),
+            };
+            if let Err(err) = result {
+                eprintln!("Error: {:?}", err);
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Adc { channel } => match client.read_adc(channel).await? {
+            Ok(value) => println!("{}", value),
+            Err(err) => {
+                eprintln!("Error: {:?}", err);
+                return Ok(ExitCode::FAILURE);
+            }
+        },
+        Command::Config { command } => {
+            let result = match command {
+                ConfigCommand::Get => {
+                    println!("{:#?}", client.get_config().await?);
+                    Ok(())
+                }
+                ConfigCommand::Set(args) => {
+                    let mut config = client.get_config().await?;
+                    if let Err(err) = args.apply(&mut config) {
+                        eprintln!("Error: {}", err);
+                        return Ok(ExitCode::FAILURE);
+                    }
+                    client.set_config(&config).await?
+                }
+                ConfigCommand::Reset => client.reset_config().await?,
+            };
+            if let Err(err) = result {
+                eprintln!("Error: {:?}", err);
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Setpoint { channel, value } => client.publish_setpoint(channel, value).await?,
+        Command::Picoboot => {
+            client.picoboot_reset(Duration::from_millis(500)).await?;
+            println!("Rebooting into the bootloader");
+        }
+        Command::Listen {
+            status_only,
+            logs_only,
+        } => listen(client, !logs_only, !status_only).await?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_broadcast(config: &BroadcastConfig) {
+    let state = if config.enabled { "on" } else { "off" };
+    println!("{}, every {} ms", state, config.interval_ms);
+}
+
+async fn listen(client: &Client, status: bool, logs: bool) -> Result<(), client::ClientError> {
+    let mut status_sub = client.subscribe_status().await?;
+    let mut logs_sub = client.subscribe_logs().await?;
+    loop {
+        tokio::select! {
+            msg = status_sub.recv(), if status => match msg {
+                Ok(msg) => println!("status: {:?}", msg),
+                Err(_) => break,
+            },
+            msg = logs_sub.recv(), if logs => match msg {
+                Ok(msg) => println!("log: {}", msg),
+                Err(_) => break,
+            },
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+    }
+    eprintln!("Device disconnected");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Command, clap::Error> {
+        Cli::try_parse_from(std::iter::once("template-host").chain(args.iter().copied()))
+            .map(|cli| cli.command)
+    }
+
+    fn config_set(args: &[&str]) -> Result<DeviceConfig, String> {
+        let args = [&["config", "set"], args].concat();
+        let Command::Config {
+            command: ConfigCommand::Set(set),
+        } = parse(&args).unwrap()
+        else {
+            panic!("not `config set`");
+        };
+        let mut config = DeviceConfig::default();
+        set.apply(&mut config).map(|()| config)
+    }
+
+    #[test]
+    fn cli_is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn negative_numbers() {
+        assert!(matches!(
+            parse(&["setpoint", "1", "-2.5"]),
+            Ok(Command::Setpoint { channel: 1, value }) if value == -2.5
+        ));
+        let config = config_set(&["--temperature-offset", "-1.5"]).unwrap();
+        assert_eq!(config.calibration.temperature_offset, -1.5);
+    }
+
+    #[test]
+    fn config_set_keeps_other_settings() {
+        let config = config_set(&["--nickname", "stage", "--led-brightness", "20"]).unwrap();
+        assert_eq!(config.nickname.as_str(), "stage");
+        assert_eq!(config.led, LedMode::Steady { brightness: 20 });
+        let default = DeviceConfig::default();
+        assert_eq!(config.broadcast, default.broadcast);
+        assert_eq!(config.calibration, default.calibration);
+    }
+
+    #[test]
+    fn config_set_negative_offsets() {
+        let config =
+            config_set(&["--temperature-offset", "-1.5", "--adc-offset", "-1,0,2"]).unwrap();
+        assert_eq!(config.calibration.temperature_offset, -1.5);
+        assert_eq!(config.calibration.adc_offset, [-1.0, 0.0, 2.0]);
+    }
+
+    #[test]
+    fn config_set_rejects_invalid_values() {
+        assert_eq!(
+            config_set(&["--nickname", &"x".repeat(33)]),
+            Err("the nickname is too long".to_string())
+        );
+        assert_eq!(
+            config_set(&["--adc-gain", "1,2"]),
+            Err("expected three values, one per ADC channel".to_string())
+        );
+        assert!(parse(&["listen", "--status-only", "--logs-only"]).is_err());
+    }
+
+    #[test]
+    fn led_modes() {
+        let Command::Led {
+            command: LedCommand::Mode(mode),
+        } = parse(&["led", "pulses", "3", "--duty", "20"]).unwrap()
+        else {
+            panic!("not `led pulses`");
+        };
+        assert_eq!(
+            LedMode::from(mode),
+            LedMode::Pulses {
+                brightness: 100,
+                period_ms: 500,
+                duty: 20,
+                count: 3,
+            }
+        );
+    }
+}
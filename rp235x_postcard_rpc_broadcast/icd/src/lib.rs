@@ -1,5 +1,6 @@
 #![cfg_attr(not(feature = "use-std"), no_std)]
 
+use heapless::String;
 use postcard_rpc::{endpoints, topics, TopicDirection};
 use postcard_schema::Schema;
 use serde::{Deserialize, Serialize};
@@ -14,20 +15,323 @@ pub struct SleptMillis {
     pub millis: u16,
 }
 
-#[derive(Debug, Serialize, Deserialize, Schema)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Schema)]
 pub enum LedState {
     Off,
     On,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize, Schema)]
+/// Shortest period of the blinking LED modes.
+pub const LED_PERIOD_MIN_MS: u16 = 20;
+/// Shortest period of `LedMode::Heartbeat`, which has two 100 ms beats.
+pub const HEARTBEAT_PERIOD_MIN_MS: u16 = 400;
+
+/// What the LED does, set by `SetLedModeEndpoint`.
+///
+/// Brightness and duty cycle are in percent. The duty cycle is the part of
+/// the period the LED is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Schema)]
+pub enum LedMode {
+    /// Constant brightness, zero is off.
+    Steady { brightness: u8 },
+    Blink {
+        brightness: u8,
+        period_ms: u16,
+        duty: u8,
+    },
+    /// Blink `count` times, then switch to `Steady` off.
+    Pulses {
+        brightness: u8,
+        period_ms: u16,
+        duty: u8,
+        count: u16,
+    },
+    /// Two short beats per period.
+    Heartbeat { brightness: u8, period_ms: u16 },
+}
+
+impl LedMode {
+    pub const OFF: Self = LedMode::Steady { brightness: 0 };
+    pub const ON: Self = LedMode::Steady { brightness: 100 };
+
+    /// Check the limits, the device rejects modes that fail.
+    pub fn validate(&self) -> Result<(), LedModeError> {
+        match *self {
+            LedMode::Steady { brightness } => check_percent(brightness, LedModeError::Brightness),
+            LedMode::Blink {
+                brightness,
+                period_ms,
+                duty,
+            }
+            | LedMode::Pulses {
+                brightness,
+                period_ms,
+                duty,
+                ..
+            } => {
+                check_percent(brightness, LedModeError::Brightness)?;
+                check_percent(duty, LedModeError::Duty)?;
+                check_period(period_ms, LED_PERIOD_MIN_MS)
+            }
+            LedMode::Heartbeat {
+                brightness,
+                period_ms,
+            } => {
+                check_percent(brightness, LedModeError::Brightness)?;
+                check_period(period_ms, HEARTBEAT_PERIOD_MIN_MS)
+            }
+        }
+    }
+}
+
+fn check_percent(value: u8, err: LedModeError) -> Result<(), LedModeError> {
+    match value {
+        0..=100 => Ok(()),
+        _ => Err(err),
+    }
+}
+
+fn check_period(period_ms: u16, min_ms: u16) -> Result<(), LedModeError> {
+    match period_ms >= min_ms {
+        true => Ok(()),
+        false => Err(LedModeError::PeriodTooShort { min_ms }),
+    }
+}
+
+impl From<LedState> for LedMode {
+    fn from(state: LedState) -> Self {
+        match state {
+            LedState::Off => LedMode::OFF,
+            LedState::On => LedMode::ON,
+        }
+    }
+}
+
+/// Why a `LedMode` was rejected, the LED keeps its mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Schema)]
+pub enum LedModeError {
+    /// The brightness is above 100 percent.
+    Brightness,
+    /// The duty cycle is above 100 percent.
+    Duty,
+    PeriodTooShort {
+        min_ms: u16,
+    },
+}
+
+/// Response of `SetLedModeEndpoint`.
+pub type LedModeResult = Result<(), LedModeError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Schema)]
+pub enum PinLevel {
+    Low,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Schema)]
+pub enum Pull {
+    None,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Schema)]
+pub enum PinMode {
+    Input(Pull),
+    /// An output, starting at the given level.
+    Output(PinLevel),
+}
+
+/// Configure GPIO `pin`, the device only allows the pins of its allowlist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Schema)]
+pub struct PinConfig {
+    pub pin: u8,
+    pub mode: PinMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Schema)]
+pub struct PinWrite {
+    pub pin: u8,
+    pub level: PinLevel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Schema)]
+pub enum GpioError {
+    /// The pin is not on the allowlist of the device, e.g., because it is
+    /// used by the firmware.
+    NotAllowed,
+    /// The pin is an input, configure it as output first.
+    NotAnOutput,
+    /// The device samples ADC channels 0 to 2 only.
+    UnknownAdcChannel,
+}
+
+/// Response of `ConfigurePinEndpoint` and `SetPinEndpoint`.
+pub type GpioResult = Result<(), GpioError>;
+/// Response of `GetPinEndpoint`.
+pub type PinLevelResult = Result<PinLevel, GpioError>;
+/// Response of `ReadAdcEndpoint`: the latest raw 12 bit reading.
+pub type AdcResult = Result<u16, GpioError>;
+
+/// Broadcast on `BcCtrlStatus`, see `BroadcastConfig`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, Schema)]
 pub struct CtrlStatus {
-    pub var1: u64,
-    pub var2: u64,
-    pub var3: u64,
-    pub var4: u64,
+    /// Milliseconds since boot.
+    pub uptime_ms: u64,
+    /// Number of requests handled since boot.
+    pub requests: u64,
+    /// Raw 12 bit readings of ADC0 to ADC2 (GPIO 26 to 28).
+    pub adc: [u16; 3],
+    /// Temperature of the chip in degrees Celsius.
+    pub temperature: f32,
+    /// The last value received on `SetpointTopic` per channel.
+    pub setpoints: [f32; SETPOINT_CHANNELS],
+}
+
+/// Number of setpoint channels of the device.
+pub const SETPOINT_CHANNELS: usize = 4;
+
+/// A new target value for one of the control loops of the device.
+///
+/// Published by the host on `SetpointTopic`, the device applies it without
+/// replying. Setpoints of channels from `SETPOINT_CHANNELS` on are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Setpoint {
+    pub channel: u8,
+    pub value: f32,
 }
 
+/// Shortest interval of the `BcCtrlStatus` broadcast.
+pub const BROADCAST_INTERVAL_MIN_MS: u32 = 10;
+/// Longest interval of the `BcCtrlStatus` broadcast.
+pub const BROADCAST_INTERVAL_MAX_MS: u32 = 60_000;
+
+/// How `CtrlStatus` is broadcast, the device starts with the default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Schema)]
+pub struct BroadcastConfig {
+    pub enabled: bool,
+    pub interval_ms: u32,
+}
+
+impl BroadcastConfig {
+    /// Once per second.
+    pub const DEFAULT: Self = Self {
+        enabled: true,
+        interval_ms: 1000,
+    };
+}
+
+impl Default for BroadcastConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Schema)]
+pub struct BroadcastInterval {
+    pub millis: u32,
+}
+
+/// The requested interval is not within the limits, the configuration is
+/// unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Schema)]
+pub struct IntervalOutOfRange {
+    pub min_ms: u32,
+    pub max_ms: u32,
+}
+
+/// Response of `SetBroadcastIntervalEndpoint`: the new configuration.
+pub type BroadcastIntervalResult = Result<BroadcastConfig, IntervalOutOfRange>;
+
+/// Fails if `millis` is not within `BROADCAST_INTERVAL_MIN_MS` and
+/// `BROADCAST_INTERVAL_MAX_MS`.
+pub fn check_broadcast_interval(millis: u32) -> Result<(), IntervalOutOfRange> {
+    match (BROADCAST_INTERVAL_MIN_MS..=BROADCAST_INTERVAL_MAX_MS).contains(&millis) {
+        true => Ok(()),
+        false => Err(IntervalOutOfRange {
+            min_ms: BROADCAST_INTERVAL_MIN_MS,
+            max_ms: BROADCAST_INTERVAL_MAX_MS,
+        }),
+    }
+}
+
+/// Longest nickname of a device, in bytes.
+pub const NICKNAME_LEN: usize = 32;
+
+/// Calibration constants of the device.
+///
+/// The device adds `temperature_offset` to `CtrlStatus::temperature`. The ADC
+/// constants are kept for the host, which converts the raw readings as
+/// `raw * adc_gain + adc_offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Calibration {
+    pub temperature_offset: f32,
+    pub adc_gain: [f32; 3],
+    pub adc_offset: [f32; 3],
+}
+
+impl Calibration {
+    /// Uncalibrated.
+    pub const DEFAULT: Self = Self {
+        temperature_offset: 0.0,
+        adc_gain: [1.0; 3],
+        adc_offset: [0.0; 3],
+    };
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The settings the device starts with, kept in its flash.
+///
+/// Changing the broadcast or the LED by their own endpoints does not change
+/// the configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Schema)]
+pub struct DeviceConfig {
+    pub nickname: String<NICKNAME_LEN>,
+    pub broadcast: BroadcastConfig,
+    pub led: LedMode,
+    pub calibration: Calibration,
+}
+
+impl DeviceConfig {
+    /// Used until a configuration is written, and after a reset.
+    pub const DEFAULT: Self = Self {
+        nickname: String::new(),
+        broadcast: BroadcastConfig::DEFAULT,
+        led: LedMode::OFF,
+        calibration: Calibration::DEFAULT,
+    };
+
+    /// Check the limits, the device rejects configurations that fail.
+    pub fn validate(&self) -> ConfigResult {
+        check_broadcast_interval(self.broadcast.interval_ms)
+            .map_err(ConfigError::BroadcastInterval)?;
+        self.led.validate().map_err(ConfigError::LedMode)
+    }
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Why a `DeviceConfig` was not written, the device keeps its configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Schema)]
+pub enum ConfigError {
+    BroadcastInterval(IntervalOutOfRange),
+    LedMode(LedModeError),
+    /// Writing or erasing the flash failed.
+    Storage,
+}
+
+/// Response of `SetConfigEndpoint` and `ResetConfigEndpoint`.
+pub type ConfigResult = Result<(), ConfigError>;
+
 // ---
 
 // Endpoints spoken by our device
@@ -35,21 +339,34 @@ pub struct CtrlStatus {
 // GetUniqueIdEndpoint is mandatory, the others are examples
 endpoints! {
     list = ENDPOINT_LIST;
-    | EndpointTy                | RequestTy     | ResponseTy            | Path                          |
-    | ----------                | ---------     | ----------            | ----                          |
-    | GetUniqueIdEndpoint       | ()            | u64                   | "poststation/unique_id/get"   |
-    | RebootToPicoBoot          | ()            | ()                    | "template/picoboot/reset"     |
-    | SleepEndpoint             | SleepMillis   | SleptMillis           | "template/sleep"              |
-    | SetLedEndpoint            | LedState      | ()                    | "template/led/set"            |
-    | GetLedEndpoint            | ()            | LedState              | "template/led/get"            |
+    | EndpointTy                   | RequestTy         | ResponseTy              | Path                              |
+    | ----------                   | ---------         | ----------              | ----                              |
+    | GetUniqueIdEndpoint          | ()                | u64                     | "poststation/unique_id/get"       |
+    | RebootToPicoBoot             | ()                | ()                      | "template/picoboot/reset"         |
+    | SleepEndpoint                | SleepMillis       | SleptMillis             | "template/sleep"                  |
+    | SetLedEndpoint               | LedState          | ()                      | "template/led/set"                |
+    | GetLedEndpoint               | ()                | LedMode                 | "template/led/get"                |
+    | SetLedModeEndpoint           | LedMode           | LedModeResult           | "template/led/mode/set"           |
+    | StartBroadcastEndpoint       | ()                | ()                      | "template/broadcast/start"        |
+    | StopBroadcastEndpoint        | ()                | ()                      | "template/broadcast/stop"         |
+    | SetBroadcastIntervalEndpoint | BroadcastInterval | BroadcastIntervalResult | "template/broadcast/interval/set" |
+    | GetBroadcastConfigEndpoint   | ()                | BroadcastConfig         | "template/broadcast/get"          |
+    | ConfigurePinEndpoint         | PinConfig         | GpioResult              | "template/gpio/configure"         |
+    | SetPinEndpoint               | PinWrite          | GpioResult              | "template/gpio/set"               |
+    | GetPinEndpoint               | u8                | PinLevelResult          | "template/gpio/get"               |
+    | ReadAdcEndpoint              | u8                | AdcResult               | "template/adc/read"               |
+    | GetConfigEndpoint            | ()                | DeviceConfig            | "template/config/get"             |
+    | SetConfigEndpoint            | DeviceConfig      | ConfigResult            | "template/config/set"             |
+    | ResetConfigEndpoint          | ()                | ConfigResult            | "template/config/reset"           |
 }
 
 // incoming topics handled by our device
 topics! {
     list = TOPICS_IN_LIST;
     direction = TopicDirection::ToServer;
-    | TopicTy                   | MessageTy     | Path              |
-    | -------                   | ---------     | ----              |
+    | TopicTy       | MessageTy | Path                |
+    | -------       | --------- | ----                |
+    | SetpointTopic | Setpoint  | "template/setpoint" |
 }
 
 // outgoing topics handled by our device
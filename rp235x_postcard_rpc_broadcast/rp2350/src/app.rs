@@ -1,44 +1,30 @@
 //! A basic postcard-rpc/poststation-compatible application
 
-use crate::handlers::{get_led, picoboot_reset, set_led, sleep_handler, unique_id};
-use embassy_rp::{gpio::Output, peripherals::USB, usb};
+use crate::handlers::sleep_handler;
+use embassy_rp::{peripherals::USB, usb};
 use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
 use postcard_rpc::server::impls::embassy_usb_v0_4::{
     dispatch_impl::{spawn_fn, WireRxBuf, WireRxImpl, WireSpawnImpl, WireStorage, WireTxImpl},
     PacketBuffers,
 };
-use postcard_rpc::{
-    define_dispatch,
-    server::{Server, SpawnContext},
-};
+use postcard_rpc::{define_dispatch, server::Server};
 use static_cell::ConstStaticCell;
+use template_device::handlers::{
+    configure_pin, get_broadcast_config, get_config, get_led, get_pin, picoboot_reset, read_adc,
+    reset_config, set_broadcast_interval, set_config, set_led, set_led_mode, set_pin, setpoint,
+    start_broadcast, stop_broadcast, unique_id,
+};
 use template_icd::{
-    GetLedEndpoint, GetUniqueIdEndpoint, RebootToPicoBoot, SetLedEndpoint, SleepEndpoint,
+    ConfigurePinEndpoint, GetBroadcastConfigEndpoint, GetConfigEndpoint, GetLedEndpoint,
+    GetPinEndpoint, GetUniqueIdEndpoint, ReadAdcEndpoint, RebootToPicoBoot, ResetConfigEndpoint,
+    SetBroadcastIntervalEndpoint, SetConfigEndpoint, SetLedEndpoint, SetLedModeEndpoint,
+    SetPinEndpoint, SetpointTopic, SleepEndpoint, StartBroadcastEndpoint, StopBroadcastEndpoint,
 };
 use template_icd::{ENDPOINT_LIST, TOPICS_IN_LIST, TOPICS_OUT_LIST};
 
-/// Context contains the data that we will pass (as a mutable reference)
-/// to each endpoint or topic handler
-pub struct Context {
-    /// We'll use this unique ID to identify ourselves to the poststation
-    /// server. This should be unique per device.
-    pub unique_id: u64,
-    pub led: Output<'static>,
-}
-
-impl SpawnContext for Context {
-    type SpawnCtxt = TaskContext;
-
-    fn spawn_ctxt(&mut self) -> Self::SpawnCtxt {
-        TaskContext {
-            unique_id: self.unique_id,
-        }
-    }
-}
-
-pub struct TaskContext {
-    pub unique_id: u64,
-}
+/// The context and the handlers are shared with the simulator, see the
+/// `template-device` crate.
+pub use template_device::{Context, TaskContext};
 
 /// Type Aliases
 ///
@@ -105,13 +91,25 @@ define_dispatch! {
         // define below MUST be contained in this list.
         list: ENDPOINT_LIST;
 
-        | EndpointTy                | kind      | handler                       |
-        | ----------                | ----      | -------                       |
-        | GetUniqueIdEndpoint       | blocking  | unique_id                     |
-        | RebootToPicoBoot          | blocking  | picoboot_reset                |
-        | SleepEndpoint             | spawn     | sleep_handler                 |
-        | SetLedEndpoint            | blocking  | set_led                       |
-        | GetLedEndpoint            | blocking  | get_led                       |
+        | EndpointTy                   | kind     | handler                |
+        | ----------                   | ----     | -------                |
+        | GetUniqueIdEndpoint          | blocking | unique_id              |
+        | RebootToPicoBoot             | blocking | picoboot_reset         |
+        | SleepEndpoint                | spawn    | sleep_handler          |
+        | SetLedEndpoint               | blocking | set_led                |
+        | GetLedEndpoint               | blocking | get_led                |
+        | SetLedModeEndpoint           | blocking | set_led_mode           |
+        | StartBroadcastEndpoint       | blocking | start_broadcast        |
+        | StopBroadcastEndpoint        | blocking | stop_broadcast         |
+        | SetBroadcastIntervalEndpoint | blocking | set_broadcast_interval |
+        | GetBroadcastConfigEndpoint   | blocking | get_broadcast_config   |
+        | ConfigurePinEndpoint         | blocking | configure_pin          |
+        | SetPinEndpoint               | blocking | set_pin                |
+        | GetPinEndpoint               | blocking | get_pin                |
+        | ReadAdcEndpoint              | blocking | read_adc               |
+        | GetConfigEndpoint            | blocking | get_config             |
+        | SetConfigEndpoint            | blocking | set_config             |
+        | ResetConfigEndpoint          | blocking | reset_config           |
     };
 
     // Topics IN are messages we receive from the client, but that we do not reply
@@ -124,6 +122,7 @@ define_dispatch! {
 
         | TopicTy                   | kind      | handler                       |
         | ----------                | ----      | -------                       |
+        | SetpointTopic             | blocking  | setpoint                      |
     };
 
     // Topics OUT are the messages we send to the client whenever we'd like. Since
@@ -0,0 +1,134 @@
+//! The Pico 2 board, as seen by the handlers.
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use embassy_rp::gpio::{self, Flex};
+use template_device::Board;
+use template_icd::{
+    AdcResult, ConfigResult, DeviceConfig, GpioError, GpioResult, PinLevel, PinLevelResult,
+    PinMode, Pull,
+};
+
+use crate::measure;
+use crate::storage::Storage;
+
+/// A GPIO the host may use.
+pub struct GpioPin {
+    number: u8,
+    flex: Flex<'static>,
+    output: bool,
+}
+
+impl GpioPin {
+    /// An input without pull, until the host configures it.
+    pub fn new(pin: impl gpio::Pin) -> Self {
+        let number = pin.pin();
+        let mut flex = Flex::new(pin);
+        flex.set_as_input();
+        flex.set_pull(gpio::Pull::None);
+        Self {
+            number,
+            flex,
+            output: false,
+        }
+    }
+}
+
+/// The LED is not part of the board, it belongs to `led::led_task`.
+pub struct PicoBoard {
+    /// The allowlist: the host can only use these pins. The LED, the ADC
+    /// pins and the pins of the board itself are owned by the firmware; USB
+    /// and flash do not use GPIOs on the RP2350.
+    pub gpio: [GpioPin; 8],
+    pub storage: Storage,
+}
+
+impl PicoBoard {
+    fn pin(&mut self, number: u8) -> Result<&mut GpioPin, GpioError> {
+        self.gpio
+            .iter_mut()
+            .find(|pin| pin.number == number)
+            .ok_or(GpioError::NotAllowed)
+    }
+}
+
+impl Board for PicoBoard {
+    fn reboot_to_picoboot(&mut self) {
+        embassy_rp::rom_data::reboot(0x0002, 500, 0x0000, 0x0000);
+        loop {
+            // Wait for reset...
+            compiler_fence(Ordering::SeqCst);
+        }
+    }
+
+    fn configure_pin(&mut self, pin: u8, mode: PinMode) -> GpioResult {
+        let pin = self.pin(pin)?;
+        match mode {
+            PinMode::Input(pull) => {
+                pin.flex.set_as_input();
+                pin.flex.set_pull(match pull {
+                    Pull::None => gpio::Pull::None,
+                    Pull::Up => gpio::Pull::Up,
+                    Pull::Down => gpio::Pull::Down,
+                });
+                pin.output = false;
+            }
+            PinMode::Output(level) => {
+                pin.flex.set_pull(gpio::Pull::None);
+                pin.flex.set_level(level_of(level));
+                pin.flex.set_as_output();
+                pin.output = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_pin(&mut self, pin: u8, level: PinLevel) -> GpioResult {
+        let pin = self.pin(pin)?;
+        if !pin.output {
+            return Err(GpioError::NotAnOutput);
+        }
+        pin.flex.set_level(level_of(level));
+        Ok(())
+    }
+
+    fn pin_level(&self, pin: u8) -> PinLevelResult {
+        let pin = self
+            .gpio
+            .iter()
+            .find(|p| p.number == pin)
+            .ok_or(GpioError::NotAllowed)?;
+        match pin.flex.is_high() {
+            true => Ok(PinLevel::High),
+            false => Ok(PinLevel::Low),
+        }
+    }
+
+    fn adc(&self, channel: u8) -> AdcResult {
+        let samples = measure::latest();
+        samples
+            .adc
+            .get(usize::from(channel))
+            .copied()
+            .ok_or(GpioError::UnknownAdcChannel)
+    }
+
+    fn load_config(&mut self) -> Option<DeviceConfig> {
+        self.storage.load()
+    }
+
+    fn store_config(&mut self, config: &DeviceConfig) -> ConfigResult {
+        self.storage.store(config)
+    }
+
+    fn erase_config(&mut self) -> ConfigResult {
+        self.storage.erase()
+    }
+}
+
+fn level_of(level: PinLevel) -> gpio::Level {
+    match level {
+        PinLevel::Low => gpio::Level::Low,
+        PinLevel::High => gpio::Level::High,
+    }
+}
@@ -1,39 +1,11 @@
-use core::sync::atomic::{compiler_fence, Ordering};
-
 use embassy_time::{Instant, Timer};
 use postcard_rpc::{header::VarHeader, server::Sender};
-use template_icd::{LedState, SleepEndpoint, SleepMillis, SleptMillis};
-
-use crate::app::{AppTx, Context, TaskContext};
-
-/// This is an example of a BLOCKING handler.
-pub fn unique_id(context: &mut Context, _header: VarHeader, _arg: ()) -> u64 {
-    context.unique_id
-}
+use template_icd::{SleepEndpoint, SleepMillis, SleptMillis};
 
-/// Also a BLOCKING handler
-pub fn picoboot_reset(_context: &mut Context, _header: VarHeader, _arg: ()) {
-    embassy_rp::rom_data::reboot(0x0002, 500, 0x0000, 0x0000);
-    loop {
-        // Wait for reset...
-        compiler_fence(Ordering::SeqCst);
-    }
-}
-
-/// Also a BLOCKING handler
-pub fn set_led(context: &mut Context, _header: VarHeader, arg: LedState) {
-    match arg {
-        LedState::Off => context.led.set_low(),
-        LedState::On => context.led.set_high(),
-    }
-}
+use crate::app::{AppTx, TaskContext};
 
-pub fn get_led(context: &mut Context, _header: VarHeader, _arg: ()) -> LedState {
-    match context.led.is_set_low() {
-        true => LedState::Off,
-        false => LedState::On,
-    }
-}
+// The BLOCKING handlers are in `template_device::handlers`, such that the
+// simulator can run them as well.
 
 /// This is a SPAWN handler
 ///
@@ -41,11 +13,12 @@ pub fn get_led(context: &mut Context, _header: VarHeader, _arg: ()) -> LedState
 /// at the same time. We will return an error if a fourth is requested at the same time
 #[embassy_executor::task(pool_size = 3)]
 pub async fn sleep_handler(
-    _context: TaskContext,
+    context: TaskContext,
     header: VarHeader,
     arg: SleepMillis,
     sender: Sender<AppTx>,
 ) {
+    context.stats.count_request();
     // We can send string logs, using the sender
     let _ = sender.log_str("Starting sleep...").await;
     let start = Instant::now();
@@ -0,0 +1,46 @@
+//! The onboard LED on PIN_25, dimmed by PWM slice 4.
+
+use embassy_futures::select::{select, Either};
+use embassy_rp::pwm::{self, Pwm};
+use embassy_time::Timer;
+use template_device::led::Pattern;
+
+use crate::LED;
+
+/// The counter wraps after `TOP`, so a compare value of `TOP + 1` is fully on.
+const TOP: u16 = 9_999;
+
+fn config(brightness: u8) -> pwm::Config {
+    let mut config = pwm::Config::default();
+    config.top = TOP;
+    // Squared, the eye is more sensitive to changes of low brightness.
+    config.compare_b = u16::from(brightness) * u16::from(brightness);
+    config
+}
+
+/// Plays the pattern of the mode in `LED`, starts over when it changes.
+#[embassy_executor::task]
+pub async fn led_task(mut pwm: Pwm<'static>) {
+    'mode: loop {
+        let mode = LED.mode();
+        for step in Pattern::new(mode) {
+            pwm.set_config(&config(step.brightness));
+            match step.duration_ms {
+                Some(millis) => {
+                    let timer = Timer::after_millis(millis.into());
+                    if let Either::Second(()) = select(timer, LED.changed()).await {
+                        continue 'mode;
+                    }
+                }
+                None => {
+                    LED.changed().await;
+                    continue 'mode;
+                }
+            }
+        }
+        // Only `LedMode::Pulses` ends.
+        LED.finished(mode);
+        pwm.set_config(&config(0));
+        LED.changed().await;
+    }
+}
@@ -4,11 +4,15 @@
 use app::AppTx;
 use defmt::info;
 use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
 use embassy_rp::{
+    adc::{self, Adc, Channel},
     bind_interrupts,
     block::ImageDef,
-    gpio::{Level, Output},
+    flash::Flash,
+    gpio::Pull,
     peripherals::USB,
+    pwm::Pwm,
     usb,
 };
 use embassy_time::{Duration, Instant, Ticker};
@@ -18,17 +22,38 @@ use postcard_rpc::{
     server::{Dispatch, Sender, Server},
 };
 use static_cell::StaticCell;
-use template_icd::{BcCtrlStatus, CtrlStatus};
+use template_device::{config::ConfigControl, led::LedControl, BroadcastControl, Setpoints, Stats};
+use template_icd::{BcCtrlStatus, BroadcastConfig, CtrlStatus, LedMode};
 
 
 bind_interrupts!(pub struct Irqs {
     USBCTRL_IRQ => usb::InterruptHandler<USB>;
+    ADC_IRQ_FIFO => adc::InterruptHandler;
 });
 
 use {defmt_rtt as _, panic_probe as _};
 
 pub mod app;
+pub mod board;
 pub mod handlers;
+pub mod led;
+pub mod measure;
+pub mod storage;
+
+/// Counted by the handlers, reported by `broadcast`.
+pub static STATS: Stats = Stats::new();
+
+/// Changed by the handlers, followed by `broadcast`.
+pub static BROADCAST: BroadcastControl = BroadcastControl::new(BroadcastConfig::DEFAULT);
+
+/// Received on `SetpointTopic`, reported by `broadcast`.
+pub static SETPOINTS: Setpoints = Setpoints::new();
+
+/// Changed by the handlers, played by `led::led_task`.
+pub static LED: LedControl = LedControl::new(LedMode::OFF);
+
+/// Loaded from the flash at boot, changed by the handlers.
+pub static CONFIG: ConfigControl = ConfigControl::new();
 
 #[link_section = ".start_block"]
 #[used]
@@ -93,9 +118,41 @@ async fn main(spawner: Spawner) {
     let driver = usb::Driver::new(p.USB, Irqs);
     let pbufs = app::PBUFS.take();
     let config = usb_config(ser_buf);
-    let led = Output::new(p.PIN_25, Level::Low);
-
-    let context = app::Context { unique_id, led };
+    static BOARD: StaticCell<board::PicoBoard> = StaticCell::new();
+    let board = BOARD.init(board::PicoBoard {
+        gpio: [
+            board::GpioPin::new(p.PIN_2),
+            board::GpioPin::new(p.PIN_3),
+            board::GpioPin::new(p.PIN_4),
+            board::GpioPin::new(p.PIN_5),
+            board::GpioPin::new(p.PIN_6),
+            board::GpioPin::new(p.PIN_7),
+            board::GpioPin::new(p.PIN_8),
+            board::GpioPin::new(p.PIN_9),
+        ],
+        storage: storage::Storage::new(Flash::new_blocking(p.FLASH)),
+    });
+    let led_pwm = Pwm::new_output_b(p.PWM_SLICE4, p.PIN_25, Default::default());
+
+    let mut context = app::Context {
+        unique_id,
+        board,
+        stats: &STATS,
+        broadcast: &BROADCAST,
+        setpoints: &SETPOINTS,
+        led: &LED,
+        config: &CONFIG,
+    };
+    template_device::config::load(&mut context);
+
+    // ADC INIT
+    let adc = Adc::new(p.ADC, Irqs, adc::Config::default());
+    let pins = [
+        Channel::new_pin(p.PIN_26, Pull::None),
+        Channel::new_pin(p.PIN_27, Pull::None),
+        Channel::new_pin(p.PIN_28, Pull::None),
+    ];
+    let temp_sensor = Channel::new_temp_sensor(p.ADC_TEMP_SENSOR);
 
     let (device, tx_impl, rx_impl) =
         app::STORAGE.init_poststation(driver, config, pbufs.tx_buf.as_mut_slice());
@@ -112,6 +169,8 @@ async fn main(spawner: Spawner) {
     // We need to spawn the USB task so that USB messages are handled by
     // embassy-usb
     spawner.must_spawn(usb_task(device));
+    spawner.must_spawn(measure::measure(adc, pins, temp_sensor));
+    spawner.must_spawn(led::led_task(led_pwm));
     spawner.must_spawn(logging_task(sender.clone()));
     spawner.must_spawn(broadcast(sender));
 
@@ -140,14 +199,29 @@ pub async fn logging_task(sender: Sender<AppTx>) {
     }
 }
 
+/// Publishes the latest samples of `measure`, as configured by `BROADCAST`
 #[embassy_executor::task]
 pub async fn broadcast(sender: Sender<AppTx>) {
-    let mut ticker = Ticker::every(Duration::from_secs(1));
     let mut seq = 0u8;
-    let msg = CtrlStatus::default();
     loop {
-        ticker.next().await;
-        let _ = sender.publish::<BcCtrlStatus>(seq.into(), &msg).await;
-        seq = seq.wrapping_add(1); 
+        let config = BROADCAST.config();
+        if !config.enabled {
+            BROADCAST.changed().await;
+            continue;
+        }
+        let mut ticker = Ticker::every(Duration::from_millis(config.interval_ms.into()));
+        // Publish until the configuration changes, then start over with the new one.
+        while let Either::First(()) = select(ticker.next(), BROADCAST.changed()).await {
+            let samples = measure::latest();
+            let msg = CtrlStatus {
+                uptime_ms: Instant::now().as_millis(),
+                requests: STATS.requests().into(),
+                adc: samples.adc,
+                temperature: samples.temperature,
+                setpoints: SETPOINTS.all(),
+            };
+            let _ = sender.publish::<BcCtrlStatus>(seq.into(), &msg).await;
+            seq = seq.wrapping_add(1);
+        }
     }
 }
@@ -0,0 +1,57 @@
+//! Periodic sampling of the ADC, shared with the status broadcast.
+
+use core::cell::Cell;
+
+use embassy_rp::adc::{Adc, Async, Channel};
+use embassy_sync::blocking_mutex::{raw::ThreadModeRawMutex, Mutex};
+use embassy_time::{Duration, Ticker};
+
+use crate::CONFIG;
+
+/// The latest readings, see `CtrlStatus`.
+#[derive(Clone, Copy, Default)]
+pub struct Samples {
+    pub adc: [u16; 3],
+    pub temperature: f32,
+}
+
+pub static SAMPLES: Mutex<ThreadModeRawMutex, Cell<Samples>> = Mutex::new(Cell::new(Samples {
+    adc: [0; 3],
+    temperature: 0.0,
+}));
+
+/// Read the latest samples.
+pub fn latest() -> Samples {
+    SAMPLES.lock(|samples| samples.get())
+}
+
+/// Convert a reading of the temperature sensor to degrees Celsius, see the
+/// RP2350 datasheet, section 12.4.6.
+fn temperature(raw: u16) -> f32 {
+    let volts = f32::from(raw) * 3.3 / 4096.0;
+    27.0 - (volts - 0.706) / 0.001721
+}
+
+/// Samples ADC0 to ADC2 and the temperature sensor ten times per second.
+#[embassy_executor::task]
+pub async fn measure(
+    mut adc: Adc<'static, Async>,
+    mut pins: [Channel<'static>; 3],
+    mut temp_sensor: Channel<'static>,
+) {
+    let mut ticker = Ticker::every(Duration::from_millis(100));
+    loop {
+        ticker.next().await;
+        let mut samples = latest();
+        for (pin, value) in pins.iter_mut().zip(samples.adc.iter_mut()) {
+            // Keep the last value if a conversion fails.
+            if let Ok(raw) = adc.read(pin).await {
+                *value = raw;
+            }
+        }
+        if let Ok(raw) = adc.read(&mut temp_sensor).await {
+            samples.temperature = temperature(raw) + CONFIG.calibration().temperature_offset;
+        }
+        SAMPLES.lock(|cell| cell.set(samples));
+    }
+}
@@ -0,0 +1,80 @@
+//! The configuration in the flash sectors reserved by `memory.x`.
+//!
+//! `sequential-storage` appends every new configuration to the reserved
+//! sectors and erases a sector only when all of them are full, so that the
+//! sectors wear evenly.
+
+use core::ops::Range;
+
+use embassy_embedded_hal::adapter::BlockingAsync;
+use embassy_futures::block_on;
+use embassy_rp::flash::{Blocking, Flash, ERASE_SIZE};
+use embassy_rp::peripherals::FLASH;
+use sequential_storage::cache::NoCache;
+use sequential_storage::map;
+use template_icd::{ConfigError, ConfigResult, DeviceConfig};
+
+/// The flash the firmware is linked for, see `memory.x`.
+pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
+
+/// The last four sectors, the `CONFIG` region of `memory.x`.
+const CONFIG_RANGE: Range<u32> = (FLASH_SIZE - 4 * ERASE_SIZE) as u32..FLASH_SIZE as u32;
+
+/// The configuration is the only item of the map.
+const CONFIG_KEY: u8 = 0;
+
+/// Larger than a serialized `DeviceConfig` with the overhead of the map.
+const BUF_LEN: usize = 256;
+
+pub type ConfigFlash = Flash<'static, FLASH, Blocking, FLASH_SIZE>;
+
+pub struct Storage {
+    flash: BlockingAsync<ConfigFlash>,
+}
+
+impl Storage {
+    pub fn new(flash: ConfigFlash) -> Self {
+        Self {
+            flash: BlockingAsync::new(flash),
+        }
+    }
+
+    // The flash is blocking, so are the futures: `block_on` returns as soon
+    // as the flash operation is done.
+
+    pub fn load(&mut self) -> Option<DeviceConfig> {
+        let mut buf = [0u8; BUF_LEN];
+        let bytes: &[u8] = block_on(map::fetch_item(
+            &mut self.flash,
+            CONFIG_RANGE,
+            &mut NoCache::new(),
+            &mut buf,
+            &CONFIG_KEY,
+        ))
+        .ok()??;
+        // A configuration of an older firmware may not decode, it is replaced
+        // by the default.
+        postcard::from_bytes(bytes).ok()
+    }
+
+    pub fn store(&mut self, config: &DeviceConfig) -> ConfigResult {
+        let mut item = [0u8; BUF_LEN];
+        let item: &[u8] =
+            postcard::to_slice(config, &mut item).map_err(|_| ConfigError::Storage)?;
+        let mut buf = [0u8; BUF_LEN];
+        block_on(map::store_item(
+            &mut self.flash,
+            CONFIG_RANGE,
+            &mut NoCache::new(),
+            &mut buf,
+            &CONFIG_KEY,
+            &item,
+        ))
+        .map_err(|_| ConfigError::Storage)
+    }
+
+    pub fn erase(&mut self) -> ConfigResult {
+        block_on(sequential_storage::erase_all(&mut self.flash, CONFIG_RANGE))
+            .map_err(|_| ConfigError::Storage)
+    }
+}
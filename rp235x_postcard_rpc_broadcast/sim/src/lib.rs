@@ -0,0 +1,455 @@
+//! The firmware without the board: runs the handlers of `template-device` on
+//! a std host, connected to a `HostClient` by in-memory channels.
+//!
+//! The bootloader, the GPIOs, the ADC and the flash are simulated by `SimBoard`, the brightness of
+//! the LED by a task playing the same patterns as the firmware. Time is
+//! tokio's, such that tests with a paused clock do not have to wait for `sleep`.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use postcard_rpc::header::{VarHeader, VarSeqKind};
+use postcard_rpc::host_client::{test_channels as client_channels, HostClient};
+use postcard_rpc::server::impls::test_channels::dispatch_impl::{new_server, spawn_fn, Settings};
+use postcard_rpc::server::impls::test_channels::{ChannelWireRx, ChannelWireSpawn, ChannelWireTx};
+use postcard_rpc::server::{Dispatch, Sender, Server};
+use postcard_rpc::standard_icd::WireError;
+use postcard_rpc::define_dispatch;
+use template_device::config::ConfigControl;
+use template_device::handlers::{
+    configure_pin, get_broadcast_config, get_config, get_led, get_pin, picoboot_reset, read_adc,
+    reset_config, set_broadcast_interval, set_config, set_led, set_led_mode, set_pin, setpoint,
+    start_broadcast, stop_broadcast, unique_id,
+};
+use template_device::led::{LedControl, Pattern};
+use template_device::{Board, BroadcastControl, Context, Setpoints, Stats, TaskContext};
+use template_icd::{
+    AdcResult, BcCtrlStatus, BroadcastConfig, ConfigResult, ConfigurePinEndpoint, CtrlStatus,
+    DeviceConfig, GetBroadcastConfigEndpoint, GetConfigEndpoint, GetLedEndpoint, GetPinEndpoint,
+    GetUniqueIdEndpoint, GpioError, GpioResult, LedMode, PinLevel, PinLevelResult, PinMode, Pull,
+    ReadAdcEndpoint, RebootToPicoBoot, ResetConfigEndpoint, SetBroadcastIntervalEndpoint,
+    SetConfigEndpoint, SetLedEndpoint, SetLedModeEndpoint, SetPinEndpoint, SetpointTopic,
+    SleepEndpoint, SleepMillis, SleptMillis, StartBroadcastEndpoint, StopBroadcastEndpoint,
+};
+use template_icd::{ENDPOINT_LIST, TOPICS_IN_LIST, TOPICS_OUT_LIST};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, interval_at, sleep, Instant};
+
+/// Size of the receive buffer of the server, as `PacketBuffers` in the firmware.
+const RX_BUF: usize = 1024;
+
+/// Messages in flight per direction.
+const CHANNEL_DEPTH: usize = 64;
+
+pub type SimTx = ChannelWireTx;
+pub type SimServer = Server<SimTx, ChannelWireRx, Box<[u8]>, SimApp>;
+
+// Same endpoints and handlers as `rp2350/src/app.rs`, on tokio.
+define_dispatch! {
+    app: SimApp;
+    spawn_fn: spawn_fn;
+    tx_impl: SimTx;
+    spawn_impl: ChannelWireSpawn;
+    context: Context;
+
+    endpoints: {
+        list: ENDPOINT_LIST;
+
+        | EndpointTy                   | kind     | handler                |
+        | ----------                   | ----     | -------                |
+        | GetUniqueIdEndpoint          | blocking | unique_id              |
+        | RebootToPicoBoot             | blocking | picoboot_reset         |
+        | SleepEndpoint                | spawn    | sleep_handler          |
+        | SetLedEndpoint               | blocking | set_led                |
+        | GetLedEndpoint               | blocking | get_led                |
+        | SetLedModeEndpoint           | blocking | set_led_mode           |
+        | StartBroadcastEndpoint       | blocking | start_broadcast        |
+        | StopBroadcastEndpoint        | blocking | stop_broadcast         |
+        | SetBroadcastIntervalEndpoint | blocking | set_broadcast_interval |
+        | GetBroadcastConfigEndpoint   | blocking | get_broadcast_config   |
+        | ConfigurePinEndpoint         | blocking | configure_pin          |
+        | SetPinEndpoint               | blocking | set_pin                |
+        | GetPinEndpoint               | blocking | get_pin                |
+        | ReadAdcEndpoint              | blocking | read_adc               |
+        | GetConfigEndpoint            | blocking | get_config             |
+        | SetConfigEndpoint            | blocking | set_config             |
+        | ResetConfigEndpoint          | blocking | reset_config           |
+    };
+
+    topics_in: {
+        list: TOPICS_IN_LIST;
+
+        | TopicTy                   | kind      | handler                       |
+        | ----------                | ----      | -------                       |
+        | SetpointTopic             | blocking  | setpoint                      |
+    };
+
+    topics_out: {
+        list: TOPICS_OUT_LIST;
+    };
+}
+
+/// The allowlist of the GPIOs, as on the Pico.
+pub const GPIO_PINS: [u8; 8] = [2, 3, 4, 5, 6, 7, 8, 9];
+
+/// A GPIO of the allowlist.
+#[derive(Debug)]
+pub struct SimPin {
+    pub number: u8,
+    pub mode: PinMode,
+    /// Level driven from outside, inputs without one follow their pull.
+    pub external: Option<PinLevel>,
+}
+
+impl SimPin {
+    fn level(&self) -> PinLevel {
+        match (self.mode, self.external) {
+            (PinMode::Output(level), _) | (PinMode::Input(_), Some(level)) => level,
+            (PinMode::Input(Pull::Up), None) => PinLevel::High,
+            (PinMode::Input(_), None) => PinLevel::Low,
+        }
+    }
+}
+
+/// State of the simulated hardware.
+#[derive(Debug)]
+pub struct SimState {
+    /// Brightness of the LED in percent.
+    pub brightness: u8,
+    /// How often the device was rebooted into the bootloader.
+    pub picoboot_resets: usize,
+    pub pins: Vec<SimPin>,
+    /// The configuration in the flash.
+    pub stored_config: Option<DeviceConfig>,
+    /// Raw readings of ADC0 to ADC2.
+    pub adc: [u16; 3],
+    /// Temperature of the chip in degrees Celsius.
+    pub temperature: f32,
+    /// The latest readings of the sampler, reported by the broadcast.
+    pub samples: Samples,
+}
+
+/// The latest readings, see `CtrlStatus`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Samples {
+    pub adc: [u16; 3],
+    pub temperature: f32,
+}
+
+/// The board, its state is shared with the `SimDevice`.
+pub struct SimBoard {
+    state: Arc<Mutex<SimState>>,
+}
+
+impl SimState {
+    fn pin(&mut self, number: u8) -> Result<&mut SimPin, GpioError> {
+        self.pins
+            .iter_mut()
+            .find(|pin| pin.number == number)
+            .ok_or(GpioError::NotAllowed)
+    }
+}
+
+impl Board for SimBoard {
+    /// Only counted, the simulated device keeps running.
+    fn reboot_to_picoboot(&mut self) {
+        self.state.lock().unwrap().picoboot_resets += 1;
+    }
+
+    fn configure_pin(&mut self, pin: u8, mode: PinMode) -> GpioResult {
+        self.state.lock().unwrap().pin(pin)?.mode = mode;
+        Ok(())
+    }
+
+    fn set_pin(&mut self, pin: u8, level: PinLevel) -> GpioResult {
+        let mut state = self.state.lock().unwrap();
+        let pin = state.pin(pin)?;
+        match pin.mode {
+            PinMode::Output(_) => {
+                pin.mode = PinMode::Output(level);
+                Ok(())
+            }
+            PinMode::Input(_) => Err(GpioError::NotAnOutput),
+        }
+    }
+
+    fn pin_level(&self, pin: u8) -> PinLevelResult {
+        Ok(self.state.lock().unwrap().pin(pin)?.level())
+    }
+
+    fn adc(&self, channel: u8) -> AdcResult {
+        let state = self.state.lock().unwrap();
+        state
+            .adc
+            .get(usize::from(channel))
+            .copied()
+            .ok_or(GpioError::UnknownAdcChannel)
+    }
+
+    fn load_config(&mut self) -> Option<DeviceConfig> {
+        self.state.lock().unwrap().stored_config.clone()
+    }
+
+    fn store_config(&mut self, config: &DeviceConfig) -> ConfigResult {
+        self.state.lock().unwrap().stored_config = Some(config.clone());
+        Ok(())
+    }
+
+    fn erase_config(&mut self) -> ConfigResult {
+        self.state.lock().unwrap().stored_config = None;
+        Ok(())
+    }
+}
+
+/// A running simulated device with a client connected to it.
+///
+/// The server, the status broadcast, the LED, and the uptime log run as tokio tasks
+/// until the device is dropped.
+pub struct SimDevice {
+    pub client: HostClient<WireError>,
+    state: Arc<Mutex<SimState>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl SimDevice {
+    /// Start a device with the given unique id, must be called within a tokio
+    /// runtime.
+    pub fn start(unique_id: u64) -> Self {
+        Self::start_with_config(unique_id, None)
+    }
+
+    /// Start a device with a configuration in its flash, e.g., the
+    /// `stored_config` of a device before it was "rebooted".
+    pub fn start_with_config(unique_id: u64, stored_config: Option<DeviceConfig>) -> Self {
+        let state = Arc::new(Mutex::new(SimState {
+            brightness: 0,
+            picoboot_resets: 0,
+            pins: GPIO_PINS
+                .iter()
+                .map(|&number| SimPin {
+                    number,
+                    mode: PinMode::Input(Pull::None),
+                    external: None,
+                })
+                .collect(),
+            stored_config,
+            adc: [0; 3],
+            temperature: 27.0,
+            samples: Samples::default(),
+        }));
+        // The handlers expect a board and shared state that live forever, as
+        // on the Pico.
+        let board = Box::leak(Box::new(SimBoard {
+            state: state.clone(),
+        }));
+        let stats: &'static Stats = Box::leak(Box::new(Stats::new()));
+        let control: &'static BroadcastControl =
+            Box::leak(Box::new(BroadcastControl::new(BroadcastConfig::DEFAULT)));
+        let setpoints: &'static Setpoints = Box::leak(Box::new(Setpoints::new()));
+        let led: &'static LedControl = Box::leak(Box::new(LedControl::new(LedMode::OFF)));
+        let config: &'static ConfigControl = Box::leak(Box::new(ConfigControl::new()));
+
+        let (client_tx, server_rx) = mpsc::channel(CHANNEL_DEPTH);
+        let (server_tx, client_rx) = mpsc::channel(CHANNEL_DEPTH);
+        let client = client_channels::new_from_channels(client_tx, client_rx, VarSeqKind::Seq1);
+
+        let mut context = Context {
+            unique_id,
+            board,
+            stats,
+            broadcast: control,
+            setpoints,
+            led,
+            config,
+        };
+        template_device::config::load(&mut context);
+        let dispatcher = SimApp::new(context, ChannelWireSpawn {});
+        let kkind = dispatcher.min_key_len();
+        let mut server: SimServer = new_server(
+            dispatcher,
+            Settings {
+                tx: ChannelWireTx::new(server_tx),
+                rx: ChannelWireRx::new(server_rx),
+                buf: RX_BUF,
+                kkind,
+            },
+        );
+        let sender = server.sender();
+
+        let tasks = vec![
+            tokio::spawn(async move {
+                // Ends when the client is dropped.
+                let _ = server.run().await;
+            }),
+            tokio::spawn(logging_task(sender.clone())),
+            tokio::spawn(led_task(led, state.clone())),
+            tokio::spawn(measure(config, state.clone())),
+            tokio::spawn(broadcast(sender, stats, control, setpoints, state.clone())),
+        ];
+        Self {
+            client,
+            state,
+            tasks,
+        }
+    }
+
+    /// Brightness of the LED in percent.
+    pub fn brightness(&self) -> u8 {
+        self.state.lock().unwrap().brightness
+    }
+
+    pub fn picoboot_resets(&self) -> usize {
+        self.state.lock().unwrap().picoboot_resets
+    }
+
+    /// Drive an input from outside, or stop driving it with `None`.
+    ///
+    /// Panics if the pin is not on the allowlist.
+    pub fn drive_pin(&self, pin: u8, level: Option<PinLevel>) {
+        self.state.lock().unwrap().pin(pin).unwrap().external = level;
+    }
+
+    /// Panics if the pin is not on the allowlist.
+    pub fn pin_mode(&self, pin: u8) -> PinMode {
+        self.state.lock().unwrap().pin(pin).unwrap().mode
+    }
+
+    /// The configuration in the flash, to start another device with.
+    pub fn stored_config(&self) -> Option<DeviceConfig> {
+        self.state.lock().unwrap().stored_config.clone()
+    }
+
+    /// Set the raw readings of ADC0 to ADC2, reported by the broadcast after
+    /// the next sample.
+    pub fn set_adc(&self, adc: [u16; 3]) {
+        self.state.lock().unwrap().adc = adc;
+    }
+
+    /// Set the chip temperature, reported by the broadcast after the next
+    /// sample with the calibrated offset.
+    pub fn set_temperature(&self, temperature: f32) {
+        self.state.lock().unwrap().temperature = temperature;
+    }
+}
+
+impl Drop for SimDevice {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Same as the SPAWN handler of the firmware, with tokio's clock.
+async fn sleep_handler(
+    context: TaskContext,
+    header: VarHeader,
+    arg: SleepMillis,
+    sender: Sender<SimTx>,
+) {
+    context.stats.count_request();
+    let _ = sender.log_str("Starting sleep...").await;
+    let start = Instant::now();
+    sleep(Duration::from_millis(arg.millis.into())).await;
+    let _ = sender.log_str("Finished sleep").await;
+    let _ = sender
+        .reply::<SleepEndpoint>(
+            header.seq_no,
+            &SleptMillis {
+                millis: start.elapsed().as_millis() as u16,
+            },
+        )
+        .await;
+}
+
+/// The LED task of the firmware, sets the brightness in `state` instead of
+/// the PWM.
+async fn led_task(led: &'static LedControl, state: Arc<Mutex<SimState>>) {
+    'mode: loop {
+        let mode = led.mode();
+        for step in Pattern::new(mode) {
+            state.lock().unwrap().brightness = step.brightness;
+            match step.duration_ms {
+                Some(millis) => {
+                    tokio::select! {
+                        _ = sleep(Duration::from_millis(millis.into())) => {}
+                        _ = led.changed() => continue 'mode,
+                    }
+                }
+                None => {
+                    led.changed().await;
+                    continue 'mode;
+                }
+            }
+        }
+        led.finished(mode);
+        state.lock().unwrap().brightness = 0;
+        led.changed().await;
+    }
+}
+
+/// The "sign of life" logger of the firmware.
+async fn logging_task(sender: Sender<SimTx>) {
+    let mut ticker = interval(Duration::from_secs(3));
+    let start = Instant::now();
+    loop {
+        ticker.tick().await;
+        // Formatted first, `sender_fmt!` keeps the arguments across the await,
+        // which is not `Send` for `tokio::spawn`.
+        let msg = format!("Uptime: {:?}", start.elapsed());
+        let _ = sender.log_str(&msg).await;
+    }
+}
+
+/// The sampler of the firmware, ten times per second like `measure::measure`.
+async fn measure(config: &'static ConfigControl, state: Arc<Mutex<SimState>>) {
+    let period = Duration::from_millis(100);
+    let mut ticker = interval_at(Instant::now() + period, period);
+    loop {
+        ticker.tick().await;
+        let mut state = state.lock().unwrap();
+        state.samples = Samples {
+            adc: state.adc,
+            temperature: state.temperature + config.calibration().temperature_offset,
+        };
+    }
+}
+
+/// The status broadcast of the firmware, with the simulated measurements.
+async fn broadcast(
+    sender: Sender<SimTx>,
+    stats: &'static Stats,
+    control: &'static BroadcastControl,
+    setpoints: &'static Setpoints,
+    state: Arc<Mutex<SimState>>,
+) {
+    let mut seq = 0u8;
+    let start = Instant::now();
+    loop {
+        let config = control.config();
+        if !config.enabled {
+            control.changed().await;
+            continue;
+        }
+        let period = Duration::from_millis(config.interval_ms.into());
+        // Like the `Ticker` of the firmware, the first tick is one period from now.
+        let mut ticker = interval_at(Instant::now() + period, period);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = control.changed() => break,
+            }
+            let samples = state.lock().unwrap().samples;
+            let msg = CtrlStatus {
+                uptime_ms: start.elapsed().as_millis() as u64,
+                requests: stats.requests().into(),
+                adc: samples.adc,
+                temperature: samples.temperature,
+                setpoints: setpoints.all(),
+            };
+            let _ = sender.publish::<BcCtrlStatus>(seq.into(), &msg).await;
+            seq = seq.wrapping_add(1);
+        }
+    }
+}
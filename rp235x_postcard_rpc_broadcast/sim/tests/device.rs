@@ -0,0 +1,402 @@
+//! The host client against the simulated device, with a paused clock.
+
+use std::time::Duration;
+
+use postcard_rpc::host_client::MultiSubscription;
+use template_host::client::Client;
+use template_icd::{
+    BroadcastConfig, ConfigError, DeviceConfig, GpioError, IntervalOutOfRange, LedMode,
+    LedModeError, LedState, PinLevel, PinMode, Pull,
+};
+use template_sim::SimDevice;
+use tokio::time::{sleep, timeout};
+
+const UNIQUE_ID: u64 = 0x0123_4567_89ab_cdef;
+
+fn start() -> (SimDevice, Client) {
+    let device = SimDevice::start(UNIQUE_ID);
+    let client = Client::new(device.client.clone());
+    (device, client)
+}
+
+/// The next log message, without the periodic uptime messages.
+async fn next_log(logs: &mut MultiSubscription<String>) -> String {
+    loop {
+        let msg = logs.recv().await.unwrap();
+        if !msg.starts_with("Uptime") {
+            return msg;
+        }
+    }
+}
+
+/// Let the LED task follow a new mode, and the clock advance by `millis`.
+async fn wait_ms(millis: u64) {
+    sleep(Duration::from_millis(millis)).await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn unique_id() {
+    let (_device, client) = start();
+    assert_eq!(client.unique_id().await.unwrap(), UNIQUE_ID);
+}
+
+#[tokio::test(start_paused = true)]
+async fn led_set_and_get() {
+    let (device, client) = start();
+    assert_eq!(client.get_led().await.unwrap(), LedMode::OFF);
+
+    client.set_led(LedState::On).await.unwrap();
+    wait_ms(1).await;
+    assert_eq!(device.brightness(), 100);
+    assert_eq!(client.get_led().await.unwrap(), LedMode::ON);
+
+    client.set_led(LedState::Off).await.unwrap();
+    wait_ms(1).await;
+    assert_eq!(device.brightness(), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn led_brightness() {
+    let (device, client) = start();
+    let mode = LedMode::Steady { brightness: 30 };
+    client.set_led_mode(mode).await.unwrap().unwrap();
+    wait_ms(1).await;
+    assert_eq!(device.brightness(), 30);
+    assert_eq!(client.get_led().await.unwrap(), mode);
+}
+
+#[tokio::test(start_paused = true)]
+async fn led_blink() {
+    let (device, client) = start();
+    let mode = LedMode::Blink {
+        brightness: 80,
+        period_ms: 100,
+        duty: 25,
+    };
+    client.set_led_mode(mode).await.unwrap().unwrap();
+    wait_ms(1).await;
+    assert_eq!(device.brightness(), 80);
+    wait_ms(30).await;
+    assert_eq!(device.brightness(), 0);
+    wait_ms(80).await;
+    assert_eq!(device.brightness(), 80);
+    assert_eq!(client.get_led().await.unwrap(), mode);
+}
+
+#[tokio::test(start_paused = true)]
+async fn led_pulses_end_off() {
+    let (device, client) = start();
+    let mode = LedMode::Pulses {
+        brightness: 100,
+        period_ms: 100,
+        duty: 50,
+        count: 2,
+    };
+    client.set_led_mode(mode).await.unwrap().unwrap();
+    wait_ms(110).await;
+    assert_eq!(device.brightness(), 100);
+    assert_eq!(client.get_led().await.unwrap(), mode);
+
+    wait_ms(200).await;
+    assert_eq!(device.brightness(), 0);
+    assert_eq!(client.get_led().await.unwrap(), LedMode::OFF);
+}
+
+#[tokio::test(start_paused = true)]
+async fn led_heartbeat() {
+    let (device, client) = start();
+    let mode = LedMode::Heartbeat {
+        brightness: 100,
+        period_ms: 1000,
+    };
+    client.set_led_mode(mode).await.unwrap().unwrap();
+    let mut levels = Vec::new();
+    for _ in 0..6 {
+        wait_ms(50).await;
+        levels.push(device.brightness());
+        wait_ms(50).await;
+    }
+    assert_eq!(levels, [100, 0, 100, 0, 0, 0]);
+    // The next period starts at 1000 ms.
+    wait_ms(450).await;
+    assert_eq!(device.brightness(), 100);
+}
+
+#[tokio::test(start_paused = true)]
+async fn led_mode_rejected() {
+    let (_device, client) = start();
+    let too_bright = LedMode::Steady { brightness: 101 };
+    assert_eq!(
+        client.set_led_mode(too_bright).await.unwrap(),
+        Err(LedModeError::Brightness)
+    );
+    let too_fast = LedMode::Blink {
+        brightness: 100,
+        period_ms: 10,
+        duty: 50,
+    };
+    assert_eq!(
+        client.set_led_mode(too_fast).await.unwrap(),
+        Err(LedModeError::PeriodTooShort { min_ms: 20 })
+    );
+    assert_eq!(client.get_led().await.unwrap(), LedMode::OFF);
+}
+
+#[tokio::test(start_paused = true)]
+async fn sleep_logs_and_replies() {
+    let (_device, client) = start();
+    let mut logs = client.subscribe_logs().await.unwrap();
+
+    assert_eq!(client.sleep(250).await.unwrap(), 250);
+    assert_eq!(next_log(&mut logs).await, "Starting sleep...");
+    assert_eq!(next_log(&mut logs).await, "Finished sleep");
+}
+
+#[tokio::test(start_paused = true)]
+async fn concurrent_sleeps() {
+    let (_device, client) = start();
+    let (short, long) = tokio::join!(client.sleep(100), client.sleep(300));
+    assert_eq!(short.unwrap(), 100);
+    assert_eq!(long.unwrap(), 300);
+}
+
+#[tokio::test(start_paused = true)]
+async fn status_broadcast() {
+    let (device, client) = start();
+    device.set_adc([0, 2048, 4095]);
+    device.set_temperature(31.5);
+    let mut status = client.subscribe_status().await.unwrap();
+    let first = status.recv().await.unwrap();
+    assert_eq!(first.adc, [0, 2048, 4095]);
+    assert_eq!(first.temperature, 31.5);
+
+    client.unique_id().await.unwrap();
+    client.set_led(LedState::On).await.unwrap();
+    let second = status.recv().await.unwrap();
+    assert_eq!(second.requests, first.requests + 2);
+    assert_eq!(second.uptime_ms, first.uptime_ms + 1000);
+}
+
+#[tokio::test(start_paused = true)]
+async fn broadcast_interval() {
+    let (_device, client) = start();
+    assert_eq!(
+        client.broadcast_config().await.unwrap(),
+        BroadcastConfig::DEFAULT
+    );
+    let config = client.set_broadcast_interval(10).await.unwrap().unwrap();
+    assert_eq!(
+        config,
+        BroadcastConfig {
+            enabled: true,
+            interval_ms: 10,
+        }
+    );
+
+    let mut status = client.subscribe_status().await.unwrap();
+    let first = status.recv().await.unwrap();
+    let second = status.recv().await.unwrap();
+    assert_eq!(second.uptime_ms, first.uptime_ms + 10);
+}
+
+#[tokio::test(start_paused = true)]
+async fn broadcast_interval_out_of_range() {
+    let (_device, client) = start();
+    let range = IntervalOutOfRange {
+        min_ms: 10,
+        max_ms: 60_000,
+    };
+    assert_eq!(client.set_broadcast_interval(9).await.unwrap(), Err(range));
+    assert_eq!(
+        client.set_broadcast_interval(60_001).await.unwrap(),
+        Err(range)
+    );
+    assert_eq!(
+        client.broadcast_config().await.unwrap(),
+        BroadcastConfig::DEFAULT
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn broadcast_stop_and_start() {
+    let (_device, client) = start();
+    let mut status = client.subscribe_status().await.unwrap();
+    client.stop_broadcast().await.unwrap();
+    assert!(!client.broadcast_config().await.unwrap().enabled);
+    assert!(timeout(Duration::from_secs(5), status.recv())
+        .await
+        .is_err());
+
+    client.start_broadcast().await.unwrap();
+    assert!(timeout(Duration::from_secs(2), status.recv()).await.is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn setpoints() {
+    let (_device, client) = start();
+    client.publish_setpoint(0, 1.5).await.unwrap();
+    client.publish_setpoint(3, -2.0).await.unwrap();
+    // Not a channel of the device, dropped.
+    client.publish_setpoint(4, 7.0).await.unwrap();
+    client.publish_setpoint(0, 2.5).await.unwrap();
+
+    let mut status = client.subscribe_status().await.unwrap();
+    let msg = status.recv().await.unwrap();
+    assert_eq!(msg.setpoints, [2.5, 0.0, 0.0, -2.0]);
+    // Topics are not requests.
+    assert_eq!(msg.requests, 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn gpio_output() {
+    let (device, client) = start();
+    let mode = PinMode::Output(PinLevel::High);
+    client.configure_pin(2, mode).await.unwrap().unwrap();
+    assert_eq!(device.pin_mode(2), mode);
+    assert_eq!(client.get_pin(2).await.unwrap(), Ok(PinLevel::High));
+
+    client.set_pin(2, PinLevel::Low).await.unwrap().unwrap();
+    assert_eq!(device.pin_mode(2), PinMode::Output(PinLevel::Low));
+    assert_eq!(client.get_pin(2).await.unwrap(), Ok(PinLevel::Low));
+}
+
+#[tokio::test(start_paused = true)]
+async fn gpio_input() {
+    let (device, client) = start();
+    client
+        .configure_pin(3, PinMode::Input(Pull::Up))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(client.get_pin(3).await.unwrap(), Ok(PinLevel::High));
+    device.drive_pin(3, Some(PinLevel::Low));
+    assert_eq!(client.get_pin(3).await.unwrap(), Ok(PinLevel::Low));
+
+    assert_eq!(
+        client.set_pin(3, PinLevel::High).await.unwrap(),
+        Err(GpioError::NotAnOutput)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn gpio_allowlist() {
+    let (_device, client) = start();
+    // The LED, and the first ADC pin.
+    for pin in [25, 26] {
+        let mode = PinMode::Output(PinLevel::High);
+        assert_eq!(
+            client.configure_pin(pin, mode).await.unwrap(),
+            Err(GpioError::NotAllowed)
+        );
+        assert_eq!(
+            client.get_pin(pin).await.unwrap(),
+            Err(GpioError::NotAllowed)
+        );
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn adc() {
+    let (device, client) = start();
+    device.set_adc([10, 20, 30]);
+    assert_eq!(client.read_adc(1).await.unwrap(), Ok(20));
+    assert_eq!(
+        client.read_adc(3).await.unwrap(),
+        Err(GpioError::UnknownAdcChannel)
+    );
+}
+
+/// A configuration with every setting changed from the default.
+fn bench_config() -> DeviceConfig {
+    let mut config = DeviceConfig::DEFAULT;
+    config.nickname = "bench-1".try_into().unwrap();
+    config.broadcast.interval_ms = 100;
+    config.led = LedMode::Steady { brightness: 40 };
+    config.calibration.temperature_offset = -1.5;
+    config.calibration.adc_gain = [1.0, 1.0, 0.5];
+    config
+}
+
+#[tokio::test(start_paused = true)]
+async fn config_set_and_get() {
+    let (device, client) = start();
+    assert_eq!(client.get_config().await.unwrap(), DeviceConfig::DEFAULT);
+    assert_eq!(device.stored_config(), None);
+
+    client.set_config(&bench_config()).await.unwrap().unwrap();
+    assert_eq!(client.get_config().await.unwrap(), bench_config());
+    assert_eq!(device.stored_config(), Some(bench_config()));
+    // Applied at once.
+    assert_eq!(client.broadcast_config().await.unwrap().interval_ms, 100);
+    wait_ms(1).await;
+    assert_eq!(device.brightness(), 40);
+}
+
+#[tokio::test(start_paused = true)]
+async fn config_survives_reboot() {
+    let (device, client) = start();
+    client.set_config(&bench_config()).await.unwrap().unwrap();
+    drop(client);
+    let stored = device.stored_config();
+    drop(device);
+
+    let device = SimDevice::start_with_config(UNIQUE_ID, stored);
+    let client = Client::new(device.client.clone());
+    assert_eq!(client.get_config().await.unwrap(), bench_config());
+    wait_ms(1).await;
+    assert_eq!(device.brightness(), 40);
+
+    device.set_temperature(31.5);
+    let mut status = client.subscribe_status().await.unwrap();
+    let first = status.recv().await.unwrap();
+    let second = status.recv().await.unwrap();
+    assert_eq!(second.uptime_ms, first.uptime_ms + 100);
+    assert_eq!(second.temperature, 30.0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn config_reset() {
+    let (device, client) = start();
+    client.set_config(&bench_config()).await.unwrap().unwrap();
+    client.reset_config().await.unwrap().unwrap();
+    assert_eq!(client.get_config().await.unwrap(), DeviceConfig::DEFAULT);
+    assert_eq!(device.stored_config(), None);
+    assert_eq!(
+        client.broadcast_config().await.unwrap(),
+        BroadcastConfig::DEFAULT
+    );
+    wait_ms(1).await;
+    assert_eq!(device.brightness(), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn config_rejected() {
+    let (device, client) = start();
+    let mut config = bench_config();
+    config.broadcast.interval_ms = 5;
+    assert_eq!(
+        client.set_config(&config).await.unwrap(),
+        Err(ConfigError::BroadcastInterval(IntervalOutOfRange {
+            min_ms: 10,
+            max_ms: 60_000,
+        }))
+    );
+    let mut config = bench_config();
+    config.led = LedMode::Steady { brightness: 101 };
+    assert_eq!(
+        client.set_config(&config).await.unwrap(),
+        Err(ConfigError::LedMode(LedModeError::Brightness))
+    );
+    assert_eq!(client.get_config().await.unwrap(), DeviceConfig::DEFAULT);
+    assert_eq!(device.stored_config(), None);
+}
+
+#[tokio::test(start_paused = true)]
+async fn picoboot_reset() {
+    let (device, client) = start();
+    client
+        .picoboot_reset(Duration::from_millis(500))
+        .await
+        .unwrap();
+    assert_eq!(device.picoboot_resets(), 1);
+}
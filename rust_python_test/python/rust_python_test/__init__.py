@@ -1,8 +1,14 @@
 from pathlib import Path
 
-from rust_python_test._lowlevel import hello, lst_to_crd_rs
-
-__all__ = ["hello"]
+from rust_python_test._lowlevel import (
+    ShotStatistics,
+    drift_correction,
+    hello,
+    lst_to_crd_rs,
+    shot_statistics,
+)
+
+__all__ = ["ShotStatistics", "drift_correction", "hello", "shot_statistics"]
 
 def lst_to_crd() -> None:
     """Convert a list file to a crd file.
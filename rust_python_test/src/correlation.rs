@@ -0,0 +1,144 @@
+//! Multi-ion statistics per shot for CRD datasets.
+//!
+//! The number of ions per shot should follow a Poisson distribution if the
+//! detector is not saturated. Ions that are created together, e.g., by the
+//! fragmentation of a molecule, show up as correlated counts between mass
+//! windows within the same shot.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::check_crd_data;
+
+/// Result of `shot_statistics`, all fields are read-only attributes in Python.
+#[pyclass(frozen, get_all)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShotStatistics {
+    /// The number of shots that contain `k` ions, indexed by `k`.
+    pub multiplicity: Vec<u64>,
+    /// The Poisson expectation for the same, using the mean number of ions per shot.
+    pub poisson: Vec<f64>,
+    /// The number of ion pairs within the same shot for every pair of mass windows.
+    pub pairs: Vec<Vec<u64>>,
+    /// The expected number of such pairs for uncorrelated (Poisson) ions.
+    pub expected: Vec<Vec<f64>>,
+}
+
+/// Multi-ion statistics of a CRD dataset.
+///
+/// `mass_windows` is a list of `(start, stop)` channel ranges, `stop` is
+/// exclusive. Ions that fall into no window are ignored for the correlations, ions
+/// in overlapping windows are assigned to the first matching one.
+///
+/// Returns a `ShotStatistics` with the distribution of ions per shot and the
+/// ion pairs between mass windows.
+///
+/// Ion pairs are counted for distinct ions, i.e., a shot with `n` ions in window `i`
+/// contributes `n * (n - 1)` to the diagonal element `i, i`. The expectation of
+/// both, diagonal and off-diagonal elements, is `N_i * N_j / nof_shots` with `N_i`
+/// the total number of ions in window `i`.
+#[pyfunction]
+pub fn shot_statistics(
+    ions_per_shot: Vec<u32>,
+    all_tofs: Vec<u32>,
+    mass_windows: Vec<(u32, u32)>,
+) -> PyResult<ShotStatistics> {
+    check_crd_data(&ions_per_shot, &all_tofs)?;
+    if ions_per_shot.is_empty() {
+        return Err(PyValueError::new_err("Dataset contains no shots"));
+    }
+    if let Some((start, stop)) = mass_windows.iter().find(|(start, stop)| start >= stop) {
+        return Err(PyValueError::new_err(format!(
+            "Invalid mass window ({}, {}): start must be smaller than stop",
+            start, stop
+        )));
+    }
+
+    let nof_shots = ions_per_shot.len() as f64;
+    let max_ions = ions_per_shot.iter().max().copied().unwrap_or(0) as usize;
+
+    let mut multiplicity = vec![0u64; max_ions + 1];
+    ions_per_shot
+        .iter()
+        .for_each(|&n| multiplicity[n as usize] += 1);
+
+    let mean = all_tofs.len() as f64 / nof_shots;
+    let mut poisson = Vec::with_capacity(multiplicity.len());
+    let mut prob = (-mean).exp();
+    for k in 0..multiplicity.len() {
+        if k > 0 {
+            prob *= mean / k as f64;
+        }
+        poisson.push(prob * nof_shots);
+    }
+
+    let nof_windows = mass_windows.len();
+    let mut totals = vec![0u64; nof_windows];
+    let mut pairs = vec![vec![0u64; nof_windows]; nof_windows];
+    let mut in_shot = vec![0u64; nof_windows];
+    let mut start = 0;
+    for &n in ions_per_shot.iter() {
+        let end = start + n as usize;
+        in_shot.iter_mut().for_each(|c| *c = 0);
+        for &tof in all_tofs[start..end].iter() {
+            if let Some(win) = mass_windows
+                .iter()
+                .position(|&(lo, hi)| (lo..hi).contains(&tof))
+            {
+                in_shot[win] += 1;
+            }
+        }
+        for (it, &ni) in in_shot.iter().enumerate().filter(|(_, &ni)| ni > 0) {
+            totals[it] += ni;
+            for (jt, &nj) in in_shot.iter().enumerate() {
+                pairs[it][jt] += if it == jt { ni * (ni - 1) } else { ni * nj };
+            }
+        }
+        start = end;
+    }
+
+    let expected = totals
+        .iter()
+        .map(|&ni| {
+            totals
+                .iter()
+                .map(|&nj| ni as f64 * nj as f64 / nof_shots)
+                .collect()
+        })
+        .collect();
+
+    Ok(ShotStatistics {
+        multiplicity,
+        poisson,
+        pairs,
+        expected,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs_and_expectation() {
+        // Windows 0 and 1, the ion at 25 is in no window.
+        let ions_per_shot = vec![2, 3, 0, 1];
+        let all_tofs = vec![1, 15, 2, 3, 12, 25];
+        let stats = shot_statistics(ions_per_shot, all_tofs, vec![(0, 10), (10, 20)]).unwrap();
+
+        assert_eq!(stats.multiplicity, vec![1, 1, 1, 1]);
+        // Mean of 1.5 ions per shot, 4 shots.
+        let poisson: Vec<f64> = [1.0, 1.5, 1.125, 0.5625]
+            .iter()
+            .map(|p| p * (-1.5f64).exp() * 4.0)
+            .collect();
+        for (p, expected) in stats.poisson.iter().zip(poisson) {
+            assert!((p - expected).abs() < 1e-12);
+        }
+        // Shot 0 has one ion in each window, shot 1 two in window 0 and one in
+        // window 1.
+        assert_eq!(stats.pairs, vec![vec![2, 3], vec![3, 0]]);
+        // N_0 = 3, N_1 = 2, S = 4.
+        assert_eq!(stats.expected, vec![vec![2.25, 1.5], vec![1.5, 1.0]]);
+    }
+}
@@ -0,0 +1,228 @@
+//! Time-of-flight drift correction for CRD datasets.
+//!
+//! A CRD dataset is described here, as in `rimseval`, by the number of ions
+//! in every shot (`ions_per_shot`) and the flat list of all arrival times in
+//! channels (`all_tofs`). Shots are grouped into packages of equal size. For
+//! every package, a reference peak is located and the slow drift of its
+//! position is fitted with a polynomial, which is then used to shift the arrival
+//! times of every package back onto the position of the first package.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::check_crd_data;
+
+/// Correct the time-of-flight drift of a CRD dataset, package by package.
+///
+/// The reference peak is searched for in `peak_center +/- peak_half_width` in
+/// the first package. The window then follows the peak, i.e., it is re-centered
+/// on the centroid found in the previous package. Packages without any counts
+/// in the window are skipped in the fit.
+///
+/// Shots after the last complete package are not used to track the peak or in
+/// the fit. They are shifted by the same amount as the last complete package.
+///
+/// Returns a tuple with the corrected arrival times, the measured peak centroids
+/// per package (`NaN` if no counts were found), and the fitted drift curve per
+/// package.
+#[pyfunction]
+#[pyo3(signature = (ions_per_shot, all_tofs, shots_per_pkg, peak_center, peak_half_width, poly_deg=2))]
+pub fn drift_correction(
+    ions_per_shot: Vec<u32>,
+    all_tofs: Vec<u32>,
+    shots_per_pkg: usize,
+    peak_center: f64,
+    peak_half_width: f64,
+    poly_deg: usize,
+) -> PyResult<(Vec<u32>, Vec<f64>, Vec<f64>)> {
+    if shots_per_pkg == 0 {
+        return Err(PyValueError::new_err("shots_per_pkg must be larger than 0"));
+    }
+    if peak_half_width <= 0.0 {
+        return Err(PyValueError::new_err("peak_half_width must be positive"));
+    }
+    check_crd_data(&ions_per_shot, &all_tofs)?;
+
+    let packages = package_ranges(&ions_per_shot, shots_per_pkg);
+    let centroids = track_peak(&all_tofs, &packages, peak_center, peak_half_width);
+
+    let (xs, ys): (Vec<f64>, Vec<f64>) = centroids
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.is_finite())
+        .map(|(it, c)| (it as f64, *c))
+        .unzip();
+    if xs.len() <= poly_deg {
+        return Err(PyValueError::new_err(format!(
+            "Reference peak found in {} packages, need at least {} for a fit of degree {}",
+            xs.len(),
+            poly_deg + 1,
+            poly_deg
+        )));
+    }
+    let coeffs = polyfit(&xs, &ys, poly_deg)
+        .ok_or_else(|| PyValueError::new_err("Drift fit is singular"))?;
+    let drift: Vec<f64> = (0..packages.len())
+        .map(|it| polyval(&coeffs, it as f64))
+        .collect();
+
+    let mut tofs = all_tofs;
+    let tail = packages.last().map_or(0, |range| range.end)..tofs.len();
+    let fitted_tail = drift.last().copied().unwrap_or(drift[0]);
+    let ranges = packages.iter().chain(std::iter::once(&tail));
+    for (range, fitted) in ranges.zip(drift.iter().chain(std::iter::once(&fitted_tail))) {
+        let shift = (drift[0] - fitted).round() as i64;
+        for tof in tofs[range.clone()].iter_mut() {
+            *tof = (*tof as i64 + shift).clamp(0, u32::MAX as i64) as u32;
+        }
+    }
+
+    Ok((tofs, centroids, drift))
+}
+
+/// Ranges into `all_tofs` for every package of `shots_per_pkg` shots.
+///
+/// The last package is dropped if it is incomplete, since its statistics would
+/// not be comparable to the others. Its shots start at the end of the last range.
+fn package_ranges(ions_per_shot: &[u32], shots_per_pkg: usize) -> Vec<std::ops::Range<usize>> {
+    let mut start = 0;
+    ions_per_shot
+        .chunks_exact(shots_per_pkg)
+        .map(|shots| {
+            let end = start + shots.iter().map(|&n| n as usize).sum::<usize>();
+            let range = start..end;
+            start = end;
+            range
+        })
+        .collect()
+}
+
+/// Centroid of the reference peak in every package, following the peak along.
+fn track_peak(
+    tofs: &[u32],
+    packages: &[std::ops::Range<usize>],
+    center: f64,
+    half_width: f64,
+) -> Vec<f64> {
+    let mut center = center;
+    packages
+        .iter()
+        .map(|range| {
+            let (sum, cnt) = tofs[range.clone()]
+                .iter()
+                .map(|&t| t as f64)
+                .filter(|t| (t - center).abs() <= half_width)
+                .fold((0.0, 0usize), |(sum, cnt), t| (sum + t, cnt + 1));
+            if cnt == 0 {
+                return f64::NAN;
+            }
+            center = sum / cnt as f64;
+            center
+        })
+        .collect()
+}
+
+/// Least squares polynomial fit, coefficients in increasing order.
+///
+/// Solves the normal equations by Gaussian elimination with partial pivoting,
+/// which is plenty for the low degrees and few packages we deal with here.
+fn polyfit(xs: &[f64], ys: &[f64], deg: usize) -> Option<Vec<f64>> {
+    let n = deg + 1;
+    // Scale x to [0, 1] to keep the normal equations well conditioned.
+    let scale = xs.iter().cloned().fold(1.0, f64::max);
+    let mut mat = vec![vec![0.0; n + 1]; n];
+    for (&x, &y) in xs.iter().zip(ys) {
+        let x = x / scale;
+        for (row, line) in mat.iter_mut().enumerate() {
+            for (col, val) in line[..n].iter_mut().enumerate() {
+                *val += x.powi((row + col) as i32);
+            }
+            line[n] += y * x.powi(row as i32);
+        }
+    }
+
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&a, &b| mat[a][col].abs().total_cmp(&mat[b][col].abs()))?;
+        if mat[pivot][col].abs() < f64::EPSILON {
+            return None;
+        }
+        mat.swap(col, pivot);
+        let (upper, lower) = mat.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for line in lower.iter_mut() {
+            let factor = line[col] / pivot_row[col];
+            for (val, pv) in line[col..].iter_mut().zip(&pivot_row[col..]) {
+                *val -= factor * pv;
+            }
+        }
+    }
+
+    let mut coeffs = vec![0.0; n];
+    for row in (0..n).rev() {
+        let acc: f64 = (row + 1..n).map(|k| mat[row][k] * coeffs[k]).sum();
+        coeffs[row] = (mat[row][n] - acc) / mat[row][row];
+    }
+    // Undo the scaling of x.
+    for (it, c) in coeffs.iter_mut().enumerate() {
+        *c /= scale.powi(it as i32);
+    }
+    Some(coeffs)
+}
+
+/// Evaluate a polynomial with coefficients in increasing order.
+fn polyval(coeffs: &[f64], x: f64) -> f64 {
+    coeffs.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polyfit_recovers_polynomial() {
+        let coeffs = [2.0, -0.5, 0.25];
+        let xs: Vec<f64> = (0..10).map(|x| x as f64).collect();
+        let ys: Vec<f64> = xs.iter().map(|&x| polyval(&coeffs, x)).collect();
+        let fit = polyfit(&xs, &ys, 2).unwrap();
+        for (c, expected) in fit.iter().zip(coeffs) {
+            assert!((c - expected).abs() < 1e-9, "{:?}", fit);
+        }
+    }
+
+    #[test]
+    fn polyval_increasing_order() {
+        assert_eq!(polyval(&[1.0, 2.0, 3.0], 2.0), 17.0);
+        assert_eq!(polyval(&[], 2.0), 0.0);
+    }
+
+    #[test]
+    fn polyfit_singular() {
+        assert_eq!(polyfit(&[1.0, 1.0, 1.0], &[1.0, 2.0, 3.0], 1), None);
+    }
+
+    #[test]
+    fn drift_correction_linear_drift() {
+        // 5 packages of 4 shots and a tail of 2 shots, two ions per shot: one in
+        // the reference peak and one far away, both drift by 3 channels per
+        // package.
+        let shots = 5 * 4 + 2;
+        let ions_per_shot = vec![2; shots];
+        let all_tofs: Vec<u32> = (0..shots)
+            .flat_map(|shot| {
+                let drift = 3 * (shot / 4).min(4) as u32;
+                [1000 + drift, 2000 + drift]
+            })
+            .collect();
+
+        let (tofs, centroids, drift) =
+            drift_correction(ions_per_shot, all_tofs, 4, 1000.0, 5.0, 1).unwrap();
+
+        assert_eq!(centroids, vec![1000.0, 1003.0, 1006.0, 1009.0, 1012.0]);
+        for (fitted, expected) in drift.iter().zip(centroids) {
+            assert!((fitted - expected).abs() < 1e-9, "{:?}", drift);
+        }
+        // The tail is shifted like the last complete package.
+        let expected: Vec<u32> = (0..shots).flat_map(|_| [1000, 2000]).collect();
+        assert_eq!(tofs, expected);
+    }
+}
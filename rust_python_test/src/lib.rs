@@ -1,8 +1,11 @@
 use std::fs::File;
 
-use pyo3::exceptions::PyFileNotFoundError;
+use pyo3::exceptions::{PyFileNotFoundError, PyValueError};
 use pyo3::prelude::*;
 
+mod correlation;
+mod drift;
+
 /// Prints a message.
 #[pyfunction]
 fn hello() -> PyResult<String> {
@@ -19,10 +22,26 @@ fn lst_to_crd_rs(fname: &str) -> PyResult<()> {
     Ok(())
 }
 
+/// Check that `ions_per_shot` and `all_tofs` describe the same CRD dataset.
+fn check_crd_data(ions_per_shot: &[u32], all_tofs: &[u32]) -> PyResult<()> {
+    let total_ions: u64 = ions_per_shot.iter().map(|&n| n as u64).sum();
+    if total_ions != all_tofs.len() as u64 {
+        return Err(PyValueError::new_err(format!(
+            "ions_per_shot sums to {} but all_tofs contains {} entries",
+            total_ions,
+            all_tofs.len()
+        )));
+    }
+    Ok(())
+}
+
 /// A Python module implemented in Rust.
 #[pymodule]
 fn _lowlevel(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(hello, m)?)?;
     m.add_function(wrap_pyfunction!(lst_to_crd_rs, m)?)?;
+    m.add_function(wrap_pyfunction!(drift::drift_correction, m)?)?;
+    m.add_function(wrap_pyfunction!(correlation::shot_statistics, m)?)?;
+    m.add_class::<correlation::ShotStatistics>()?;
     Ok(())
 }
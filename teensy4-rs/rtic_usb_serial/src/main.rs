@@ -1,8 +1,9 @@
 //! This code is based on the teensy4-rs template for the defmt logger
 //!
-//! It demonstrates a simple loopback device, i.e.,
-//! it reads a given command into a buffer and as soon as a newline
-//! is found, sends the buffer back with a newline character appended.
+//! It reads a given command into a buffer and as soon as a newline
+//! is found, hands the line to our `scpi_parser` and sends the reply back
+//! with a newline character appended. Supported are `LED[:STATe] ON|OFF`,
+//! `LED[:STATe]?`, and the built-in commands like `*IDN?` and `SYST:ERR?`.
 //!
 //! Note: When testing with picocom, make sure to use the following mappigns:
 //! --omap crlf
@@ -10,10 +11,8 @@
 //! These mappings are necessary since we assume a simple b'\n' as the terminator.
 //!
 //!
-//! The following parts would need to be implemented to make this production ready:
-//! - Error handling...
-//! - Make sure the buffer does not overflow
-//! - Implement a proper command parser
+//! Lines that do not fit into the read buffer are discarded and an
+//! input buffer overrun is added to the SCPI error queue.
 
 #![no_std]
 #![no_main]
@@ -39,6 +38,9 @@ mod app {
 
     use rtic_monotonics::systick::*;
 
+    use core::fmt::Write;
+    use scpi_parser::{Params, Parser, Response, ScpiError};
+
     /// We're intentionally using a full-speed device instead of a high-speed
     /// device. The full-speed device has better support in the usb-device
     /// ecosystem (in terms of packages and host support), and we don't need a
@@ -51,11 +53,39 @@ mod app {
 
     const TERMINATOR: u8 = b'\n';
 
+    /// Reply to `*IDN?`.
+    const IDN: &str = "Teensy,rtic_usb_serial,0,0.1";
+
+    /// Everything the SCPI command handlers have access to.
+    pub struct Device {
+        led: board::Led,
+    }
+
+    fn set_led(device: &mut Device, params: Params, _: &mut Response) -> Result<(), ScpiError> {
+        params.at_most(1)?;
+        match params.arg(0)?.to_bool()? {
+            true => device.led.set(),
+            false => device.led.clear(),
+        }
+        Ok(())
+    }
+
+    fn get_led(device: &mut Device, _: Params, resp: &mut Response) -> Result<(), ScpiError> {
+        resp.write_str(if device.led.is_set() { "1" } else { "0" })?;
+        Ok(())
+    }
+
+    fn reset(device: &mut Device, _: Params, _: &mut Response) -> Result<(), ScpiError> {
+        device.led.clear();
+        Ok(())
+    }
+
     #[local]
     struct Local {
         usb_class: SerialPort<'static, BusAdapter>,
         usb_device: UsbDevice<'static, BusAdapter>,
-        led: board::Led,
+        device: Device,
+        parser: Parser<Device, 4>,
     }
 
     #[shared]
@@ -79,6 +109,11 @@ mod app {
         } = board::t40(cx.device);
         let led = board::led(&mut gpio2, pins.p13);
 
+        let mut parser = Parser::new(IDN);
+        parser.register("LED[:STATe]", set_led).unwrap();
+        parser.register("LED[:STATe]?", get_led).unwrap();
+        parser.register("*RST", reset).unwrap();
+
         let bus_adapter = BusAdapter::with_speed(usb, cx.local.ep_memory, cx.local.ep_state, SPEED);
         bus_adapter.set_interrupts(true);
         bus_adapter.gpt_mut(Gpt0, |gpt| {
@@ -123,18 +158,21 @@ mod app {
             Local {
                 usb_class,
                 usb_device,
-                led,
+                device: Device { led },
+                parser,
             },
         )
     }
 
-    #[task(binds = USB_OTG1, local = [usb_class, usb_device, led, configured: bool = false], shared=[read_buffer, read_index])]
+    #[task(binds = USB_OTG1, local = [usb_class, usb_device, device, parser, configured: bool = false, overrun: bool = false], shared=[read_buffer, read_index])]
     fn usb_interrupt(mut cx: usb_interrupt::Context) {
         let usb_interrupt::LocalResources {
             usb_class,
             usb_device,
-            led,
+            device,
+            parser,
             configured,
+            overrun,
             ..
         } = cx.local;
 
@@ -146,28 +184,39 @@ mod app {
                 *configured = true;
 
                 let mut buffer = [0; 256];
-                match usb_class.read(&mut buffer) {
-                    Ok(count) => {
-                        led.clear();
-                        cx.shared.read_index.lock(|ind| {
-                            cx.shared.read_buffer.lock(|buf| {
-                                for chr in buffer.iter().take(count) {
-                                    if *chr == TERMINATOR {
-                                        led.toggle();
-                                        let _ = usb_class.write(&buf[..*ind]);
-                                        let _ = usb_class.write(&[TERMINATOR]);
-                                        *ind = 0; // no need to clear the buffer...
+                if let Ok(count) = usb_class.read(&mut buffer) {
+                    cx.shared.read_index.lock(|ind| {
+                        cx.shared.read_buffer.lock(|buf| {
+                            for chr in buffer.iter().take(count) {
+                                if *chr == TERMINATOR {
+                                    if *overrun {
+                                        parser.push_error(ScpiError::InputBufferOverrun);
+                                        *overrun = false;
                                     } else {
-                                        buf[*ind] = *chr;
-                                        *ind += 1;
-                                    };
+                                        let mut reply = [0; 256];
+                                        let len = match core::str::from_utf8(&buf[..*ind]) {
+                                            Ok(line) => parser.process(device, line, &mut reply),
+                                            Err(_) => {
+                                                parser.push_error(ScpiError::SyntaxError);
+                                                0
+                                            }
+                                        };
+                                        if len > 0 {
+                                            let _ = usb_class.write(&reply[..len]);
+                                            let _ = usb_class.write(&[TERMINATOR]);
+                                        }
+                                    }
+                                    *ind = 0; // no need to clear the buffer...
+                                } else if *ind < buf.len() {
+                                    buf[*ind] = *chr;
+                                    *ind += 1;
+                                } else {
+                                    // Discard the rest of the line.
+                                    *overrun = true;
                                 };
-                            });
+                            };
                         });
-                    }
-                    Err(_) => {
-                        led.toggle();
-                    }
+                    });
                 }
             } else {
                 *configured = false;
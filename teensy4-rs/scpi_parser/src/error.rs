@@ -0,0 +1,176 @@
+//! SCPI errors and the error queue.
+
+use core::fmt;
+
+/// Standard SCPI errors, as reported by `SYSTem:ERRor?`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScpiError {
+    CommandError,
+    SyntaxError,
+    DataTypeError,
+    ParameterNotAllowed,
+    MissingParameter,
+    UndefinedHeader,
+    ExecutionError,
+    DataOutOfRange,
+    TooMuchData,
+    QueueOverflow,
+    InputBufferOverrun,
+}
+
+impl ScpiError {
+    /// Error code as defined by the SCPI standard.
+    pub const fn code(self) -> i16 {
+        match self {
+            ScpiError::CommandError => -100,
+            ScpiError::SyntaxError => -102,
+            ScpiError::DataTypeError => -104,
+            ScpiError::ParameterNotAllowed => -108,
+            ScpiError::MissingParameter => -109,
+            ScpiError::UndefinedHeader => -113,
+            ScpiError::ExecutionError => -200,
+            ScpiError::DataOutOfRange => -222,
+            ScpiError::TooMuchData => -223,
+            ScpiError::QueueOverflow => -350,
+            ScpiError::InputBufferOverrun => -363,
+        }
+    }
+
+    /// Error message as defined by the SCPI standard.
+    pub const fn message(self) -> &'static str {
+        match self {
+            ScpiError::CommandError => "Command error",
+            ScpiError::SyntaxError => "Syntax error",
+            ScpiError::DataTypeError => "Data type error",
+            ScpiError::ParameterNotAllowed => "Parameter not allowed",
+            ScpiError::MissingParameter => "Missing parameter",
+            ScpiError::UndefinedHeader => "Undefined header",
+            ScpiError::ExecutionError => "Execution error",
+            ScpiError::DataOutOfRange => "Data out of range",
+            ScpiError::TooMuchData => "Too much data",
+            ScpiError::QueueOverflow => "Queue overflow",
+            ScpiError::InputBufferOverrun => "Input buffer overrun",
+        }
+    }
+}
+
+impl fmt::Display for ScpiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},\"{}\"", self.code(), self.message())
+    }
+}
+
+/// Writing a response only fails if it does not fit into the output buffer.
+impl From<fmt::Error> for ScpiError {
+    fn from(_: fmt::Error) -> Self {
+        ScpiError::TooMuchData
+    }
+}
+
+/// First in, first out queue of errors with a fixed capacity `Q`.
+///
+/// As required by SCPI, the most recent error is replaced by a
+/// `QueueOverflow` if the queue is full.
+pub struct ErrorQueue<const Q: usize> {
+    errors: [ScpiError; Q],
+    head: usize,
+    len: usize,
+}
+
+impl<const Q: usize> ErrorQueue<Q> {
+    pub const fn new() -> Self {
+        Self {
+            errors: [ScpiError::CommandError; Q],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, error: ScpiError) {
+        if Q == 0 {
+            return;
+        }
+        if self.len == Q {
+            self.errors[(self.head + Q - 1) % Q] = ScpiError::QueueOverflow;
+            return;
+        }
+        self.errors[(self.head + self.len) % Q] = error;
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<ScpiError> {
+        if self.len == 0 {
+            return None;
+        }
+        let error = self.errors[self.head];
+        self.head = (self.head + 1) % Q;
+        self.len -= 1;
+        Some(error)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+impl<const Q: usize> Default for ErrorQueue<Q> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_is_fifo() {
+        let mut queue = ErrorQueue::<4>::new();
+        queue.push(ScpiError::SyntaxError);
+        queue.push(ScpiError::UndefinedHeader);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.pop(), Some(ScpiError::SyntaxError));
+        assert_eq!(queue.pop(), Some(ScpiError::UndefinedHeader));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn queue_overflow_replaces_last_error() {
+        let mut queue = ErrorQueue::<2>::new();
+        queue.push(ScpiError::SyntaxError);
+        queue.push(ScpiError::UndefinedHeader);
+        queue.push(ScpiError::DataTypeError);
+        assert_eq!(queue.pop(), Some(ScpiError::SyntaxError));
+        assert_eq!(queue.pop(), Some(ScpiError::QueueOverflow));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn queue_wraps_around() {
+        let mut queue = ErrorQueue::<2>::new();
+        for _ in 0..5 {
+            queue.push(ScpiError::MissingParameter);
+            assert_eq!(queue.pop(), Some(ScpiError::MissingParameter));
+        }
+        queue.push(ScpiError::SyntaxError);
+        queue.clear();
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn display_as_error_query_reply() {
+        let mut buf = [0u8; 32];
+        let mut resp = crate::Response::new(&mut buf);
+        core::fmt::write(&mut resp, format_args!("{}", ScpiError::UndefinedHeader)).unwrap();
+        assert_eq!(resp.as_bytes(), b"-113,\"Undefined header\"");
+    }
+}
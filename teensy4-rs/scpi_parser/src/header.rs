@@ -0,0 +1,168 @@
+//! Matching of command headers against registered patterns.
+//!
+//! Patterns use the usual SCPI notation: the uppercase part of a node is the
+//! short form, the full node is the long form, and optional nodes are given in
+//! brackets, e.g., `MEASure[:SCALar]:VOLTage?` matches `MEAS:VOLT?`,
+//! `measure:scalar:voltage?`, and so on. Common commands start with a `*`.
+
+/// Maximum number of nodes in a header or pattern.
+pub const MAX_DEPTH: usize = 8;
+
+/// A list of header nodes without allocation.
+#[derive(Clone, Copy)]
+pub(crate) struct Nodes<'a> {
+    nodes: [&'a str; MAX_DEPTH],
+    len: usize,
+}
+
+impl<'a> Nodes<'a> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            nodes: [""; MAX_DEPTH],
+            len: 0,
+        }
+    }
+
+    /// Append the `:` separated nodes of `header`, fails if too deep.
+    pub(crate) fn extend(&mut self, header: &'a str) -> Option<()> {
+        for node in header.split(':') {
+            *self.nodes.get_mut(self.len)? = node;
+            self.len += 1;
+        }
+        Some(())
+    }
+
+    /// All but the last node, i.e., the path for following commands.
+    pub(crate) fn parent(&self) -> Self {
+        Self {
+            nodes: self.nodes,
+            len: self.len.saturating_sub(1),
+        }
+    }
+
+    pub(crate) fn as_slice(&self) -> &[&'a str] {
+        &self.nodes[..self.len]
+    }
+}
+
+/// Does the header given by `nodes` (and `query`) match `pattern`?
+pub(crate) fn matches(pattern: &str, nodes: &[&str], query: bool) -> bool {
+    let (pattern, pattern_query) = match pattern.strip_suffix('?') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    if query != pattern_query {
+        return false;
+    }
+    let pattern = pattern.strip_prefix(':').unwrap_or(pattern);
+
+    let mut pat_nodes = [("", false); MAX_DEPTH];
+    let mut len = 0;
+    let mut rest = pattern;
+    while !rest.is_empty() {
+        let (node, optional, tail) = if let Some(opt) = rest.strip_prefix('[') {
+            let Some((node, tail)) = opt.split_once(']') else {
+                return false;
+            };
+            (node.trim_start_matches(':'), true, tail)
+        } else {
+            let rest = rest.strip_prefix(':').unwrap_or(rest);
+            let end = rest.find([':', '[']).unwrap_or(rest.len());
+            (&rest[..end], false, &rest[end..])
+        };
+        let Some(slot) = pat_nodes.get_mut(len) else {
+            return false;
+        };
+        *slot = (node, optional);
+        len += 1;
+        rest = tail;
+    }
+
+    match_nodes(&pat_nodes[..len], nodes)
+}
+
+fn match_nodes(pattern: &[(&str, bool)], nodes: &[&str]) -> bool {
+    match (pattern.split_first(), nodes.split_first()) {
+        (None, None) => true,
+        (None, Some(_)) => false,
+        (Some(((_, optional), pat_rest)), None) => *optional && match_nodes(pat_rest, nodes),
+        (Some(((pat, optional), pat_rest)), Some((node, rest))) => {
+            (node_matches(pat, node) && match_nodes(pat_rest, rest))
+                || (*optional && match_nodes(pat_rest, nodes))
+        }
+    }
+}
+
+/// Compare a single node against its short and long form, ignoring case.
+fn node_matches(pattern: &str, node: &str) -> bool {
+    let short_len = pattern
+        .find(|c: char| c.is_ascii_lowercase())
+        .unwrap_or(pattern.len());
+    node.eq_ignore_ascii_case(pattern) || node.eq_ignore_ascii_case(&pattern[..short_len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(pattern: &str, header: &str) -> bool {
+        let (header, query) = match header.strip_suffix('?') {
+            Some(header) => (header, true),
+            None => (header, false),
+        };
+        let mut nodes = Nodes::new();
+        nodes
+            .extend(header.strip_prefix(':').unwrap_or(header))
+            .unwrap();
+        matches(pattern, nodes.as_slice(), query)
+    }
+
+    #[test]
+    fn short_and_long_form() {
+        assert!(check("MEASure:VOLTage?", "MEAS:VOLT?"));
+        assert!(check("MEASure:VOLTage?", "measure:voltage?"));
+        assert!(check("MEASure:VOLTage?", "Meas:Voltage?"));
+        assert!(check("MEASure:VOLTage?", ":MEAS:VOLT?"));
+        assert!(!check("MEASure:VOLTage?", "MEASU:VOLT?"));
+        assert!(!check("MEASure:VOLTage?", "MEA:VOLT?"));
+    }
+
+    #[test]
+    fn query_must_match() {
+        assert!(!check("MEASure:VOLTage?", "MEAS:VOLT"));
+        assert!(!check("DELAY", "DELAY?"));
+        assert!(check("DELAY", "delay"));
+    }
+
+    #[test]
+    fn optional_nodes() {
+        let pattern = "MEASure[:SCALar]:VOLTage?";
+        assert!(check(pattern, "MEAS:VOLT?"));
+        assert!(check(pattern, "MEAS:SCAL:VOLT?"));
+        assert!(!check(pattern, "MEAS:SCAL?"));
+        assert!(check("SYSTem:ERRor[:NEXT]?", "SYST:ERR?"));
+        assert!(check("SYSTem:ERRor[:NEXT]?", "SYST:ERR:NEXT?"));
+        assert!(check("[SOURce]:VOLTage", "VOLT"));
+        assert!(check("[SOURce]:VOLTage", "SOUR:VOLT"));
+    }
+
+    #[test]
+    fn hierarchy_must_match() {
+        assert!(!check("MEASure:VOLTage?", "VOLT?"));
+        assert!(!check("MEASure:VOLTage?", "MEAS:VOLT:DC?"));
+        assert!(!check("MEASure:VOLTage?", "MEAS?"));
+    }
+
+    #[test]
+    fn common_commands() {
+        assert!(check("*IDN?", "*idn?"));
+        assert!(!check("*IDN?", "*IDN"));
+        assert!(check("*RST", "*RST"));
+    }
+
+    #[test]
+    fn too_deep() {
+        let mut nodes = Nodes::new();
+        assert!(nodes.extend("A:B:C:D:E:F:G:H:I").is_none());
+    }
+}
@@ -0,0 +1,448 @@
+//! A small `no_std` SCPI parser for our firmware.
+//!
+//! Commands are registered with their SCPI pattern (e.g., `MEASure:VOLTage?`)
+//! and a handler function. Handlers get a mutable reference to a context of
+//! your choice (e.g., the peripherals owned by an RTIC task), the parameters of
+//! the command, and a response buffer to write query replies to.
+//!
+//! No allocation is done: the number of commands `N` and the size of the error
+//! queue `Q` are given as const generics, responses are written into a buffer
+//! provided by the caller.
+//!
+//! The following commands are built in and can be overridden by registering
+//! them: `*IDN?`, `*RST`, `*CLS`, `*OPC?`, `SYSTem:ERRor[:NEXT]?`, and
+//! `SYSTem:ERRor:COUNt?`.
+//!
+//! ```
+//! use core::fmt::Write;
+//! use scpi_parser::{Params, Parser, Response, ScpiError};
+//!
+//! struct Device {
+//!     voltage: f32,
+//! }
+//!
+//! fn set_voltage(dev: &mut Device, params: Params, _: &mut Response) -> Result<(), ScpiError> {
+//!     dev.voltage = params.arg(0)?.to_f32()?;
+//!     Ok(())
+//! }
+//!
+//! fn get_voltage(dev: &mut Device, _: Params, resp: &mut Response) -> Result<(), ScpiError> {
+//!     write!(resp, "{}", dev.voltage)?;
+//!     Ok(())
+//! }
+//!
+//! let mut parser: Parser<Device, 4> = Parser::new("ACME,Device,0,0.1");
+//! parser.register("[SOURce]:VOLTage", set_voltage).unwrap();
+//! parser.register("MEASure:VOLTage?", get_voltage).unwrap();
+//!
+//! let mut dev = Device { voltage: 0.0 };
+//! let mut out = [0u8; 64];
+//! let len = parser.process(&mut dev, "VOLT 1.5;:MEAS:VOLT?", &mut out);
+//! assert_eq!(&out[..len], b"1.5");
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+use core::fmt::{self, Write};
+
+mod error;
+mod header;
+mod params;
+
+pub use crate::error::{ErrorQueue, ScpiError};
+pub use crate::header::MAX_DEPTH;
+pub use crate::params::{Param, Params};
+
+use crate::header::{matches, Nodes};
+use crate::params::split_unquoted;
+
+/// Handler of a registered command.
+pub type Handler<C> = fn(&mut C, Params, &mut Response) -> Result<(), ScpiError>;
+
+/// Returned by `Parser::register` if all command slots are taken.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TooManyCommands;
+
+struct Command<C> {
+    pattern: &'static str,
+    handler: Handler<C>,
+}
+
+/// Fixed size buffer that query replies are written to.
+pub struct Response<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Response<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+}
+
+impl Write for Response<'_> {
+    /// Fails without writing anything if `s` does not fit.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// SCPI parser with up to `N` registered commands and an error queue of length `Q`.
+pub struct Parser<C, const N: usize, const Q: usize = 8> {
+    idn: &'static str,
+    commands: [Option<Command<C>>; N],
+    errors: ErrorQueue<Q>,
+}
+
+impl<C, const N: usize, const Q: usize> Parser<C, N, Q> {
+    /// Create a parser, `idn` is the reply to `*IDN?`.
+    pub const fn new(idn: &'static str) -> Self {
+        Self {
+            idn,
+            commands: [const { None }; N],
+            errors: ErrorQueue::new(),
+        }
+    }
+
+    /// Register a command. Queries must end with a `?` in the pattern.
+    pub fn register(
+        &mut self,
+        pattern: &'static str,
+        handler: Handler<C>,
+    ) -> Result<(), TooManyCommands> {
+        let slot = self
+            .commands
+            .iter_mut()
+            .find(|c| c.is_none())
+            .ok_or(TooManyCommands)?;
+        *slot = Some(Command { pattern, handler });
+        Ok(())
+    }
+
+    /// Add an error to the queue, e.g., if the input buffer overflowed.
+    pub fn push_error(&mut self, error: ScpiError) {
+        self.errors.push(error);
+    }
+
+    pub fn errors(&self) -> &ErrorQueue<Q> {
+        &self.errors
+    }
+
+    /// Process one line of input and write the query replies to `out`.
+    ///
+    /// Several commands can be given on one line, separated by `;`. As usual
+    /// for SCPI, headers following a `;` are relative to the path of the
+    /// previous command, unless they start with a `:`. Replies of several
+    /// queries are separated by `;` as well. Errors are added to the error
+    /// queue.
+    ///
+    /// Returns the number of bytes written to `out`, without a terminator.
+    pub fn process(&mut self, ctx: &mut C, line: &str, out: &mut [u8]) -> usize {
+        let mut resp = Response::new(out);
+        let mut path = Nodes::new();
+
+        for msg in split_unquoted(line, ';') {
+            let msg = msg.trim();
+            if msg.is_empty() {
+                continue;
+            }
+            let (header, params) = msg
+                .split_once(|c: char| c.is_ascii_whitespace())
+                .unwrap_or((msg, ""));
+            let (header, query) = match header.strip_suffix('?') {
+                Some(header) => (header, true),
+                None => (header, false),
+            };
+
+            let mut nodes = Nodes::new();
+            let header = match header.strip_prefix(':') {
+                Some(header) => header,
+                None if !header.starts_with('*') => {
+                    nodes = path;
+                    header
+                }
+                None => header,
+            };
+            if header.split(':').any(str::is_empty) || nodes.extend(header).is_none() {
+                self.errors.push(ScpiError::SyntaxError);
+                continue;
+            }
+            if !header.starts_with('*') {
+                path = nodes.parent();
+            }
+
+            let mark = resp.len();
+            if !resp.is_empty() && resp.write_char(';').is_err() {
+                self.errors.push(ScpiError::TooMuchData);
+                continue;
+            }
+            let start = resp.len();
+            match self.execute(ctx, nodes.as_slice(), query, Params::new(params), &mut resp) {
+                Ok(()) if resp.len() > start => {}
+                Ok(()) => resp.truncate(mark),
+                Err(e) => {
+                    resp.truncate(mark);
+                    self.errors.push(e);
+                }
+            }
+        }
+
+        resp.len()
+    }
+
+    fn execute(
+        &mut self,
+        ctx: &mut C,
+        nodes: &[&str],
+        query: bool,
+        params: Params,
+        resp: &mut Response,
+    ) -> Result<(), ScpiError> {
+        let is = |pattern| matches(pattern, nodes, query);
+
+        if let Some(cmd) = self.commands.iter().flatten().find(|c| is(c.pattern)) {
+            return (cmd.handler)(ctx, params, resp);
+        }
+
+        if is("*IDN?") {
+            params.at_most(0)?;
+            resp.write_str(self.idn)?;
+        } else if is("*RST") {
+            // Nothing to reset in the parser itself.
+            params.at_most(0)?;
+        } else if is("*CLS") {
+            params.at_most(0)?;
+            self.errors.clear();
+        } else if is("*OPC?") {
+            // All commands are executed sequentially.
+            params.at_most(0)?;
+            resp.write_char('1')?;
+        } else if is("SYSTem:ERRor[:NEXT]?") {
+            params.at_most(0)?;
+            match self.errors.pop() {
+                Some(e) => write!(resp, "{}", e)?,
+                None => resp.write_str("0,\"No error\"")?,
+            }
+        } else if is("SYSTem:ERRor:COUNt?") {
+            params.at_most(0)?;
+            write!(resp, "{}", self.errors.len())?;
+        } else {
+            return Err(ScpiError::UndefinedHeader);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Device {
+        voltage: f32,
+        output: bool,
+        resets: u32,
+    }
+
+    fn set_voltage(dev: &mut Device, params: Params, _: &mut Response) -> Result<(), ScpiError> {
+        params.at_most(1)?;
+        let voltage = params.arg(0)?.to_f32()?;
+        if !(0.0..=10.0).contains(&voltage) {
+            return Err(ScpiError::DataOutOfRange);
+        }
+        dev.voltage = voltage;
+        Ok(())
+    }
+
+    fn get_voltage(dev: &mut Device, _: Params, resp: &mut Response) -> Result<(), ScpiError> {
+        write!(resp, "{}", dev.voltage)?;
+        Ok(())
+    }
+
+    fn set_output(dev: &mut Device, params: Params, _: &mut Response) -> Result<(), ScpiError> {
+        dev.output = params.arg(0)?.to_bool()?;
+        Ok(())
+    }
+
+    fn get_output(dev: &mut Device, _: Params, resp: &mut Response) -> Result<(), ScpiError> {
+        resp.write_str(if dev.output { "1" } else { "0" })?;
+        Ok(())
+    }
+
+    fn reset(dev: &mut Device, _: Params, _: &mut Response) -> Result<(), ScpiError> {
+        *dev = Device {
+            resets: dev.resets + 1,
+            ..Default::default()
+        };
+        Ok(())
+    }
+
+    fn parser() -> Parser<Device, 8, 4> {
+        let mut parser = Parser::new("ACME,Test,0,0.1");
+        parser.register("[SOURce]:VOLTage", set_voltage).unwrap();
+        parser.register("[SOURce]:VOLTage?", get_voltage).unwrap();
+        parser
+            .register("MEASure[:SCALar]:VOLTage?", get_voltage)
+            .unwrap();
+        parser.register("OUTPut[:STATe]", set_output).unwrap();
+        parser.register("OUTPut[:STATe]?", get_output).unwrap();
+        parser
+    }
+
+    fn run(parser: &mut Parser<Device, 8, 4>, dev: &mut Device, line: &str) -> String {
+        let mut out = [0u8; 64];
+        let len = parser.process(dev, line, &mut out);
+        String::from_utf8(out[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn set_and_query() {
+        let mut parser = parser();
+        let mut dev = Device::default();
+        assert_eq!(run(&mut parser, &mut dev, "SOUR:VOLT 2.5"), "");
+        assert_eq!(dev.voltage, 2.5);
+        assert_eq!(run(&mut parser, &mut dev, "meas:volt?"), "2.5");
+        assert_eq!(run(&mut parser, &mut dev, "OUTP ON"), "");
+        assert_eq!(run(&mut parser, &mut dev, "OUTPUT:STATE?"), "1");
+        assert!(parser.errors().is_empty());
+    }
+
+    #[test]
+    fn builtins() {
+        let mut parser = parser();
+        let mut dev = Device::default();
+        assert_eq!(run(&mut parser, &mut dev, "*IDN?"), "ACME,Test,0,0.1");
+        assert_eq!(run(&mut parser, &mut dev, "*OPC?"), "1");
+        assert_eq!(run(&mut parser, &mut dev, "*RST"), "");
+        assert_eq!(run(&mut parser, &mut dev, "SYST:ERR?"), "0,\"No error\"");
+        assert_eq!(run(&mut parser, &mut dev, "*IDN? 1"), "");
+        assert_eq!(run(&mut parser, &mut dev, "SYST:ERR:COUN?"), "1");
+        assert_eq!(
+            run(&mut parser, &mut dev, "SYSTEM:ERROR:NEXT?"),
+            "-108,\"Parameter not allowed\""
+        );
+    }
+
+    #[test]
+    fn builtins_can_be_overridden() {
+        let mut parser = parser();
+        parser.register("*RST", reset).unwrap();
+        let mut dev = Device::default();
+        run(&mut parser, &mut dev, "VOLT 3;*RST");
+        assert_eq!(dev.resets, 1);
+        assert_eq!(dev.voltage, 0.0);
+    }
+
+    #[test]
+    fn error_queue() {
+        let mut parser = parser();
+        let mut dev = Device::default();
+        run(&mut parser, &mut dev, "FOO:BAR");
+        run(&mut parser, &mut dev, "VOLT");
+        run(&mut parser, &mut dev, "VOLT abc");
+        run(&mut parser, &mut dev, "VOLT 11");
+        run(&mut parser, &mut dev, "VOLT 1,2");
+        assert_eq!(parser.errors().len(), 4);
+        assert_eq!(
+            run(&mut parser, &mut dev, "SYST:ERR?"),
+            "-113,\"Undefined header\""
+        );
+        assert_eq!(
+            run(&mut parser, &mut dev, "SYST:ERR?"),
+            "-109,\"Missing parameter\""
+        );
+        assert_eq!(
+            run(&mut parser, &mut dev, "SYST:ERR?"),
+            "-104,\"Data type error\""
+        );
+        assert_eq!(
+            run(&mut parser, &mut dev, "SYST:ERR?"),
+            "-350,\"Queue overflow\""
+        );
+        assert_eq!(run(&mut parser, &mut dev, "SYST:ERR?"), "0,\"No error\"");
+
+        run(&mut parser, &mut dev, "FOO");
+        run(&mut parser, &mut dev, "*CLS");
+        assert!(parser.errors().is_empty());
+    }
+
+    #[test]
+    fn multiple_commands_per_line() {
+        let mut parser = parser();
+        let mut dev = Device::default();
+        assert_eq!(
+            run(
+                &mut parser,
+                &mut dev,
+                "VOLT 1.5;OUTP 1;:MEAS:VOLT?;:OUTP?;*IDN?"
+            ),
+            "1.5;1;ACME,Test,0,0.1"
+        );
+        assert!(parser.errors().is_empty());
+    }
+
+    #[test]
+    fn headers_are_relative_to_previous_path() {
+        let mut parser = parser();
+        let mut dev = Device::default();
+        // `STAT?` is relative to `OUTP:`, `VOLT` is not found under `OUTP:`.
+        assert_eq!(run(&mut parser, &mut dev, "OUTP:STAT 1;STAT?"), "1");
+        assert_eq!(run(&mut parser, &mut dev, "OUTP:STAT 1;VOLT?"), "");
+        assert_eq!(parser.errors().len(), 1);
+        // Common commands do not change the path.
+        assert_eq!(run(&mut parser, &mut dev, "SOUR:VOLT 2;*OPC?;VOLT?"), "1;2");
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let mut parser = parser();
+        let mut dev = Device::default();
+        run(&mut parser, &mut dev, "MEAS::VOLT?");
+        run(&mut parser, &mut dev, "A:B:C:D:E:F:G:H:I");
+        assert_eq!(parser.errors().len(), 2);
+        assert_eq!(
+            run(&mut parser, &mut dev, "SYST:ERR?"),
+            "-102,\"Syntax error\""
+        );
+    }
+
+    #[test]
+    fn output_overflow() {
+        let mut parser = parser();
+        let mut dev = Device::default();
+        let mut out = [0u8; 8];
+        assert_eq!(parser.process(&mut dev, "*IDN?", &mut out), 0);
+        assert_eq!(parser.errors().len(), 1);
+        assert_eq!(parser.process(&mut dev, "*OPC?;*IDN?;*OPC?", &mut out), 3);
+        assert_eq!(&out[..3], b"1;1");
+    }
+
+    #[test]
+    fn too_many_commands() {
+        let mut parser: Parser<Device, 1> = Parser::new("");
+        assert_eq!(parser.register("A", set_output), Ok(()));
+        assert_eq!(parser.register("B", set_output), Err(TooManyCommands));
+    }
+}
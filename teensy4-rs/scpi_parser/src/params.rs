@@ -0,0 +1,170 @@
+//! Parameters of a command.
+
+use crate::ScpiError;
+
+/// The comma separated parameters following a command header.
+#[derive(Clone, Copy, Debug)]
+pub struct Params<'a> {
+    raw: &'a str,
+}
+
+impl<'a> Params<'a> {
+    pub(crate) fn new(raw: &'a str) -> Self {
+        Self { raw: raw.trim() }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Param<'a>> {
+        let raw = self.raw;
+        split_unquoted(raw, ',')
+            .filter(move |_| !raw.is_empty())
+            .map(|p| Param(p.trim()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+
+    /// The parameter at `index`, or a `MissingParameter` error.
+    pub fn arg(&self, index: usize) -> Result<Param<'a>, ScpiError> {
+        self.iter().nth(index).ok_or(ScpiError::MissingParameter)
+    }
+
+    /// Fail with `ParameterNotAllowed` if there are more than `max` parameters.
+    pub fn at_most(&self, max: usize) -> Result<(), ScpiError> {
+        match self.len() > max {
+            true => Err(ScpiError::ParameterNotAllowed),
+            false => Ok(()),
+        }
+    }
+}
+
+/// A single parameter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Param<'a>(&'a str);
+
+impl<'a> Param<'a> {
+    /// The raw parameter as sent.
+    pub fn raw(&self) -> &'a str {
+        self.0
+    }
+
+    /// A string parameter, with the surrounding quotes removed if present.
+    pub fn as_str(&self) -> &'a str {
+        ['"', '\'']
+            .iter()
+            .find_map(|&q| self.0.strip_prefix(q).and_then(|s| s.strip_suffix(q)))
+            .unwrap_or(self.0)
+    }
+
+    pub fn to_i32(&self) -> Result<i32, ScpiError> {
+        self.0.parse().map_err(|_| ScpiError::DataTypeError)
+    }
+
+    pub fn to_u32(&self) -> Result<u32, ScpiError> {
+        self.0.parse().map_err(|_| ScpiError::DataTypeError)
+    }
+
+    pub fn to_f32(&self) -> Result<f32, ScpiError> {
+        self.0.parse().map_err(|_| ScpiError::DataTypeError)
+    }
+
+    /// A boolean parameter, `ON`/`OFF` or a number (non-zero is `true`).
+    pub fn to_bool(&self) -> Result<bool, ScpiError> {
+        if self.0.eq_ignore_ascii_case("ON") {
+            Ok(true)
+        } else if self.0.eq_ignore_ascii_case("OFF") {
+            Ok(false)
+        } else {
+            self.to_f32().map(|v| v != 0.0)
+        }
+    }
+}
+
+/// Split `s` at `sep`, ignoring separators within quoted strings.
+pub(crate) fn split_unquoted(s: &str, sep: char) -> impl Iterator<Item = &str> {
+    let mut rest = Some(s);
+    core::iter::from_fn(move || {
+        let s = rest?;
+        let mut quote = None;
+        for (it, c) in s.char_indices() {
+            match (quote, c) {
+                (None, '"' | '\'') => quote = Some(c),
+                (Some(q), c) if c == q => quote = None,
+                (None, c) if c == sep => {
+                    rest = Some(&s[it + c.len_utf8()..]);
+                    return Some(&s[..it]);
+                }
+                _ => {}
+            }
+        }
+        rest = None;
+        Some(s)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_params() {
+        let params = Params::new("  ");
+        assert!(params.is_empty());
+        assert_eq!(params.len(), 0);
+        assert_eq!(params.arg(0), Err(ScpiError::MissingParameter));
+    }
+
+    #[test]
+    fn numeric_params() {
+        let params = Params::new(" 12, -3.5 ,7");
+        assert_eq!(params.len(), 3);
+        assert_eq!(params.arg(0).unwrap().to_i32(), Ok(12));
+        assert_eq!(params.arg(1).unwrap().to_f32(), Ok(-3.5));
+        assert_eq!(
+            params.arg(1).unwrap().to_i32(),
+            Err(ScpiError::DataTypeError)
+        );
+        assert_eq!(params.arg(2).unwrap().to_u32(), Ok(7));
+        assert_eq!(params.at_most(2), Err(ScpiError::ParameterNotAllowed));
+        assert_eq!(params.at_most(3), Ok(()));
+    }
+
+    #[test]
+    fn bool_params() {
+        let params = Params::new("ON,off,1,0,maybe");
+        let values: [Result<bool, ScpiError>; 5] = [
+            Ok(true),
+            Ok(false),
+            Ok(true),
+            Ok(false),
+            Err(ScpiError::DataTypeError),
+        ];
+        for (param, value) in params.iter().zip(values) {
+            assert_eq!(param.to_bool(), value);
+        }
+    }
+
+    #[test]
+    fn quoted_strings() {
+        let params = Params::new("\"a,b\", 'c;d' , e");
+        assert_eq!(params.len(), 3);
+        assert_eq!(params.arg(0).unwrap().as_str(), "a,b");
+        assert_eq!(params.arg(1).unwrap().as_str(), "c;d");
+        assert_eq!(params.arg(1).unwrap().raw(), "'c;d'");
+        assert_eq!(params.arg(2).unwrap().as_str(), "e");
+    }
+
+    #[test]
+    fn split_keeps_empty_parts() {
+        let mut parts = split_unquoted("a;;b;", ';');
+        assert_eq!(parts.next(), Some("a"));
+        assert_eq!(parts.next(), Some(""));
+        assert_eq!(parts.next(), Some("b"));
+        assert_eq!(parts.next(), Some(""));
+        assert_eq!(parts.next(), None);
+    }
+}